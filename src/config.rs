@@ -1,20 +1,36 @@
-use crate::data::stats::Frame;
+use crate::data::spells::bans_every_spell;
+use crate::data::stats::{AttackBuff, CombatModel, DefenceBuff, Frame};
 use crate::data::wizard::{WizardCharacter, WizardColor};
 use crate::error::ChaosError;
-use crate::gfx::buffer::Buffer;
+use crate::gfx::buffer::{is_renderable, Buffer};
 use directories::BaseDirs;
 use serde::{Deserialize, Serialize};
-use std::fs::{create_dir_all, read_to_string, File};
+use std::fmt;
+use std::fs::{create_dir_all, read_to_string, rename, File};
 use std::io::Write;
+use std::net::{IpAddr, UdpSocket};
 use std::path::Path;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Player {
     pub name: String,
     pub character: WizardCharacter,
     pub color: WizardColor,
 }
 
+pub const MAX_NAME_LEN: usize = 12;
+
+impl Player {
+    pub fn sanitize(&mut self) {
+        self.name = self
+            .name
+            .chars()
+            .map(|ch| if is_renderable(ch) { ch } else { '?' })
+            .take(MAX_NAME_LEN)
+            .collect();
+    }
+}
+
 impl From<&Player> for Buffer {
     fn from(player: &Player) -> Self {
         player.character.as_buffer(player.color)
@@ -31,12 +47,75 @@ impl From<&Player> for Frame {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct NetAddress {
     pub host: String,
     pub port: usize,
 }
 
+impl NetAddress {
+    pub fn is_valid_host(host: &str) -> bool {
+        if host.parse::<IpAddr>().is_ok() {
+            return true;
+        }
+        host.len() <= 253
+            && host.split('.').all(|label| {
+                !label.is_empty()
+                    && label.len() <= 63
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-')
+            })
+    }
+
+    pub fn parse_port(port: &str) -> Option<usize> {
+        port.parse().ok().filter(|port| (1..=65535).contains(port))
+    }
+
+    pub fn all_interfaces(port: usize) -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port,
+        }
+    }
+
+    pub fn bind_address(&self, all_interfaces: bool) -> Self {
+        if all_interfaces {
+            Self::all_interfaces(self.port)
+        } else {
+            self.clone()
+        }
+    }
+
+    pub fn lan_ip() -> Option<IpAddr> {
+        let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+        socket.connect("8.8.8.8:80").ok()?;
+        let ip = socket.local_addr().ok()?.ip();
+        if ip.is_unspecified() {
+            None
+        } else {
+            Some(ip)
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        match self.host.parse::<IpAddr>() {
+            Ok(ip) => ip.is_loopback() || ip.is_unspecified() || Self::lan_ip() == Some(ip),
+            Err(_) => self.host.eq_ignore_ascii_case("localhost"),
+        }
+    }
+}
+
+impl fmt::Display for NetAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
 impl Default for NetAddress {
     fn default() -> Self {
         Self {
@@ -46,26 +125,188 @@ impl Default for NetAddress {
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    pub high_visibility_cursor: bool,
+    pub volume: u8,
+    pub muted: bool,
+    pub owner_markers: bool,
+    pub spell_percentages: bool,
+    pub sprite_facing: bool,
+    pub alignment_trend: bool,
+    pub animation_speed: u8,
+    pub chance_thresholds: bool,
+    pub confirm_below_chance: u8,
+    pub auto_pass: bool,
+    pub timers: bool,
+    pub single_column_spells: bool,
+    pub spell_icons: bool,
+    pub auto_target: bool,
+    pub death_flash: bool,
+    pub suggest_spell: bool,
+    pub presence_bars: bool,
+    pub quit_keys: Vec<String>,
+    #[serde(skip)]
+    pub debug_line_of_sight: bool,
+    #[serde(skip)]
+    pub auto_ready: bool,
+}
+
+impl Options {
+    pub fn effective_volume(&self) -> u8 {
+        if self.muted {
+            0
+        } else {
+            self.volume.min(100)
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            high_visibility_cursor: false,
+            volume: 100,
+            muted: false,
+            owner_markers: false,
+            spell_percentages: false,
+            sprite_facing: false,
+            alignment_trend: false,
+            animation_speed: 100,
+            chance_thresholds: false,
+            confirm_below_chance: 0,
+            auto_pass: false,
+            timers: true,
+            single_column_spells: false,
+            spell_icons: false,
+            auto_target: false,
+            death_flash: false,
+            suggest_spell: false,
+            presence_bars: false,
+            quit_keys: Vec::new(),
+            debug_line_of_sight: false,
+            auto_ready: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostOptions {
+    pub starting_level: u8,
+    pub stalemate_rounds: u8,
+    pub private_buff_failures: bool,
+    pub sync_animations: bool,
+    pub banned_spells: Vec<String>,
+    pub creation_limit: u8,
+    pub keepalive_timeout: u8,
+    pub shuffle_turn_order: bool,
+    pub magic_wood_chance: u8,
+    #[serde(alias = "magic_wood_spell_cap")]
+    pub max_spells: u8,
+    pub all_interfaces: bool,
+    pub combat_model: CombatModel,
+    pub campaign: bool,
+    pub seed: Option<u64>,
+}
+
+impl HostOptions {
+    pub fn sanitize(&mut self) {
+        if bans_every_spell(&self.banned_spells) {
+            eprintln!("banned_spells would leave no spells to play with, ignoring it");
+            self.banned_spells.clear();
+        }
+    }
+}
+
+impl Default for HostOptions {
+    fn default() -> Self {
+        Self {
+            starting_level: 0,
+            stalemate_rounds: 0,
+            private_buff_failures: false,
+            sync_animations: false,
+            banned_spells: Vec::new(),
+            creation_limit: 0,
+            keepalive_timeout: 20,
+            shuffle_turn_order: false,
+            magic_wood_chance: 20,
+            max_spells: 20,
+            all_interfaces: false,
+            combat_model: CombatModel::Classic,
+            campaign: false,
+            seed: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CampaignWizard {
+    pub player: Player,
+    pub level: u8,
+    pub spells: Vec<String>,
+    pub attack_buff: Option<AttackBuff>,
+    pub defence_buff: Option<DefenceBuff>,
+    pub magic_wings: bool,
+    pub magic_bow: bool,
+    pub shadow_form: bool,
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct GameConfig {
     pub player: Option<Player>,
     pub last_host: Option<NetAddress>,
     pub last_join: Option<NetAddress>,
+    #[serde(default)]
+    pub recent_hosts: Vec<NetAddress>,
+    #[serde(default)]
+    pub options: Options,
+    #[serde(default)]
+    pub host_options: HostOptions,
+    #[serde(default)]
+    pub campaign: Vec<CampaignWizard>,
 }
 
 impl GameConfig {
     pub fn load() -> Result<GameConfig, ChaosError> {
-        if let Some(base) = BaseDirs::new() {
-            let path = Path::new(base.config_dir()).join("Chaos").join("Config.toml");
-            if path.exists() {
-                let string = read_to_string(path)?;
-                let config = toml::from_str(&string)?;
-                return Ok(config);
+        match BaseDirs::new() {
+            Some(base) => GameConfig::load_from(&Path::new(base.config_dir()).join("Chaos").join("Config.toml")),
+            None => Ok(GameConfig::default()),
+        }
+    }
+
+    fn load_from(path: &Path) -> Result<GameConfig, ChaosError> {
+        if path.exists() {
+            let string = read_to_string(path)?;
+            match toml::from_str::<GameConfig>(&string) {
+                Ok(mut config) => {
+                    // older versions saved joins here too, only a local address could have been hosted on
+                    if let Some(addr) = config.last_host.take_if(|addr| !addr.is_local()) {
+                        config.add_recent_host(addr);
+                    }
+                    if let Some(player) = config.player.as_mut() {
+                        player.sanitize();
+                    }
+                    config.host_options.sanitize();
+                    return Ok(config);
+                }
+                Err(err) => {
+                    let backup = path.with_extension("toml.bak");
+                    eprintln!("Config file is unreadable ({err}), moved to {}", backup.display());
+                    rename(path, backup)?;
+                }
             }
         }
         Ok(GameConfig::default())
     }
 
+    pub fn add_recent_host(&mut self, addr: NetAddress) {
+        self.recent_hosts.retain(|recent| *recent != addr);
+        self.recent_hosts.insert(0, addr);
+        self.recent_hosts.truncate(5);
+    }
+
     pub fn save(&self) -> Result<(), ChaosError> {
         let string = toml::to_string_pretty(&self)?;
         if let Some(base) = BaseDirs::new() {
@@ -80,3 +321,133 @@ impl GameConfig {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{remove_dir_all, write};
+
+    #[test]
+    fn host_entry_accepts_hostnames_and_ips() {
+        assert!(NetAddress::is_valid_host("a-b.c"));
+        assert!(NetAddress::is_valid_host("my-server.example.com"));
+        assert!(NetAddress::is_valid_host("192.168.0.1"));
+        assert!(NetAddress::is_valid_host("::1"));
+    }
+
+    #[test]
+    fn host_entry_rejects_malformed_hosts() {
+        assert!(!NetAddress::is_valid_host(".."));
+        assert!(!NetAddress::is_valid_host("-a.b"));
+        assert!(!NetAddress::is_valid_host("a-.b"));
+        assert!(!NetAddress::is_valid_host("a b"));
+        assert!(!NetAddress::is_valid_host(&"a".repeat(64)));
+    }
+
+    #[test]
+    fn port_entry_only_accepts_valid_ports() {
+        assert_eq!(NetAddress::parse_port("1"), Some(1));
+        assert_eq!(NetAddress::parse_port("5000"), Some(5000));
+        assert_eq!(NetAddress::parse_port("65535"), Some(65535));
+        assert_eq!(NetAddress::parse_port("0"), None);
+        assert_eq!(NetAddress::parse_port("65536"), None);
+        assert_eq!(NetAddress::parse_port("99999"), None);
+        assert_eq!(NetAddress::parse_port(""), None);
+    }
+
+    #[test]
+    fn hosting_on_all_interfaces_only_changes_the_bind_address() {
+        let addr = NetAddress {
+            host: "192.168.0.10".to_string(),
+            port: 5000,
+        };
+        assert_eq!(addr.bind_address(false), addr);
+        let bind = addr.bind_address(true);
+        assert_eq!(bind.to_string(), "0.0.0.0:5000");
+        assert!(bind.host.parse::<IpAddr>().unwrap().is_unspecified());
+        assert_eq!(addr.to_string(), "192.168.0.10:5000");
+    }
+
+    #[test]
+    fn a_server_bound_to_all_interfaces_accepts_loopback_connections() {
+        let listener = std::net::TcpListener::bind(NetAddress::all_interfaces(0).to_string()).unwrap();
+        let port = listener.local_addr().unwrap().port() as usize;
+        let connect = NetAddress {
+            host: "127.0.0.1".to_string(),
+            port,
+        };
+        assert!(std::net::TcpStream::connect(connect.to_string()).is_ok());
+        assert!(listener.accept().is_ok());
+    }
+
+    #[test]
+    fn names_are_sanitized_to_renderable_characters() {
+        let mut player = Player {
+            name: "ZOË © ÅSA\tWIZARD".to_string(),
+            character: WizardCharacter::Gowin,
+            color: WizardColor::BrightGreen,
+        };
+        player.sanitize();
+        assert_eq!(player.name, "ZO? © ?SA?WI");
+        let mut plain = Player {
+            name: "ZO? © ?SA?WI".to_string(),
+            ..player.clone()
+        };
+        plain.sanitize();
+        assert_eq!(plain, player);
+    }
+
+    #[test]
+    fn recent_hosts_move_to_the_front_without_duplicates() {
+        let host = |port: usize| NetAddress {
+            host: "127.0.0.1".to_string(),
+            port,
+        };
+        let mut config = GameConfig::default();
+        for port in 1..=7 {
+            config.add_recent_host(host(port));
+        }
+        assert_eq!(config.recent_hosts, (3..=7).rev().map(host).collect::<Vec<_>>());
+        config.add_recent_host(host(5));
+        assert_eq!(config.recent_hosts, [5, 7, 6, 4, 3].map(host).to_vec());
+        config.add_recent_host(host(5));
+        assert_eq!(config.recent_hosts, [5, 7, 6, 4, 3].map(host).to_vec());
+    }
+
+    #[test]
+    fn an_unreadable_config_is_moved_aside_and_defaults_are_used() {
+        let dir = std::env::temp_dir().join(format!("chaos-config-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("Config.toml");
+        write(&path, "player = [not toml").unwrap();
+        let config = GameConfig::load_from(&path).unwrap();
+        assert!(config.player.is_none() && config.last_join.is_none() && config.recent_hosts.is_empty());
+        assert!(!path.exists());
+        assert_eq!(read_to_string(dir.join("Config.toml.bak")).unwrap(), "player = [not toml");
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_a_remote_last_host_is_moved_into_recent_hosts() {
+        let dir = std::env::temp_dir().join(format!("chaos-last-host-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("Config.toml");
+        let load = |host: &str| {
+            write(&path, format!("[last_host]\nhost = \"{host}\"\nport = 8080\n")).unwrap();
+            GameConfig::load_from(&path).unwrap()
+        };
+        let addr = |host: &str| NetAddress {
+            host: host.to_string(),
+            port: 8080,
+        };
+        for host in ["localhost", "127.0.0.1", "0.0.0.0", "::1"] {
+            let config = load(host);
+            assert_eq!(config.last_host, Some(addr(host)));
+            assert!(config.recent_hosts.is_empty());
+        }
+        let config = load("chaos.example.com");
+        assert_eq!(config.last_host, None);
+        assert_eq!(config.recent_hosts, vec![addr("chaos.example.com")]);
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -6,8 +6,41 @@ pub mod stats;
 pub mod wizard;
 
 use crate::gfx::buffer::Buffer;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+pub static ANIMATION_SPEED: AtomicU8 = AtomicU8::new(100);
+
+pub fn frame_timing(timing: u8) -> u8 {
+    scaled_timing(timing, ANIMATION_SPEED.load(Ordering::Relaxed))
+}
+
+fn scaled_timing(timing: u8, speed: u8) -> u8 {
+    let speed = speed.clamp(10, 250) as u32;
+    (timing as u32 * 100 / speed).min(u8::MAX as u32) as u8
+}
 
 pub trait Ticable {
     fn tic(&mut self) -> Option<&Buffer>;
     fn current_tic(&self) -> &Buffer;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animation_speed_scales_frame_timing() {
+        assert_eq!(scaled_timing(40, 100), 40);
+        assert_eq!(scaled_timing(40, 200), 20);
+        assert_eq!(scaled_timing(40, 50), 80);
+        assert_eq!(scaled_timing(40, 150), 26);
+    }
+
+    #[test]
+    fn out_of_range_animation_speeds_are_clamped() {
+        assert_eq!(scaled_timing(20, 0), 200);
+        assert_eq!(scaled_timing(100, 1), u8::MAX);
+        assert_eq!(scaled_timing(250, 255), 100);
+        assert_eq!(scaled_timing(0, 0), 0);
+    }
+}
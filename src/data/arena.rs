@@ -1,6 +1,6 @@
 use crate::data::creation::GameCreation;
 use crate::data::stats::CreationStats;
-use crate::data::wizard::GameWizard;
+use crate::data::wizard::{GameWizard, WizardColor};
 use crate::data::Ticable;
 use crate::gfx::buffer::{Buffer, MouseCursor};
 use crate::gfx::color::Color;
@@ -9,7 +9,7 @@ use std::{error, fmt};
 
 use super::stats::Frame;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Spawn {
     Blob(GameCreation),
     Fire(GameCreation),
@@ -31,7 +31,7 @@ impl Ticable for Spawn {
     }
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct Tile {
     pub spawn: Option<Spawn>,
     pub corpse: Option<GameCreation>,
@@ -64,12 +64,23 @@ impl fmt::Display for ArenaError {
 
 impl error::Error for ArenaError {}
 
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttackVerdict {
+    Allowed,
+    Empty,
+    Friendly,
+    NotAttackable,
+    Undead,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Arena {
     pub alignment: i8,
     pub tiles: Vec<Tile>,
     pub width: u8,
     pub height: u8,
+    #[serde(skip)]
+    pub show_facing: bool,
 }
 
 impl Arena {
@@ -81,6 +92,7 @@ impl Arena {
             tiles: vec![Tile::default(); width as usize * height as usize],
             width,
             height,
+            show_facing: false,
         }
     }
 
@@ -130,6 +142,10 @@ impl Arena {
         })
     }
 
+    pub fn tiles_in_spell_range(&self, x: u8, y: u8, range: u8) -> Vec<(u8, u8)> {
+        self.each_tile_in_spell_range(x, y, range).map(|(x, y, _)| (x, y)).collect()
+    }
+
     fn each_tile_in_combat_range(&self, x: u8, y: u8, range: u8) -> impl Iterator<Item = (u8, u8, &Tile)> + '_ {
         self.each_tile().filter(move |(tile_x, tile_y, _)| {
             let distance = (*tile_x as isize - x as isize).pow(2) + (*tile_y as isize - y as isize).pow(2);
@@ -152,20 +168,27 @@ impl Arena {
     }
 
     fn all_attackable_opposition(&self, x: u8, y: u8, range: u8, id: u32) -> impl Iterator<Item = (u8, u8, &Tile)> {
-        self.each_tile_in_spell_range(x, y, range).filter(move |(_, _, tile)| {
-            match tile.spawn {
-                Some(Spawn::Blob(ref blob)) => return blob.id != id,
-                Some(Spawn::Fire(_)) => return false,
-                _ => {}
-            }
-            if let Some(ref wizard) = tile.wizard {
-                return wizard.id != id;
-            }
-            if let Some(ref creation) = tile.creation {
-                return creation.id != id && creation.stats.attackable;
-            }
-            false
-        })
+        self.each_tile_in_spell_range(x, y, range)
+            .filter(move |(_, _, tile)| Self::is_attackable_opposition(tile, id))
+    }
+
+    fn is_attackable_opposition(tile: &Tile, id: u32) -> bool {
+        match tile.spawn {
+            Some(Spawn::Blob(ref blob)) => return blob.id != id,
+            Some(Spawn::Fire(_)) => return false,
+            _ => {}
+        }
+        if let Some(ref wizard) = tile.wizard {
+            return wizard.id != id;
+        }
+        if let Some(ref creation) = tile.creation {
+            return creation.id != id && creation.stats.attackable;
+        }
+        false
+    }
+
+    pub fn has_attackable_opposition(&self, x: u8, y: u8, id: u32) -> bool {
+        Self::is_attackable_opposition(self.get(x, y), id)
     }
 
     pub fn all_subvertable_opposition_tiles(&self, x: u8, y: u8, range: u8, id: u32) -> Vec<(u8, u8)> {
@@ -305,6 +328,7 @@ impl Arena {
             if let Some(wizard) = tile.wizard.as_mut() {
                 if wizard.id == id {
                     wizard.moves_left = if blob { 0 } else { wizard.stats.get_movement() };
+                    wizard.ranged_used = false;
                 }
             }
             if let Some(creation) = tile.creation.as_mut() {
@@ -316,6 +340,7 @@ impl Arena {
                     } else {
                         creation.stats.base.movement
                     };
+                    creation.ranged_used = false;
                 }
             }
         }
@@ -346,11 +371,20 @@ impl Arena {
 
     pub fn move_wizard(&mut self, id: u32, x: u8, y: u8) {
         let (sx, sy) = self.find_wizard_pos(id);
+        if x != sx {
+            self.get_mut_wizard(sx, sy).facing_left = x < sx;
+        }
         self.get_mut(x, y).wizard = self.get_mut(sx, sy).wizard.take();
     }
 
     pub fn move_creation(&mut self, sx: u8, sy: u8, dx: u8, dy: u8) {
+        if dx != sx {
+            self.get_mut_creation(sx, sy).facing_left = dx < sx;
+        }
         if self.get(sx, sy).wizard.is_some() {
+            if dx != sx {
+                self.get_mut_wizard(sx, sy).facing_left = dx < sx;
+            }
             self.get_mut(dx, dy).wizard = self.get_mut(sx, sy).wizard.take();
         }
         self.get_mut(dx, dy).creation = self.get_mut(sx, sy).creation.take();
@@ -368,73 +402,83 @@ impl Arena {
             .collect()
     }
 
-    fn allow_movement_with_attack(&self, x: u8, y: u8, tile: &Tile, id: u32) -> Option<(u8, u8)> {
-        if let Some(ref spawn) = tile.spawn {
-            match spawn {
-                Spawn::Blob(ref creation) if creation.id != id => Some((x, y)),
-                _ => None,
-            }
-        } else if let Some(ref creation) = tile.creation {
+    fn occupant_verdict(&self, tile: &Tile, id: u32) -> AttackVerdict {
+        if let Some(ref creation) = tile.creation {
             if creation.id != id && creation.stats.attackable
                 || (creation.stats.magic_wood && tile.wizard.as_ref().is_some_and(|wizard| wizard.id != id))
             {
-                Some((x, y))
+                AttackVerdict::Allowed
+            } else if creation.id == id {
+                AttackVerdict::Friendly
             } else {
-                None
+                AttackVerdict::NotAttackable
             }
         } else if let Some(ref wizard) = tile.wizard {
             if wizard.id != id {
-                Some((x, y))
+                AttackVerdict::Allowed
             } else {
-                None
+                AttackVerdict::Friendly
             }
         } else {
-            Some((x, y))
+            AttackVerdict::Empty
         }
     }
 
-    fn allow_wizard_movement_with_attack(&self, x: u8, y: u8, tile: &Tile, id: u32) -> Option<(u8, u8)> {
-        if let Some(ref spawn) = tile.spawn {
-            match spawn {
-                Spawn::Blob(ref creation) if creation.id != id => Some((x, y)),
-                _ => None,
+    fn tile_verdict(&self, tile: &Tile, id: u32) -> AttackVerdict {
+        match tile.spawn {
+            Some(Spawn::Blob(ref blob)) if blob.id != id => AttackVerdict::Allowed,
+            Some(Spawn::Blob(_)) => AttackVerdict::Friendly,
+            Some(Spawn::Fire(_)) => AttackVerdict::NotAttackable,
+            None => self.occupant_verdict(tile, id),
+        }
+    }
+
+    pub fn can_attack(&self, (sx, sy): (u8, u8), (dx, dy): (u8, u8), id: u32, by_wizard: bool) -> AttackVerdict {
+        let tile = self.get(dx, dy);
+        let verdict = self.tile_verdict(tile, id);
+        if verdict != AttackVerdict::Allowed || tile.spawn.is_some() {
+            return verdict;
+        }
+        if let Some(ref creation) = tile.creation {
+            if creation.stats.undead {
+                let can_harm_undead = if by_wizard {
+                    self.get_wizard(sx, sy).stats.attack_buff.is_some()
+                } else {
+                    self.get_creation(sx, sy).stats.undead
+                };
+                if !can_harm_undead {
+                    return AttackVerdict::Undead;
+                }
             }
-        } else if let Some(ref creation) = tile.creation {
-            if (creation.id != id && creation.stats.attackable)
-                || (creation.id == id && (creation.stats.mount || creation.stats.shelter) || creation.stats.magic_wood)
+        }
+        AttackVerdict::Allowed
+    }
+
+    fn allow_movement_with_attack(&self, x: u8, y: u8, tile: &Tile, id: u32) -> Option<(u8, u8)> {
+        match self.tile_verdict(tile, id) {
+            AttackVerdict::Allowed | AttackVerdict::Empty => Some((x, y)),
+            _ => None,
+        }
+    }
+
+    fn allow_wizard_movement_with_attack(&self, x: u8, y: u8, tile: &Tile, id: u32) -> Option<(u8, u8)> {
+        match self.tile_verdict(tile, id) {
+            AttackVerdict::Allowed | AttackVerdict::Empty => Some((x, y)),
+            _ if tile.spawn.is_none()
+                && tile.creation.as_ref().is_some_and(|creation| {
+                    creation.id == id && (creation.stats.mount || creation.stats.shelter) || creation.stats.magic_wood
+                }) =>
             {
                 Some((x, y))
-            } else {
-                None
-            }
-        } else if let Some(ref wizard) = tile.wizard {
-            if wizard.id != id {
-                Some((x, y))
-            } else {
-                None
             }
-        } else {
-            Some((x, y))
+            _ => None,
         }
     }
 
     fn allow_attack(&self, x: u8, y: u8, tile: &Tile, id: u32) -> Option<(u8, u8)> {
-        if let Some(ref creation) = tile.creation {
-            if creation.id != id && creation.stats.attackable
-                || (creation.stats.magic_wood && tile.wizard.as_ref().is_some_and(|wizard| wizard.id != id))
-            {
-                Some((x, y))
-            } else {
-                None
-            }
-        } else if let Some(ref wizard) = tile.wizard {
-            if wizard.id != id {
-                Some((x, y))
-            } else {
-                None
-            }
-        } else {
-            None
+        match self.occupant_verdict(tile, id) {
+            AttackVerdict::Allowed => Some((x, y)),
+            _ => None,
         }
     }
 
@@ -466,22 +510,8 @@ impl Arena {
         self.each_tile_in_spell_range(x, y, 3).filter_map(move |(x, y, tile)| {
             if tile.spawn.is_some() {
                 None
-            } else if let Some(ref creation) = tile.creation {
-                if creation.id != id && creation.stats.attackable
-                    || (creation.stats.magic_wood && tile.wizard.as_ref().is_some_and(|wizard| wizard.id != id))
-                {
-                    Some((x, y))
-                } else {
-                    None
-                }
-            } else if let Some(ref wizard) = tile.wizard {
-                if wizard.id != id {
-                    Some((x, y))
-                } else {
-                    None
-                }
             } else {
-                None
+                self.allow_attack(x, y, tile, id)
             }
         })
     }
@@ -497,7 +527,7 @@ impl Arena {
     pub fn kill_creation(&mut self, x: u8, y: u8, corpse: bool) {
         let tile = self.get_mut(x, y);
         let creation = tile.creation.take();
-        if corpse {
+        if corpse && creation.is_some() {
             tile.corpse = creation
         }
     }
@@ -615,7 +645,7 @@ impl Arena {
         buf
     }
 
-    pub fn line_of_sight(&mut self, sx: u8, sy: u8, dx: u8, dy: u8) -> bool {
+    fn line_of_sight_buffer(&self, sx: u8, sy: u8, dx: u8, dy: u8) -> Buffer {
         let mut arena = self.clone();
         for (x, y, tile) in arena.each_tile_mut() {
             tile.corpse = None;
@@ -629,7 +659,11 @@ impl Arena {
                 }
             }
         }
-        let buf = Buffer::from(&arena);
+        Buffer::from(&arena)
+    }
+
+    pub fn line_of_sight(&mut self, sx: u8, sy: u8, dx: u8, dy: u8) -> bool {
+        let buf = self.line_of_sight_buffer(sx, sy, dx, dy);
         let coords = Self::line_coords(sx, sy, dx, dy);
         for (x, y) in coords.into_iter().step_by(4) {
             let color = buf.get_pixel(x, y).expect("pixel");
@@ -640,6 +674,89 @@ impl Arena {
         true
     }
 
+    pub fn line_of_sight_samples(&self, sx: u8, sy: u8, dx: u8, dy: u8) -> Vec<(usize, usize, bool)> {
+        let buf = self.line_of_sight_buffer(sx, sy, dx, dy);
+        Self::line_coords(sx, sy, dx, dy)
+            .into_iter()
+            .step_by(4)
+            .map(|(x, y)| (x, y, buf.get_pixel(x, y).expect("pixel") == Color::Black.into()))
+            .collect()
+    }
+
+    pub fn creation_count(&self, id: u32) -> usize {
+        self.tiles
+            .iter()
+            .filter(|tile| tile.creation.as_ref().is_some_and(|creation| creation.id == id))
+            .count()
+    }
+
+    pub fn creation_owners(&self) -> Vec<(u8, u8, WizardColor)> {
+        self.each_tile()
+            .filter_map(|(x, y, tile)| {
+                let id = tile.creation.as_ref()?.id;
+                let owner = self
+                    .tiles
+                    .iter()
+                    .filter_map(|tile| tile.wizard.as_ref())
+                    .find(|wizard| wizard.id == id)?;
+                Some((x, y, owner.color))
+            })
+            .collect()
+    }
+
+    pub fn checksum(&self) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes {
+                hash = (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        for (x, y, tile) in self.each_tile() {
+            write(&[x, y]);
+            match tile.spawn {
+                Some(Spawn::Blob(ref blob)) => {
+                    write(&[1]);
+                    write(&blob.id.to_le_bytes());
+                }
+                Some(Spawn::Fire(ref fire)) => {
+                    write(&[2]);
+                    write(&fire.id.to_le_bytes());
+                }
+                None => write(&[0]),
+            }
+            match tile.creation {
+                Some(ref creation) => {
+                    write(&[1]);
+                    write(&creation.id.to_le_bytes());
+                    write(creation.stats.base.name.as_bytes());
+                }
+                None => write(&[0]),
+            }
+            match tile.wizard {
+                Some(ref wizard) => {
+                    write(&[1]);
+                    write(&wizard.id.to_le_bytes());
+                }
+                None => write(&[0]),
+            }
+        }
+        hash
+    }
+
+    pub fn occupancy(&self) -> Vec<(Option<u32>, Option<u32>, bool, bool)> {
+        self.tiles
+            .iter()
+            .map(|tile| {
+                (
+                    tile.wizard.as_ref().map(|wizard| wizard.id),
+                    tile.creation.as_ref().map(|creation| creation.id),
+                    tile.spawn.is_some(),
+                    tile.corpse.is_some(),
+                )
+            })
+            .collect()
+    }
+
     pub fn visible_corpse_tiles(&self, x: u8, y: u8, range: u8) -> Vec<(u8, u8)> {
         self.each_tile_in_spell_range(x, y, range)
             .filter_map(|(x, y, tile)| {
@@ -737,21 +854,32 @@ impl Arena {
     }
 }
 
+fn draw_piece(arena_buf: &mut Buffer, buf: &Buffer, x: u8, y: u8, flip: bool) {
+    if flip {
+        arena_buf.draw_buffer(&buf.flip_horizontal(), x as usize * 2, y as usize * 2);
+    } else {
+        arena_buf.draw_buffer(buf, x as usize * 2, y as usize * 2);
+    }
+}
+
 impl From<&mut Arena> for Buffer {
     fn from(arena: &mut Arena) -> Self {
         let mut arena_buf = Buffer::new(30, 20);
+        let show_facing = arena.show_facing;
         for (x, y, tile) in arena.each_tile_mut() {
             if let Some(ref mut spawn) = tile.spawn {
                 if let Some(buf) = spawn.tic() {
                     arena_buf.draw_buffer(buf, x as usize * 2, y as usize * 2);
                 }
             } else if let Some(ref mut creation) = tile.creation {
+                let flip = show_facing && creation.facing_left;
                 if let Some(buf) = creation.tic() {
-                    arena_buf.draw_buffer(buf, x as usize * 2, y as usize * 2);
+                    draw_piece(&mut arena_buf, buf, x, y, flip);
                 }
             } else if let Some(ref mut wizard) = tile.wizard {
+                let flip = show_facing && wizard.facing_left;
                 if let Some(buf) = wizard.tic() {
-                    arena_buf.draw_buffer(buf, x as usize * 2, y as usize * 2);
+                    draw_piece(&mut arena_buf, buf, x, y, flip);
                 }
             } else if let Some(ref creation) = tile.corpse {
                 if let Some(ref buf) = creation.corpse_buf {
@@ -771,11 +899,11 @@ impl From<&Arena> for Buffer {
                 let buf = spawn.current_tic();
                 arena_buf.draw_buffer(buf, x as usize * 2, y as usize * 2);
             } else if let Some(ref creation) = tile.creation {
-                let buf = creation.current_tic();
-                arena_buf.draw_buffer(buf, x as usize * 2, y as usize * 2);
+                let flip = arena.show_facing && creation.facing_left;
+                draw_piece(&mut arena_buf, creation.current_tic(), x, y, flip);
             } else if let Some(ref wizard) = tile.wizard {
-                let buf = wizard.current_tic();
-                arena_buf.draw_buffer(buf, x as usize * 2, y as usize * 2);
+                let flip = arena.show_facing && wizard.facing_left;
+                draw_piece(&mut arena_buf, wizard.current_tic(), x, y, flip);
             } else if let Some(ref creation) = tile.corpse {
                 if let Some(ref buf) = creation.corpse_buf {
                     arena_buf.draw_buffer(buf, x as usize * 2, y as usize * 2);
@@ -785,3 +913,232 @@ impl From<&Arena> for Buffer {
         arena_buf
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Player;
+    use crate::data::spells::{find_spells, SpellKind};
+    use crate::data::stats::AttackBuff;
+    use crate::data::wizard::{LobbyWizards, ServerWizards};
+    use rand::thread_rng;
+
+    fn wizard(id: u32) -> GameWizard {
+        let mut lobby = LobbyWizards::new();
+        lobby.join(
+            id,
+            Player {
+                name: format!("WIZARD {id}"),
+                character: (id as isize % 8).try_into().expect("character"),
+                color: (id as isize % 8).try_into().expect("color"),
+            },
+        );
+        let wizards = ServerWizards::new(lobby, &mut thread_rng());
+        GameWizard::from(wizards.get(id).expect("wizard"))
+    }
+
+    fn creation(id: u32, name: &str) -> GameCreation {
+        match find_spells(&[name.to_string()], &[]).remove(0).kind {
+            SpellKind::Creation(stats) | SpellKind::MagicWood(stats) => GameCreation::new(id, stats),
+            _ => panic!("{name} is not a creation"),
+        }
+    }
+
+    fn inconsistent_tiles(arena: &Arena) -> Vec<(u8, u8)> {
+        arena
+            .each_tile()
+            .filter(|(_, _, tile)| match (&tile.creation, &tile.wizard) {
+                (Some(creation), Some(wizard)) => {
+                    let stats = &creation.stats;
+                    !(stats.mount || stats.shelter || stats.magic_wood) || !(creation.id == wizard.id || stats.magic_wood)
+                }
+                _ => false,
+            })
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    #[test]
+    fn every_tile_a_wizard_may_walk_onto_stays_consistent() {
+        let mut arena = Arena::new();
+        arena.get_mut(3, 3).wizard = Some(wizard(0));
+        arena.get_mut(4, 3).creation = Some(creation(0, "HORSE"));
+        arena.get_mut(2, 3).creation = Some(creation(0, "GOBLIN"));
+        arena.get_mut(3, 4).creation = Some(creation(1, "HORSE"));
+        arena.get_mut(3, 2).creation = Some(creation(1, "MAGIC WOOD"));
+        let tiles = arena.wizard_movement_tiles(3, 3, 0);
+        assert!(tiles.contains(&(4, 3)) && tiles.contains(&(3, 2)));
+        assert!(!tiles.contains(&(2, 3)));
+        for (x, y) in tiles {
+            if arena.occupant_verdict(arena.get(x, y), 0) == AttackVerdict::Allowed {
+                continue;
+            }
+            let mut arena = arena.clone();
+            arena.move_wizard(0, x, y);
+            assert!(inconsistent_tiles(&arena).is_empty(), "moved to {x},{y}");
+        }
+    }
+
+    #[test]
+    fn mounting_and_riding_keep_the_wizard_on_its_own_mount() {
+        let mut arena = Arena::new();
+        arena.get_mut(3, 3).wizard = Some(wizard(0));
+        arena.get_mut(4, 3).creation = Some(creation(0, "HORSE"));
+        arena.get_mut(8, 8).creation = Some(creation(1, "MAGIC WOOD"));
+        arena.move_wizard(0, 4, 3);
+        assert!(arena.get(3, 3).wizard.is_none());
+        assert!(!arena.get_wizard(4, 3).facing_left);
+        arena.move_creation(4, 3, 3, 4);
+        assert!(arena.get(4, 3).wizard.is_none() && arena.get(4, 3).creation.is_none());
+        assert_eq!(arena.get_wizard(3, 4).id, 0);
+        assert!(arena.get_wizard(3, 4).facing_left && arena.get_creation(3, 4).facing_left);
+        arena.move_wizard(0, 8, 8);
+        assert!(arena.get(3, 4).wizard.is_none());
+        assert!(inconsistent_tiles(&arena).is_empty());
+    }
+
+    #[test]
+    fn a_mounted_wizards_death_takes_its_mount_with_it() {
+        let mut arena = Arena::new();
+        arena.get_mut(3, 3).creation = Some(creation(0, "HORSE"));
+        arena.get_mut(3, 3).wizard = Some(wizard(0));
+        arena.get_mut(8, 8).creation = Some(creation(0, "MAGIC WOOD"));
+        arena.get_mut(8, 8).wizard = Some(wizard(1));
+        arena.get_mut(5, 5).creation = Some(creation(1, "GOBLIN"));
+        arena.kill_wizard_and_creations(0);
+        assert!(arena.get(3, 3).wizard.is_none() && arena.get(3, 3).creation.is_none());
+        assert!(arena.get(8, 8).creation.is_none());
+        assert_eq!(arena.get_wizard(8, 8).id, 1);
+        assert_eq!(arena.get_creation(5, 5).id, 1);
+        assert!(inconsistent_tiles(&arena).is_empty());
+    }
+
+    #[test]
+    fn raising_the_dead_leaves_one_undead_creation_on_the_tile() {
+        let mut arena = Arena::new();
+        arena.get_mut(6, 2).creation = Some(creation(1, "GOBLIN"));
+        arena.kill_creation(6, 2, true);
+        assert!(arena.get(6, 2).creation.is_none());
+        arena.raise_dead(6, 2, 0);
+        let tile = arena.get(6, 2);
+        assert!(tile.corpse.is_none());
+        assert!(tile
+            .creation
+            .as_ref()
+            .is_some_and(|creation| creation.id == 0 && creation.stats.undead));
+        assert!(inconsistent_tiles(&arena).is_empty());
+    }
+
+    fn assert_contiguous(coords: &[(usize, usize)]) {
+        for pair in coords.windows(2) {
+            let ((ax, ay), (bx, by)) = (pair[0], pair[1]);
+            assert!(ax.abs_diff(bx) <= 1 && ay.abs_diff(by) <= 1, "gap between {:?}", pair);
+            assert_ne!(pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn line_coords_runs_straight_between_tile_centres() {
+        assert_eq!(Arena::line_coords(1, 2, 4, 2), (24..=72).map(|x| (x, 40)).collect::<Vec<_>>());
+        assert_eq!(
+            Arena::line_coords(3, 5, 3, 1),
+            (24..=88).rev().map(|y| (56, y)).collect::<Vec<_>>()
+        );
+        assert_eq!(Arena::line_coords(0, 0, 2, 2), (8..=40).map(|i| (i, i)).collect::<Vec<_>>());
+        assert_eq!(Arena::line_coords(6, 6, 6, 6), vec![(104, 104)]);
+    }
+
+    #[test]
+    fn line_coords_is_contiguous_and_includes_both_ends_in_either_direction() {
+        for (sx, sy) in [(0, 0), (7, 4), (14, 9)] {
+            for dx in 0..15 {
+                for dy in 0..10 {
+                    let coords = Arena::line_coords(sx, sy, dx, dy);
+                    assert_contiguous(&coords);
+                    let centre = |x: u8, y: u8| (x as usize * 16 + 8, y as usize * 16 + 8);
+                    assert_eq!(coords.first(), Some(&centre(sx, sy)));
+                    assert_eq!(coords.last(), Some(&centre(dx, dy)));
+                    assert_eq!(coords.len(), Arena::line_coords(dx, dy, sx, sy).len());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn only_undead_or_magic_weapons_can_attack_undead() {
+        let mut arena = Arena::new();
+        arena.get_mut(0, 0).wizard = Some(wizard(0));
+        arena.get_mut(2, 0).creation = Some(creation(0, "GOBLIN"));
+        arena.get_mut(4, 0).creation = Some(creation(0, "ZOMBIE"));
+        arena.get_mut(1, 0).creation = Some(creation(1, "VAMPIRE"));
+        assert_eq!(arena.can_attack((0, 0), (1, 0), 0, true), AttackVerdict::Undead);
+        assert_eq!(arena.can_attack((2, 0), (1, 0), 0, false), AttackVerdict::Undead);
+        assert_eq!(arena.can_attack((4, 0), (1, 0), 0, false), AttackVerdict::Allowed);
+        arena.get_mut(0, 0).wizard.as_mut().expect("wizard").stats.attack_buff = Some(AttackBuff::MagicSword);
+        assert_eq!(arena.can_attack((0, 0), (1, 0), 0, true), AttackVerdict::Allowed);
+        assert_eq!(arena.can_attack((0, 0), (4, 0), 0, true), AttackVerdict::Friendly);
+    }
+
+    #[test]
+    fn a_magic_wood_can_only_be_attacked_for_its_rider() {
+        let mut arena = Arena::new();
+        arena.get_mut(0, 0).wizard = Some(wizard(0));
+        arena.get_mut(1, 0).creation = Some(creation(1, "MAGIC WOOD"));
+        assert_eq!(arena.can_attack((0, 0), (1, 0), 0, true), AttackVerdict::NotAttackable);
+        arena.get_mut(1, 0).wizard = Some(wizard(1));
+        assert_eq!(arena.can_attack((0, 0), (1, 0), 0, true), AttackVerdict::Allowed);
+        assert_eq!(arena.can_attack((0, 0), (1, 0), 1, true), AttackVerdict::Friendly);
+        arena.get_mut(1, 0).wizard = Some(wizard(2));
+        assert_eq!(arena.can_attack((0, 0), (1, 0), 2, true), AttackVerdict::NotAttackable);
+    }
+
+    #[test]
+    fn illusions_are_attacked_like_real_creations() {
+        let mut arena = Arena::new();
+        arena.get_mut(0, 0).wizard = Some(wizard(0));
+        let mut illusion = creation(1, "GOLDEN DRAGON");
+        illusion.illusion = true;
+        arena.get_mut(1, 0).creation = Some(illusion);
+        arena.get_mut(0, 1).creation = Some(creation(1, "GOLDEN DRAGON"));
+        assert_eq!(arena.can_attack((0, 0), (1, 0), 0, true), AttackVerdict::Allowed);
+        assert_eq!(arena.can_attack((0, 0), (0, 1), 0, true), AttackVerdict::Allowed);
+        assert_eq!(arena.can_attack((0, 0), (1, 0), 1, true), AttackVerdict::Friendly);
+        assert_eq!(arena.can_attack((0, 0), (2, 2), 0, true), AttackVerdict::Empty);
+    }
+
+    #[test]
+    fn a_blob_placed_as_fire_fails_the_checksum() {
+        let mut server = Arena::new();
+        server.get_mut(2, 2).wizard = Some(wizard(0));
+        let mut client = server.clone();
+        let blob = match find_spells(&["GOOEY BLOB".to_string()], &[]).remove(0).kind {
+            SpellKind::GooeyBlob(stats) => GameCreation::new(1, stats),
+            _ => panic!("GOOEY BLOB is not a blob"),
+        };
+        server.spawn_blob(5, 5, blob.clone());
+        client.spawn_fire(5, 5, blob.clone());
+        assert_ne!(client.checksum(), server.checksum());
+        client.spawn_blob(5, 5, blob);
+        assert_eq!(client.checksum(), server.checksum());
+    }
+
+    #[test]
+    fn checksum_changes_with_owners_names_and_spawns() {
+        let mut arena = Arena::new();
+        arena.get_mut(2, 2).wizard = Some(wizard(0));
+        arena.get_mut(3, 3).creation = Some(creation(0, "GOBLIN"));
+        let base = arena.checksum();
+        let mut moved = arena.clone();
+        moved.move_creation(3, 3, 4, 3);
+        let mut subverted = arena.clone();
+        subverted.subvert(3, 3, 1);
+        let mut renamed = arena.clone();
+        renamed.get_mut(3, 3).creation = Some(creation(0, "ORC"));
+        let mut burning = arena.clone();
+        burning.spawn_fire(6, 6, creation(0, "GOBLIN"));
+        let checksums = [moved, subverted, renamed, burning].map(|arena| arena.checksum());
+        assert!(checksums.iter().all(|&checksum| checksum != base));
+        arena.get_mut_wizard(2, 2).moves_left = 3;
+        assert_eq!(arena.checksum(), base);
+    }
+}
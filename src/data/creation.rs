@@ -1,11 +1,11 @@
-use super::Ticable;
-use crate::data::stats::{CreationStats, Frame};
+use super::{frame_timing, Ticable};
+use crate::data::stats::{CombatModel, CreationStats, Frame};
 use crate::gfx::buffer::Buffer;
 use crate::gfx::color::Color;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct GameCreation {
     pub id: u32,
     pub moves_left: u8,
@@ -15,6 +15,8 @@ pub struct GameCreation {
     pub buffers: [Buffer; 4],
     pub corpse_buf: Option<Buffer>,
     pub illusion: bool,
+    pub facing_left: bool,
+    pub ranged_used: bool,
 }
 
 impl GameCreation {
@@ -30,6 +32,8 @@ impl GameCreation {
             buffers,
             corpse_buf,
             illusion: false,
+            facing_left: false,
+            ranged_used: false,
         }
     }
 
@@ -37,19 +41,16 @@ impl GameCreation {
         !(self.illusion || self.stats.undead || self.stats.magic_wood || self.stats.shadow_wood)
     }
 
-    pub fn is_engaged(&self, manoeuvre: u8) -> bool {
-        let mut rng = thread_rng();
-        self.stats.base.manoeuvre + rng.gen_range(0..=9) <= manoeuvre + rng.gen_range(0..=9)
+    pub fn is_engaged(&self, manoeuvre: u8, model: CombatModel, rng: &mut impl Rng) -> bool {
+        model.contest(manoeuvre, self.stats.base.manoeuvre, rng)
     }
 
-    pub fn defend_against_attack(&self, combat: u8) -> bool {
-        let mut rng = thread_rng();
-        combat + rng.gen_range(0..=9) >= self.stats.base.defence + rng.gen_range(0..=9)
+    pub fn defend_against_attack(&self, combat: u8, model: CombatModel, rng: &mut impl Rng) -> bool {
+        model.contest(combat, self.stats.base.defence, rng)
     }
 
-    pub fn defend_against_magical_attack(&self, spell_ability: u8) -> bool {
-        let mut rng = thread_rng();
-        spell_ability + rng.gen_range(0..=9) >= self.stats.base.magical_resistance + rng.gen_range(0..=9)
+    pub fn defend_against_magical_attack(&self, spell_ability: u8, model: CombatModel, rng: &mut impl Rng) -> bool {
+        model.contest(spell_ability, self.stats.base.magical_resistance, rng)
     }
 
     pub fn current_bytes(&self) -> [u8; 32] {
@@ -64,15 +65,14 @@ impl GameCreation {
         self.stats.gfx.frames.first().unwrap().fg
     }
 
-    pub fn should_disappear(&self) -> bool {
-        let mut rng = thread_rng();
+    pub fn should_disappear(&self, rng: &mut impl Rng) -> bool {
         rng.gen_range(0..=9) >= 8
     }
 }
 
 impl Ticable for GameCreation {
     fn tic(&mut self) -> Option<&Buffer> {
-        if self.frame_count == self.stats.gfx.timing {
+        if self.frame_count >= frame_timing(self.stats.gfx.timing) {
             self.frame_count = 0;
             self.current_frame += 1;
             if self.current_frame >= 4 {
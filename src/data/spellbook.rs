@@ -2,8 +2,15 @@
 use crate::data::arena::Spawn;
 use crate::data::spells::{Spell, SpellKind};
 use crate::data::stats::{AttackBuff, BaseStats, CreationStats, DefenceBuff, Frame, Gfx, WizardStats};
+use crate::gfx::buffer::is_renderable;
 use crate::gfx::color::Color::*;
+use directories::BaseDirs;
 use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::fs::read_to_string;
+use std::path::Path;
+
+const MAX_SPELL_NAME_LEN: usize = 14;
 
 static KING_COBRA: &[u8; 160] = include_bytes!("../gfx/bin/creations/king_cobra.bin");
 static DIRE_WOLF: &[u8; 160] = include_bytes!("../gfx/bin/creations/dire_wolf.bin");
@@ -46,7 +53,8 @@ static DARK_CITADEL: &[u8; 32] = include_bytes!("../gfx/bin/creations/dark_citad
 static WALL: &[u8; 32] = include_bytes!("../gfx/bin/creations/wall.bin");
 
 lazy_static! {
-    pub static ref SPELLS: Vec<Spell> = vec![
+    pub static ref SPELLS: Vec<Spell> = load_spellbook().unwrap_or_else(|| BUILT_IN_SPELLS.clone());
+    static ref BUILT_IN_SPELLS: Vec<Spell> = vec![
         Spell {
             name: "KING COBRA".to_string(),
             chance: 8,
@@ -2498,3 +2506,169 @@ lazy_static! {
         },
     ];
 }
+
+#[derive(Deserialize)]
+struct SpellbookFile {
+    spells: Vec<toml::Value>,
+}
+
+fn creation_stats(kind: &SpellKind) -> Option<&CreationStats> {
+    match kind {
+        SpellKind::Creation(stats)
+        | SpellKind::MagicFire(stats)
+        | SpellKind::GooeyBlob(stats)
+        | SpellKind::MagicWood(stats)
+        | SpellKind::ShadowWood(stats)
+        | SpellKind::Shelter(stats)
+        | SpellKind::Wall(stats) => Some(stats),
+        _ => None,
+    }
+}
+
+fn built_in_gfx(name: &str) -> Option<toml::Value> {
+    BUILT_IN_SPELLS
+        .iter()
+        .filter_map(|spell| creation_stats(&spell.kind))
+        .find(|stats| stats.base.name == name)
+        .and_then(|stats| toml::Value::try_from(&stats.gfx).ok())
+}
+
+fn resolve_gfx(value: &mut toml::Value) -> Result<(), String> {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                match value {
+                    toml::Value::String(name) if key == "gfx" => {
+                        let gfx = built_in_gfx(name).ok_or_else(|| format!("no built-in gfx named \"{name}\""))?;
+                        *value = gfx;
+                    }
+                    _ => resolve_gfx(value)?,
+                }
+            }
+        }
+        toml::Value::Array(values) => {
+            for value in values {
+                resolve_gfx(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn validate_spell(spell: &Spell) -> Result<(), String> {
+    let names_ok = |name: &str| name.len() <= MAX_SPELL_NAME_LEN && name.chars().all(is_renderable);
+    if !spell.is_valid() {
+        return Err(format!("\"{}\" is not a valid spell", spell.name));
+    }
+    if !names_ok(&spell.name) {
+        return Err(format!("\"{}\" is not a displayable name", spell.name));
+    }
+    match creation_stats(&spell.kind) {
+        Some(stats) if !names_ok(&stats.base.name) => Err(format!("\"{}\" is not a displayable name", stats.base.name)),
+        _ => Ok(()),
+    }
+}
+
+fn parse_spellbook(string: &str) -> Result<Vec<Spell>, String> {
+    let file: SpellbookFile = toml::from_str(string).map_err(|err| err.to_string())?;
+    let mut spells: Vec<Spell> = Vec::new();
+    for mut value in file.spells {
+        resolve_gfx(&mut value)?;
+        let spell: Spell = value.try_into().map_err(|err: toml::de::Error| err.to_string())?;
+        validate_spell(&spell)?;
+        if spells.iter().any(|other| other.name == spell.name) {
+            return Err(format!("\"{}\" is defined more than once", spell.name));
+        }
+        spells.push(spell);
+    }
+    if spells.is_empty() {
+        return Err("no spells defined".to_string());
+    }
+    Ok(spells)
+}
+
+fn load_spellbook() -> Option<Vec<Spell>> {
+    let base = BaseDirs::new()?;
+    let path = Path::new(base.config_dir()).join("Chaos").join("Spellbook.toml");
+    let string = read_to_string(&path).ok()?;
+    match parse_spellbook(&string) {
+        Ok(spells) => Some(spells),
+        Err(err) => {
+            eprintln!("Spellbook file is invalid ({err}), using the built-in spells");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_spells_are_well_formed() {
+        for spell in BUILT_IN_SPELLS.iter() {
+            assert_eq!(validate_spell(spell), Ok(()));
+        }
+    }
+
+    fn entry(name: &str, gfx: Option<&str>) -> toml::Value {
+        fn set_gfx(value: &mut toml::Value, gfx: &str) {
+            match value {
+                toml::Value::Table(table) => {
+                    for (key, value) in table.iter_mut() {
+                        if key == "gfx" {
+                            *value = toml::Value::String(gfx.to_string());
+                        } else {
+                            set_gfx(value, gfx);
+                        }
+                    }
+                }
+                toml::Value::Array(values) => values.iter_mut().for_each(|value| set_gfx(value, gfx)),
+                _ => {}
+            }
+        }
+        let spell = BUILT_IN_SPELLS.iter().find(|spell| spell.name == name).expect("spell");
+        let mut value = toml::Value::try_from(spell).expect("value");
+        if let Some(gfx) = gfx {
+            set_gfx(&mut value, gfx);
+        }
+        value
+    }
+
+    fn spellbook(entries: Vec<toml::Value>) -> String {
+        let mut file = toml::map::Map::new();
+        file.insert("spells".to_string(), toml::Value::Array(entries));
+        toml::to_string(&file).expect("toml")
+    }
+
+    #[test]
+    fn a_spellbook_file_resolves_gfx_by_creation_name() {
+        let file = spellbook(vec![entry("GOBLIN", Some("ORC")), entry("MAGIC BOLT", None)]);
+        let spells = parse_spellbook(&file).expect("spellbook");
+        assert_eq!(spells.len(), 2);
+        let orc = BUILT_IN_SPELLS.iter().find(|spell| spell.name == "ORC").expect("orc");
+        let gfx = |spell: &Spell| creation_stats(&spell.kind).map(|stats| stats.gfx.clone());
+        assert_eq!(gfx(&spells[0]), gfx(orc));
+        assert_eq!(spells[1].name, "MAGIC BOLT");
+    }
+
+    #[test]
+    fn a_spellbook_file_with_an_unknown_gfx_name_is_rejected() {
+        let file = spellbook(vec![entry("GOBLIN", Some("NOT A CREATION"))]);
+        assert_eq!(
+            parse_spellbook(&file).map(|spells| spells.len()),
+            Err("no built-in gfx named \"NOT A CREATION\"".to_string())
+        );
+    }
+
+    #[test]
+    fn a_spellbook_file_with_a_duplicate_name_is_rejected() {
+        let file = spellbook(vec![entry("MAGIC BOLT", None), entry("MAGIC BOLT", None)]);
+        assert_eq!(
+            parse_spellbook(&file).map(|spells| spells.len()),
+            Err("\"MAGIC BOLT\" is defined more than once".to_string())
+        );
+        assert!(parse_spellbook(&spellbook(Vec::new())).is_err());
+    }
+}
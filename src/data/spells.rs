@@ -1,47 +1,120 @@
 use super::{
     spellbook::SPELLS,
-    stats::{AttackBuff, DefenceBuff},
+    stats::{AttackBuff, DefenceBuff, STAT_CAP},
 };
 use crate::data::stats::CreationStats;
 use crate::gfx::buffer::Buffer;
-use crate::gfx::color::Color::*;
-use rand::{seq::SliceRandom, thread_rng, Rng};
+use crate::gfx::color::Color::{self, *};
+use rand::{seq::SliceRandom, Rng};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
 impl Spell {
-    pub fn random() -> Self {
-        let mut rng = thread_rng();
-        SPELLS[1..].choose(&mut rng).expect("spell").clone()
+    pub fn random(banned: &[String], rng: &mut impl Rng) -> Self {
+        let pool = spell_pool(banned);
+        pool.choose(rng).copied().expect("spell").clone()
+    }
+
+    pub fn is_banned(&self, banned: &[String]) -> bool {
+        banned.iter().any(|name| name.eq_ignore_ascii_case(&self.name))
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let targets_self = matches!(
+            self.kind,
+            SpellKind::WizardAttackBuff(_)
+                | SpellKind::WizardDefenceBuff(_)
+                | SpellKind::MagicBow
+                | SpellKind::MagicWings
+                | SpellKind::WorldAlignment
+                | SpellKind::ShadowForm
+        );
+        !self.name.is_empty()
+            && self.chance <= STAT_CAP
+            && (self.range == 0) == targets_self
+            && !matches!(self.kind, SpellKind::Disbelieve)
     }
 
     pub fn is_creation(&self) -> bool {
         matches!(self.kind, SpellKind::Creation(_))
     }
 
+    pub fn is_beneficial(&self) -> bool {
+        matches!(
+            self.kind,
+            SpellKind::Creation(_)
+                | SpellKind::WizardAttackBuff(_)
+                | SpellKind::WizardDefenceBuff(_)
+                | SpellKind::MagicBow
+                | SpellKind::MagicWings
+                | SpellKind::ShadowForm
+        )
+    }
+
+    pub fn matches(&self, filter: &str) -> bool {
+        self.name.contains(filter)
+    }
+
+    pub fn places_creation(&self) -> bool {
+        matches!(
+            self.kind,
+            SpellKind::Creation(_)
+                | SpellKind::MagicWood(_)
+                | SpellKind::ShadowWood(_)
+                | SpellKind::Shelter(_)
+                | SpellKind::Wall(_)
+        )
+    }
+
+    pub fn is_placement(&self) -> bool {
+        matches!(
+            self.kind,
+            SpellKind::Creation(_)
+                | SpellKind::MagicFire(_)
+                | SpellKind::GooeyBlob(_)
+                | SpellKind::ShadowWood(_)
+                | SpellKind::Shelter(_)
+                | SpellKind::Wall(_)
+        )
+    }
+
     fn cast_chance(&self, alignment: i8, spell_ability: u8) -> i8 {
         let mut chance = self.chance as i8;
         if (self.alignment > 0 && alignment > 0) || (self.alignment < 0 && alignment < 0) {
             chance += alignment.abs() / 4;
         }
-        (chance + spell_ability as i8).min(9)
+        (chance + spell_ability as i8).min(STAT_CAP as i8)
     }
 
-    pub fn cast(&self, alignment: i8, spell_ability: u8) -> bool {
+    pub fn cast(&self, alignment: i8, spell_ability: u8, rng: &mut impl Rng) -> bool {
         let chance = self.cast_chance(alignment, spell_ability);
-        let mut rng = thread_rng();
-        rng.gen_range(0..=9) <= chance
+        rng.gen_range(0..=STAT_CAP as i8) <= chance
+    }
+
+    fn percentage(chance: i8) -> u16 {
+        (chance as u16 + 1) * 100 / (STAT_CAP as u16 + 1)
+    }
+
+    pub fn casting_percentage(&self, alignment: i8, spell_ability: u8) -> u16 {
+        Self::percentage(self.cast_chance(alignment, spell_ability))
     }
 
-    pub fn as_info_buffer(&self, alignment: i8, spell_ability: u8) -> Buffer {
+    fn chance_text(chance: i8, show_threshold: bool) -> String {
+        if show_threshold {
+            format!("0-{}", chance)
+        } else {
+            format!("{}%", Self::percentage(chance))
+        }
+    }
+
+    pub fn as_info_buffer(&self, alignment: i8, spell_ability: u8, show_threshold: bool) -> Buffer {
         let mut buf = Buffer::new(32, 24);
         let chance = self.cast_chance(alignment, spell_ability);
         if let SpellKind::Creation(ref stats) = self.kind {
             let stats_buf = Buffer::from(stats);
             buf.draw_buffer(&stats_buf, 0, 0);
             buf.border(0, 0, 32, 24, BrightGreen, Black);
-            let perc = (chance + 1) * 10;
-            let text = format!("CASTING CHANCE={}%", perc);
+            let text = format!("CASTING CHANCE={}", Self::chance_text(chance, show_threshold));
             buf.draw_text(&text, 4, 18, BrightCyan);
         } else {
             buf.border(0, 0, 32, 24, BrightBlue, BrightCyan);
@@ -58,7 +131,7 @@ impl Spell {
                 _ => {}
             }
             buf.draw_text("CASTING CHANCE=", 5, 12, BrightGreen);
-            let text = format!("{}%", (chance + 1) * 10);
+            let text = Self::chance_text(chance, show_threshold);
             buf.draw_text(&text, 20, 12, BrightYellow);
             buf.draw_text("RANGE=", 5, 16, BrightGreen);
             let range = self.range / 2;
@@ -68,28 +141,77 @@ impl Spell {
         buf
     }
 
-    pub fn as_name_buffer(&self, world_alignment: i8, spell_ability: u8) -> Buffer {
-        let mut buf = Buffer::new(self.name.len() + 1, 2);
-        let chance = self.cast_chance(world_alignment, spell_ability);
-        let color = match chance {
+    fn chance_color(chance: i8) -> Color {
+        match chance as u16 * 10 / (STAT_CAP as u16 + 1) {
             0..=1 => BrightMagenta,
             2..=3 => BrightGreen,
             4..=5 => BrightCyan,
             6..=7 => BrightYellow,
-            8..=9 => BrightWhite,
-            _ => unreachable!("Invalid chance value"),
-        };
+            _ => BrightWhite,
+        }
+    }
+
+    pub fn alignment_symbol(&self) -> &'static str {
         match self.alignment.cmp(&0) {
-            Ordering::Less => buf.draw_text("*", 0, 0, color),
-            Ordering::Equal => buf.draw_text("-", 0, 0, color),
-            Ordering::Greater => buf.draw_text("^", 0, 0, color),
+            Ordering::Less => "*",
+            Ordering::Equal => "-",
+            Ordering::Greater => "^",
+        }
+    }
+
+    fn icon(&self) -> &'static str {
+        match self.kind {
+            SpellKind::Creation(_)
+            | SpellKind::MagicFire(_)
+            | SpellKind::GooeyBlob(_)
+            | SpellKind::MagicWood(_)
+            | SpellKind::ShadowWood(_)
+            | SpellKind::Shelter(_)
+            | SpellKind::Wall(_) => "&",
+            SpellKind::MagicBolt | SpellKind::Lightning | SpellKind::MagicalAttack(_) => "!",
+            SpellKind::WizardAttackBuff(_)
+            | SpellKind::WizardDefenceBuff(_)
+            | SpellKind::MagicBow
+            | SpellKind::MagicWings
+            | SpellKind::ShadowForm => "+",
+            SpellKind::Disbelieve | SpellKind::WorldAlignment | SpellKind::Subversion | SpellKind::RaiseDead => "#",
+        }
+    }
+
+    pub fn as_icon_buffer(&self, world_alignment: i8, spell_ability: u8) -> Buffer {
+        let mut buf = Buffer::new(2, 2);
+        let color = Self::chance_color(self.cast_chance(world_alignment, spell_ability));
+        buf.draw_text(self.alignment_symbol(), 0, 0, color);
+        buf.draw_text(self.icon(), 1, 0, color);
+        buf
+    }
+
+    pub fn as_name_buffer(
+        &self,
+        world_alignment: i8,
+        spell_ability: u8,
+        show_percentage: bool,
+        show_threshold: bool,
+        width: usize,
+    ) -> Buffer {
+        let width = if show_percentage { width } else { self.name.len() + 1 };
+        let mut buf = Buffer::new(width, 2);
+        let chance = self.cast_chance(world_alignment, spell_ability);
+        let color = Self::chance_color(chance);
+        buf.draw_text(self.alignment_symbol(), 0, 0, color);
+        if show_percentage {
+            let name = self.name.chars().take(width - 6).collect::<String>();
+            buf.draw_text(&name, 1, 0, color);
+            let text = format!("{:>4}", Self::chance_text(chance, show_threshold));
+            buf.draw_text(&text, width - 4, 0, color);
+        } else {
+            buf.draw_text(&self.name, 1, 0, color);
         }
-        buf.draw_text(&self.name, 1, 0, color);
         buf
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum SpellKind {
     Disbelieve,
     Creation(CreationStats),
@@ -112,7 +234,7 @@ pub enum SpellKind {
     RaiseDead,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Spell {
     pub name: String,
     pub chance: u8,
@@ -121,7 +243,27 @@ pub struct Spell {
     pub kind: SpellKind,
 }
 
-pub fn create_spells(number_of_spells: u8) -> Vec<Spell> {
+pub fn load_spellbook() {
+    lazy_static::initialize(&SPELLS);
+}
+
+fn spell_pool(banned: &[String]) -> Vec<&'static Spell> {
+    SPELLS.iter().filter(|spell| !spell.is_banned(banned)).collect()
+}
+
+pub fn bans_every_spell(banned: &[String]) -> bool {
+    SPELLS.iter().all(|spell| spell.is_banned(banned))
+}
+
+pub fn find_spells(names: &[String], banned: &[String]) -> Vec<Spell> {
+    names
+        .iter()
+        .filter_map(|name| SPELLS.iter().find(|spell| spell.name == *name && !spell.is_banned(banned)))
+        .cloned()
+        .collect()
+}
+
+pub fn create_spells(number_of_spells: u8, banned: &[String], rng: &mut impl Rng) -> Vec<Spell> {
     let mut spells = vec![Spell {
         name: "DISBELIEVE".to_string(),
         chance: 9,
@@ -129,10 +271,82 @@ pub fn create_spells(number_of_spells: u8) -> Vec<Spell> {
         alignment: 0,
         kind: SpellKind::Disbelieve,
     }];
-    let mut rng = thread_rng();
+    let pool = spell_pool(banned);
     for _ in 1..number_of_spells {
-        let spell = SPELLS.choose(&mut rng).expect("spell").clone();
+        let spell = pool.choose(rng).copied().expect("spell").clone();
         spells.push(spell);
     }
     spells
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HostOptions;
+    use rand::thread_rng;
+
+    #[test]
+    fn random_spells_are_never_disbelieve() {
+        for _ in 0..1000 {
+            assert_ne!(Spell::random(&[], &mut thread_rng()).kind, SpellKind::Disbelieve);
+        }
+    }
+
+    #[test]
+    fn only_the_first_spell_in_a_hand_is_disbelieve() {
+        let spells = create_spells(20, &[], &mut thread_rng());
+        assert_eq!(spells.len(), 20);
+        assert_eq!(spells[0].kind, SpellKind::Disbelieve);
+        assert!(spells[1..].iter().all(Spell::is_valid));
+    }
+
+    #[test]
+    fn casting_chance_stops_at_the_stat_cap() {
+        let mut bolt = find_spells(&["MAGIC BOLT".to_string()], &[]).remove(0);
+        bolt.chance = STAT_CAP;
+        assert_eq!(bolt.cast_chance(0, 0), STAT_CAP as i8);
+        assert_eq!(bolt.cast_chance(0, 1), STAT_CAP as i8);
+        assert_eq!(Spell::percentage(bolt.cast_chance(0, 1)), 100);
+        assert_eq!(Spell::chance_color(bolt.cast_chance(0, 1)), BrightWhite);
+    }
+
+    #[test]
+    fn a_ban_list_covering_every_spell_is_dropped() {
+        let mut names = SPELLS.iter().map(|spell| spell.name.clone()).collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        let allowed = names.pop().unwrap();
+        assert!(!bans_every_spell(&names));
+        assert!(spell_pool(&names).iter().all(|spell| spell.name == allowed));
+        names.push(allowed.to_lowercase());
+        assert!(bans_every_spell(&names));
+        let mut options = HostOptions {
+            banned_spells: names,
+            ..HostOptions::default()
+        };
+        options.sanitize();
+        assert!(options.banned_spells.is_empty());
+    }
+
+    #[test]
+    fn search_matches_prefixes_and_substrings() {
+        let bolt = find_spells(&["MAGIC BOLT".to_string()], &[]).remove(0);
+        assert!(bolt.matches(""));
+        assert!(bolt.matches("MAG"));
+        assert!(bolt.matches("BOLT"));
+        assert!(bolt.matches("C B"));
+        assert!(!bolt.matches("WOOD"));
+        assert!(!bolt.matches("MAGIC BOLTS"));
+    }
+
+    #[test]
+    fn banned_spells_never_appear_in_a_hand() {
+        let banned = vec!["MAGIC BOLT".to_string(), "lightning".to_string()];
+        for _ in 0..200 {
+            let spells = create_spells(20, &banned, &mut thread_rng());
+            assert!(spells.iter().all(|spell| !spell.is_banned(&banned)));
+            assert!(!Spell::random(&banned, &mut thread_rng()).is_banned(&banned));
+        }
+        assert!(find_spells(&["MAGIC BOLT".to_string()], &banned).is_empty());
+    }
+}
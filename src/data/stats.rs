@@ -1,11 +1,35 @@
 use crate::data::wizard::LobbyWizard;
 use crate::gfx::buffer::Buffer;
 use crate::gfx::color::Color;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+pub const STAT_CAP: u8 = 9;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum CombatModel {
+    #[default]
+    Classic,
+    ReducedVariance,
+    Deterministic,
+}
+
+impl CombatModel {
+    fn roll(self, rng: &mut impl Rng) -> u8 {
+        match self {
+            CombatModel::Classic => rng.gen_range(0..=9),
+            CombatModel::ReducedVariance => rng.gen_range(0..=3),
+            CombatModel::Deterministic => 0,
+        }
+    }
+
+    pub fn contest(self, attack: u8, defence: u8, rng: &mut impl Rng) -> bool {
+        attack + self.roll(rng) >= defence + self.roll(rng)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Frame {
     pub bytes: [u8; 32],
     pub fg: Color,
@@ -29,7 +53,7 @@ impl From<&Frame> for Buffer {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Gfx {
     pub timing: u8,
     pub frames: [Frame; 4],
@@ -53,7 +77,7 @@ impl Gfx {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct BaseStats {
     pub name: String,
     pub combat: u8,
@@ -88,7 +112,7 @@ impl From<&BaseStats> for Buffer {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct CreationStats {
     pub base: BaseStats,
     pub casting_chance: u8,
@@ -112,7 +136,7 @@ impl From<&CreationStats> for Buffer {
         let mut buf = Buffer::from(&stats.base);
         match stats.alignment.cmp(&0) {
             Ordering::Less => {
-                let text = format!("(CHAOS {})", stats.alignment.abs());
+                let text = format!("(CHAOS {})", stats.alignment.unsigned_abs());
                 buf.draw_text(&text, stats.base.name.len() + 5, 2, BrightMagenta);
             }
             Ordering::Greater => {
@@ -138,19 +162,19 @@ impl From<&CreationStats> for Buffer {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum AttackBuff {
     MagicKnife,
     MagicSword,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum DefenceBuff {
     MagicShield,
     MagicArmour,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WizardStats {
     pub base: BaseStats,
     pub number_of_spells: u8,
@@ -163,21 +187,20 @@ pub struct WizardStats {
     pub gfx: Gfx,
 }
 
-static MAGIC_KNIFE: &[u8] = include_bytes!("../gfx/bin/wizards/magic_knife.bin");
-static MAGIC_SWORD: &[u8] = include_bytes!("../gfx/bin/wizards/magic_sword.bin");
-static MAGIC_SHIELD: &[u8] = include_bytes!("../gfx/bin/wizards/magic_shield.bin");
-static MAGIC_ARMOUR: &[u8] = include_bytes!("../gfx/bin/wizards/magic_armour.bin");
-static MAGIC_WINGS: &[u8] = include_bytes!("../gfx/bin/wizards/magic_wings.bin");
-static MAGIC_BOW: &[u8] = include_bytes!("../gfx/bin/wizards/magic_bow.bin");
+static MAGIC_KNIFE: &[u8; 128] = include_bytes!("../gfx/bin/wizards/magic_knife.bin");
+static MAGIC_SWORD: &[u8; 128] = include_bytes!("../gfx/bin/wizards/magic_sword.bin");
+static MAGIC_SHIELD: &[u8; 32] = include_bytes!("../gfx/bin/wizards/magic_shield.bin");
+static MAGIC_ARMOUR: &[u8; 32] = include_bytes!("../gfx/bin/wizards/magic_armour.bin");
+static MAGIC_WINGS: &[u8; 128] = include_bytes!("../gfx/bin/wizards/magic_wings.bin");
+static MAGIC_BOW: &[u8; 128] = include_bytes!("../gfx/bin/wizards/magic_bow.bin");
 
 impl WizardStats {
-    pub fn new(wizard: &LobbyWizard, level: u8) -> Self {
-        let mut rng = thread_rng();
+    pub fn new(wizard: &LobbyWizard, level: u8, max_spells: u8, rng: &mut impl Rng) -> Self {
         let combat = 1 + (rng.gen_range(0..=9) / 2) + (level / 2);
         let defence = 1 + (rng.gen_range(0..=9) / 2) + (level / 2);
         let manoeuvre = 3 + (rng.gen_range(0..=9) / 2) + (level / 4);
         let magical_resistance = 6 + (rng.gen_range(0..=9) / 4);
-        let number_of_spells = (11 + (rng.gen_range(0..=9) / 4) + level).min(20);
+        let number_of_spells = (11 + (rng.gen_range(0..=9) / 4) + level).min(max_spells);
         let r = rng.gen_range(0..=9);
         let spell_ability = if r >= (5 - (level / 2)) { r / 4 } else { 0 };
         let frame = Frame::from(&wizard.player);
@@ -227,13 +250,11 @@ impl WizardStats {
     }
 
     pub fn magic_shield(&mut self) {
-        let bytes = &MAGIC_SHIELD[..].try_into().expect("Invalid frame");
-        self.gfx.change_frame_bytes(&[bytes, bytes, bytes, bytes]);
+        self.gfx.change_frame_bytes(&[MAGIC_SHIELD; 4]);
     }
 
     pub fn magic_armour(&mut self) {
-        let bytes = &MAGIC_ARMOUR[..].try_into().expect("Invalid frame");
-        self.gfx.change_frame_bytes(&[bytes, bytes, bytes, bytes]);
+        self.gfx.change_frame_bytes(&[MAGIC_ARMOUR; 4]);
     }
 
     pub fn magic_wings(&mut self) {
@@ -268,7 +289,7 @@ impl WizardStats {
                 }
             }
         }
-        combat.min(9)
+        combat.min(STAT_CAP)
     }
 
     pub fn get_defence(&self) -> u8 {
@@ -286,7 +307,7 @@ impl WizardStats {
         if self.shadow_form {
             defence += 3;
         }
-        defence.min(9)
+        defence.min(STAT_CAP)
     }
 
     pub fn get_ranged_combat(&self) -> u8 {
@@ -355,3 +376,63 @@ impl From<&WizardStats> for Buffer {
         buf
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Player;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn contests(model: CombatModel, attack: u8, defence: u8, rng: &mut impl Rng) -> Vec<bool> {
+        (0..100).map(|_| model.contest(attack, defence, rng)).collect()
+    }
+
+    #[test]
+    fn deterministic_contests_always_go_to_the_higher_stat() {
+        for attack in 0..=STAT_CAP {
+            for defence in 0..=STAT_CAP {
+                let results = contests(CombatModel::Deterministic, attack, defence, &mut StdRng::seed_from_u64(0));
+                assert!(results.iter().all(|&result| result == (attack >= defence)));
+            }
+        }
+    }
+
+    #[test]
+    fn classic_contests_repeat_with_a_fixed_seed() {
+        let first = contests(CombatModel::Classic, 3, 5, &mut StdRng::seed_from_u64(658));
+        let second = contests(CombatModel::Classic, 3, 5, &mut StdRng::seed_from_u64(658));
+        assert_eq!(first, second);
+        assert!(first.contains(&true) && first.contains(&false));
+    }
+
+    fn wizard_stats(combat: u8, defence: u8) -> WizardStats {
+        let wizard = LobbyWizard {
+            player: Player {
+                name: "WIZARD".to_string(),
+                character: 0.try_into().expect("character"),
+                color: 0.try_into().expect("color"),
+            },
+            id: 0,
+            ready: true,
+            responsive: true,
+            position: None,
+        };
+        let mut stats = WizardStats::new(&wizard, 0, 20, &mut StdRng::seed_from_u64(616));
+        stats.base.combat = combat;
+        stats.base.defence = defence;
+        stats
+    }
+
+    #[test]
+    fn buffs_reach_the_stat_cap_and_clamp_past_it() {
+        let mut stats = wizard_stats(STAT_CAP - 4, STAT_CAP - 4);
+        stats.attack_buff = Some(AttackBuff::MagicSword);
+        stats.defence_buff = Some(DefenceBuff::MagicArmour);
+        assert_eq!((stats.get_combat(), stats.get_defence()), (STAT_CAP, STAT_CAP));
+        stats.base.combat += 1;
+        stats.base.defence += 1;
+        assert_eq!((stats.get_combat(), stats.get_defence()), (STAT_CAP, STAT_CAP));
+        stats.shadow_form = true;
+        assert_eq!(stats.get_defence(), STAT_CAP);
+    }
+}
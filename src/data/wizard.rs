@@ -1,12 +1,16 @@
-use super::{spells::create_spells, Ticable};
-use crate::config::Player;
+use super::{
+    frame_timing,
+    spells::{create_spells, find_spells},
+    Ticable,
+};
+use crate::config::{CampaignWizard, Player};
 use crate::data::arena::ArenaError;
 use crate::data::spells::Spell;
-use crate::data::stats::{Frame, WizardStats};
+use crate::data::stats::{AttackBuff, CombatModel, DefenceBuff, Frame, WizardStats};
 use crate::gfx::buffer::Buffer;
 use crate::gfx::color::Color;
 use crate::net::NetworkError;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::{error, fmt};
@@ -23,7 +27,11 @@ static STARTING_POSITIONS: &[&[(u8, u8)]; 7] = &[
     &[(0, 0), (7, 0), (14, 0), (0, 4), (14, 4), (0, 9), (7, 9), (14, 9)],
 ];
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+pub fn position_table(players: usize) -> Option<&'static [(u8, u8)]> {
+    STARTING_POSITIONS.get(players.checked_sub(2)?).copied()
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum WizardCharacter {
     Jevarell,
     LargeFart,
@@ -73,7 +81,7 @@ impl TryFrom<isize> for WizardCharacter {
     }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum WizardColor {
     BrightRed,
     BrightMagenta,
@@ -142,9 +150,11 @@ pub struct LobbyWizard {
     pub player: Player,
     pub id: u32,
     pub ready: bool,
+    pub responsive: bool,
+    pub position: Option<usize>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
 pub struct Wizard {
     pub player: Player,
     pub id: u32,
@@ -152,13 +162,32 @@ pub struct Wizard {
     pub disconnected: bool,
     pub spells: Vec<Spell>,
     pub stats: WizardStats,
+    pub position: Option<usize>,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ServerWizards {
     wizards: Vec<Wizard>,
 }
 
 impl ServerWizards {
+    pub fn new(wizards: LobbyWizards, rng: &mut impl Rng) -> Self {
+        let level = wizards.level;
+        let max_spells = wizards.max_spells;
+        let banned_spells = wizards.banned_spells;
+        let campaign = wizards.campaign;
+        let mut players = wizards.players.into_values().collect::<Vec<_>>();
+        players.sort_by(|a, b| a.id.cmp(&b.id));
+        let wizards = players
+            .into_iter()
+            .map(|wizard| {
+                let progress = campaign.iter().find(|progress| progress.player == wizard.player);
+                Wizard::new(wizard, level, max_spells, &banned_spells, progress, rng)
+            })
+            .collect();
+        Self { wizards }
+    }
+
     pub fn get(&self, id: u32) -> Result<&Wizard, NetworkError> {
         self.wizards.iter().find(|w| w.id == id).ok_or(NetworkError::GenericError)
     }
@@ -171,13 +200,23 @@ impl ServerWizards {
         Ok(self.get(id)?.disconnected)
     }
 
-    pub fn starting_positions(&self) -> Result<impl Iterator<Item = (u8, u8, &Wizard)>, ArenaError> {
-        Ok(STARTING_POSITIONS
-            .get(self.wizards.len() - 2)
-            .ok_or(ArenaError::InvalidNumPlayers)?
+    pub fn starting_positions(&self) -> Result<Vec<(u8, u8, &Wizard)>, ArenaError> {
+        let table = position_table(self.wizards.len()).ok_or(ArenaError::InvalidNumPlayers)?;
+        let claimed = |index: usize| self.wizards.iter().any(|wizard| wizard.position == Some(index));
+        let mut unclaimed = (0..table.len()).filter(|index| !claimed(*index));
+        let positions = self
+            .wizards
             .iter()
-            .zip(self.wizards.iter())
-            .map(|((x, y), wiz)| (*x, *y, wiz)))
+            .map(|wizard| {
+                let index = match wizard.position.filter(|index| *index < table.len()) {
+                    Some(index) => index,
+                    None => unclaimed.next().expect("position"),
+                };
+                let (x, y) = table[index];
+                (x, y, wizard)
+            })
+            .collect::<Vec<_>>();
+        Ok(positions)
     }
 
     pub fn all_active_ids(&self) -> Vec<u32> {
@@ -218,19 +257,45 @@ impl ServerWizards {
     }
 }
 
-impl From<LobbyWizards> for ServerWizards {
-    fn from(wizards: LobbyWizards) -> Self {
-        let mut wizards = wizards.players.into_values().map(Wizard::from).collect::<Vec<_>>();
-        wizards.sort_by(|a, b| a.id.cmp(&b.id));
-        Self { wizards }
-    }
-}
-
-impl From<LobbyWizard> for Wizard {
-    fn from(wizard: LobbyWizard) -> Self {
-        let level = 0;
-        let stats = WizardStats::new(&wizard, level);
-        let spells = create_spells(stats.number_of_spells);
+impl Wizard {
+    pub fn new(
+        wizard: LobbyWizard,
+        level: u8,
+        max_spells: u8,
+        banned_spells: &[String],
+        progress: Option<&CampaignWizard>,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let level = progress.map_or(level, |progress| progress.level.min(8));
+        let mut stats = WizardStats::new(&wizard, level, max_spells, rng);
+        let mut spells = create_spells(stats.number_of_spells, banned_spells, rng);
+        if let Some(progress) = progress {
+            let carried = find_spells(&progress.spells, banned_spells);
+            for (spell, carried) in spells.iter_mut().skip(1).zip(carried) {
+                *spell = carried;
+            }
+            if let Some(ref buff) = progress.attack_buff {
+                stats.attack_buff = Some(buff.clone());
+                match buff {
+                    AttackBuff::MagicKnife => stats.magic_knife(),
+                    AttackBuff::MagicSword => stats.magic_sword(),
+                }
+            }
+            if let Some(ref buff) = progress.defence_buff {
+                stats.defence_buff = Some(buff.clone());
+                match buff {
+                    DefenceBuff::MagicShield => stats.magic_shield(),
+                    DefenceBuff::MagicArmour => stats.magic_armour(),
+                }
+            }
+            if progress.magic_bow {
+                stats.magic_bow();
+            }
+            if progress.magic_wings {
+                stats.magic_wings();
+            }
+            stats.shadow_form = progress.shadow_form;
+        }
         Self {
             player: wizard.player,
             id: wizard.id,
@@ -238,6 +303,7 @@ impl From<LobbyWizard> for Wizard {
             disconnected: false,
             spells,
             stats,
+            position: wizard.position,
         }
     }
 }
@@ -245,6 +311,10 @@ impl From<LobbyWizard> for Wizard {
 #[derive(Default)]
 pub struct LobbyWizards {
     pub players: HashMap<u32, LobbyWizard>,
+    pub level: u8,
+    pub max_spells: u8,
+    pub banned_spells: Vec<String>,
+    pub campaign: Vec<CampaignWizard>,
 }
 
 impl LobbyWizards {
@@ -262,13 +332,40 @@ impl LobbyWizards {
                 player,
                 id,
                 ready: false,
+                responsive: false,
+                position: None,
             },
         );
+        self.clear_positions();
         true
     }
 
     pub fn leave(&mut self, id: u32) -> Option<LobbyWizard> {
-        self.players.remove(&id)
+        let wizard = self.players.remove(&id);
+        if wizard.is_some() {
+            self.clear_positions();
+        }
+        wizard
+    }
+
+    fn clear_positions(&mut self) {
+        for player in self.players.values_mut() {
+            player.position = None;
+        }
+    }
+
+    pub fn claim_position(&mut self, id: u32, index: usize) -> bool {
+        let available = position_table(self.players.len()).is_some_and(|table| index < table.len());
+        if !available || self.players.values().any(|player| player.position == Some(index)) {
+            return false;
+        }
+        match self.players.get_mut(&id) {
+            Some(player) => {
+                player.position = Some(index);
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn ready(&mut self, id: u32, ready: bool) -> bool {
@@ -280,6 +377,16 @@ impl LobbyWizards {
         }
     }
 
+    pub fn responsive(&mut self, id: u32, responsive: bool) -> bool {
+        match self.players.get_mut(&id) {
+            Some(player) if player.responsive != responsive => {
+                player.responsive = responsive;
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn players(&self) -> impl Iterator<Item = LobbyWizard> {
         let mut vec: Vec<LobbyWizard> = self.players.values().cloned().collect();
         vec.sort_by(|a, b| a.id.cmp(&b.id));
@@ -291,15 +398,18 @@ impl LobbyWizards {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct GameWizard {
     pub id: u32,
     pub name: String,
+    pub color: WizardColor,
     pub moves_left: u8,
     pub stats: WizardStats,
     frame_count: u8,
     current_frame: u8,
     pub buffers: [Buffer; 4],
+    pub facing_left: bool,
+    pub ranged_used: bool,
 }
 
 impl GameWizard {
@@ -308,14 +418,12 @@ impl GameWizard {
         self.stats = stats;
     }
 
-    pub fn is_engaged(&self, manoeuvre: u8) -> bool {
-        let mut rng = thread_rng();
-        self.stats.base.manoeuvre + rng.gen_range(0..=9) <= manoeuvre + rng.gen_range(0..=9)
+    pub fn is_engaged(&self, manoeuvre: u8, model: CombatModel, rng: &mut impl Rng) -> bool {
+        model.contest(manoeuvre, self.stats.base.manoeuvre, rng)
     }
 
-    pub fn defend_against_attack(&self, combat: u8) -> bool {
-        let mut rng = thread_rng();
-        combat + rng.gen_range(0..=9) >= self.stats.get_defence() + rng.gen_range(0..=9)
+    pub fn defend_against_attack(&self, combat: u8, model: CombatModel, rng: &mut impl Rng) -> bool {
+        model.contest(combat, self.stats.get_defence(), rng)
     }
 
     pub fn current_bytes(&self) -> [u8; 32] {
@@ -331,9 +439,8 @@ impl GameWizard {
         self.stats.gfx.frames.get(self.current_frame as usize).expect("Invalid Frame")
     }
 
-    pub fn defend_against_magical_attack(&self, spell_ability: u8) -> bool {
-        let mut rng = thread_rng();
-        spell_ability + rng.gen_range(0..=9) >= self.stats.base.magical_resistance + rng.gen_range(0..=9)
+    pub fn defend_against_magical_attack(&self, spell_ability: u8, model: CombatModel, rng: &mut impl Rng) -> bool {
+        model.contest(spell_ability, self.stats.base.magical_resistance, rng)
     }
 }
 
@@ -342,18 +449,21 @@ impl From<&Wizard> for GameWizard {
         GameWizard {
             id: wizard.id,
             name: wizard.player.name.clone(),
+            color: wizard.player.color,
             moves_left: 0,
             stats: wizard.stats.clone(),
             frame_count: 0,
             current_frame: 0,
             buffers: wizard.stats.gfx.as_buffers(),
+            facing_left: false,
+            ranged_used: false,
         }
     }
 }
 
 impl Ticable for GameWizard {
     fn tic(&mut self) -> Option<&Buffer> {
-        if self.frame_count == self.stats.gfx.timing {
+        if self.frame_count >= frame_timing(self.stats.gfx.timing) {
             self.frame_count = 0;
             self.current_frame += 1;
             if self.current_frame >= 4 {
@@ -372,3 +482,141 @@ impl Ticable for GameWizard {
         self.buffers.get(self.current_frame as usize).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, thread_rng, SeedableRng};
+
+    fn player(id: u32) -> Player {
+        Player {
+            name: format!("WIZARD {id}"),
+            character: (id as isize % 8).try_into().expect("character"),
+            color: (id as isize % 8).try_into().expect("color"),
+        }
+    }
+
+    fn lobby(ids: &[u32]) -> LobbyWizards {
+        let mut wizards = LobbyWizards::new();
+        for &id in ids {
+            wizards.join(id, player(id));
+        }
+        wizards
+    }
+
+    #[test]
+    fn each_starting_level_adds_a_spell_up_to_the_hand_limit() {
+        let mut rng = StdRng::seed_from_u64(601);
+        for level in 0..=8 {
+            for _ in 0..20 {
+                let lobby_wizard = lobby(&[0]).players.remove(&0).unwrap();
+                let wizard = Wizard::new(lobby_wizard, level, 40, &[], None, &mut rng);
+                let number_of_spells = wizard.stats.number_of_spells;
+                assert!(
+                    (11 + level..=13 + level).contains(&number_of_spells),
+                    "level {level}: {number_of_spells}"
+                );
+                assert_eq!(wizard.spells.len(), number_of_spells as usize);
+            }
+        }
+        let lobby_wizard = lobby(&[0]).players.remove(&0).unwrap();
+        let wizard = Wizard::new(lobby_wizard, 8, 15, &[], None, &mut rng);
+        assert_eq!((wizard.stats.number_of_spells, wizard.spells.len()), (15, 15));
+    }
+
+    #[test]
+    fn winners_are_listed_in_id_order() {
+        let mut wizards = ServerWizards::new(lobby(&[6, 2, 4, 0]), &mut thread_rng());
+        wizards.get_mut(4).unwrap().alive = false;
+        assert_eq!(wizards.winners(), vec![player(0), player(2), player(6)]);
+    }
+
+    #[test]
+    fn a_lone_wizard_has_no_starting_position() {
+        let wizards = ServerWizards::new(lobby(&[3]), &mut thread_rng());
+        assert!(matches!(wizards.starting_positions(), Err(ArenaError::InvalidNumPlayers)));
+    }
+
+    #[test]
+    fn a_lone_ready_wizard_cannot_start_a_match() {
+        let mut wizards = lobby(&[0]);
+        wizards.ready(0, true);
+        assert!(!wizards.is_ready());
+        wizards.join(1, player(1));
+        wizards.ready(1, true);
+        assert!(wizards.is_ready());
+    }
+
+    fn start_tiles(wizards: LobbyWizards) -> Vec<(u8, u8, u32)> {
+        let wizards = ServerWizards::new(wizards, &mut thread_rng());
+        let positions = wizards.starting_positions().expect("positions");
+        positions.into_iter().map(|(x, y, wizard)| (x, y, wizard.id)).collect()
+    }
+
+    #[test]
+    fn claimed_and_auto_filled_start_positions_never_overlap() {
+        for players in 2..=8u32 {
+            let ids = (0..players).collect::<Vec<_>>();
+            let table = position_table(players as usize).expect("table");
+            for claimers in 0..=players {
+                let mut wizards = lobby(&ids);
+                for id in 0..claimers {
+                    wizards.claim_position(id, (id as usize * 3 + 1) % table.len());
+                }
+                let claims = wizards
+                    .players()
+                    .filter_map(|wizard| wizard.position.map(|index| (table[index], wizard.id)))
+                    .collect::<Vec<_>>();
+                let positions = start_tiles(wizards);
+                assert_eq!(positions.len(), players as usize);
+                for (i, (x, y, _)) in positions.iter().enumerate() {
+                    assert!(positions[..i].iter().all(|(ox, oy, _)| (ox, oy) != (x, y)), "{positions:?}");
+                }
+                for ((x, y), id) in claims {
+                    assert!(positions.contains(&(x, y, id)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_position_can_only_be_claimed_once() {
+        let mut wizards = lobby(&[0, 1, 2]);
+        assert!(wizards.claim_position(0, 1));
+        assert!(!wizards.claim_position(1, 1));
+        assert!(!wizards.claim_position(1, 3));
+        assert!(wizards.claim_position(1, 2));
+        assert_eq!(start_tiles(wizards), vec![(1, 8, 0), (13, 8, 1), (7, 1, 2)]);
+    }
+
+    #[test]
+    fn leaving_removes_the_player_from_the_lobby_list() {
+        let mut wizards = lobby(&[0, 1, 2]);
+        assert!(wizards.responsive(1, true));
+        assert!(wizards.leave(1).is_some());
+        let ids = wizards.players().map(|wizard| wizard.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![0, 2]);
+        assert!(wizards.leave(1).is_none());
+        assert!(!wizards.responsive(1, false));
+        assert_eq!(wizards.players().count(), 2);
+    }
+
+    #[test]
+    fn leaving_clears_claims_made_for_the_larger_table() {
+        let mut wizards = lobby(&[0, 1, 2, 3]);
+        assert!(wizards.claim_position(3, 3));
+        assert!(wizards.claim_position(0, 0));
+        wizards.leave(2);
+        assert!(wizards.players().all(|wizard| wizard.position.is_none()));
+        assert_eq!(start_tiles(wizards), vec![(7, 1, 0), (1, 8, 1), (13, 8, 3)]);
+    }
+
+    #[test]
+    fn start_positions_stay_distinct_on_the_half_height_lobby_map() {
+        for table in STARTING_POSITIONS {
+            for (i, (x, y)) in table.iter().enumerate() {
+                assert!(table[..i].iter().all(|(ox, oy)| (ox, oy / 2) != (x, y / 2)), "{table:?}");
+            }
+        }
+    }
+}
@@ -6,10 +6,34 @@ use std::vec;
 
 static CURSORS: &[u8; 128] = include_bytes!("bin/cursors.bin");
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+static BOLD_BOX: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0,
+    0x0f, 0xf0, 0x0f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+];
+
+static CORNERS: [u8; 32] = [
+    0xe0, 0x07, 0x80, 0x01, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x80, 0x01, 0xe0, 0x07,
+];
+
+static MARKER: [u8; 32] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x07, 0x00, 0x07,
+];
+
+static ILLUSION: [u8; 32] = [
+    0xe0, 0x00, 0xe0, 0x00, 0xe0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum MouseCursor {
     Spell,
     Box,
+    BoldBox,
+    Corners,
+    Marker,
+    Illusion,
     Wings,
     Ranged,
 }
@@ -19,13 +43,17 @@ impl MouseCursor {
         match self {
             Self::Spell => &CURSORS[0..32],
             Self::Box => &CURSORS[32..64],
+            Self::BoldBox => &BOLD_BOX,
+            Self::Corners => &CORNERS,
+            Self::Marker => &MARKER,
+            Self::Illusion => &ILLUSION,
             Self::Wings => &CURSORS[64..96],
             Self::Ranged => &CURSORS[96..],
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Buffer {
     pub data: Vec<u32>,
     pub width: usize,
@@ -45,20 +73,20 @@ impl Buffer {
     fn draw_bytes(&mut self, bytes: &[u8], x: usize, y: usize, fg: Color, bg: Option<Color>) {
         let fg = fg.into();
         let bg = bg.map(|bg| bg.into());
-        let mut index = (self.width * (y * 8)) + x * 8;
-        for mut byte in bytes.iter().copied() {
-            for _ in 0..8 {
-                if let Some(data) = self.data.get_mut(index) {
-                    if (byte & 0b1000_0000) == 0b1000_0000 {
-                        *data = fg;
-                    } else if let Some(bg) = bg {
-                        *data = bg;
-                    };
+        for (row, mut byte) in bytes.iter().copied().enumerate() {
+            let start = (y * 8 + row) * self.width;
+            for col in x * 8..x * 8 + 8 {
+                if col < self.width {
+                    if let Some(data) = self.data.get_mut(start + col) {
+                        if (byte & 0b1000_0000) == 0b1000_0000 {
+                            *data = fg;
+                        } else if let Some(bg) = bg {
+                            *data = bg;
+                        };
+                    }
                 }
                 byte <<= 1;
-                index += 1;
             }
-            index += self.width - 8;
         }
     }
 
@@ -71,21 +99,21 @@ impl Buffer {
     pub fn draw_shorts(&mut self, bytes: &[u8], x: usize, y: usize, fg: Color, bg: Option<Color>) {
         let fg = fg.into();
         let bg = bg.map(|bg| bg.into());
-        let mut index = (self.width * (y * 8)) + x * 8;
-        for slice in bytes.chunks(2) {
+        for (row, slice) in bytes.chunks(2).enumerate() {
             let mut short = u16::from_be_bytes(slice.try_into().expect("shorts in bytes"));
-            for _ in 0..16 {
-                if let Some(data) = self.data.get_mut(index) {
-                    if (short & 0b1000_0000_0000_0000) == 0b1000_0000_0000_0000 {
-                        *data = fg;
-                    } else if let Some(bg) = bg {
-                        *data = bg;
-                    };
+            let start = (y * 8 + row) * self.width;
+            for col in x * 8..x * 8 + 16 {
+                if col < self.width {
+                    if let Some(data) = self.data.get_mut(start + col) {
+                        if (short & 0b1000_0000_0000_0000) == 0b1000_0000_0000_0000 {
+                            *data = fg;
+                        } else if let Some(bg) = bg {
+                            *data = bg;
+                        };
+                    }
                 }
                 short <<= 1;
-                index += 1;
             }
-            index += self.width - 16;
         }
     }
 
@@ -97,9 +125,14 @@ impl Buffer {
 
     pub fn fill_area(&mut self, x: usize, y: usize, width: usize, height: usize, col: Color) {
         let col = col.into();
+        let x = x * 8;
+        if x >= self.width {
+            return;
+        }
+        let width = (width * 8).min(self.width - x);
         for y in y * 8..(y + height) * 8 {
-            let start = (y * self.width) + x * 8;
-            if let Some(slice) = self.data.get_mut(start..start + width * 8) {
+            let start = (y * self.width) + x;
+            if let Some(slice) = self.data.get_mut(start..start + width) {
                 slice.fill(col);
             }
         }
@@ -117,23 +150,46 @@ impl Buffer {
         let width = width * 8;
         let height = height * 8;
         let mut data = vec![0; width * height];
-        let mut index = ((y * 8) * self.width) + (x * 8);
-        for dst in data.chunks_mut(width) {
-            if let Some(data) = self.data.get(index..index + width) {
-                dst.copy_from_slice(data);
+        let x = x * 8;
+        if width > 0 && x < self.width {
+            let visible = width.min(self.width - x);
+            for (row, dst) in data.chunks_mut(width).enumerate() {
+                let start = (y * 8 + row) * self.width + x;
+                match self.data.get(start..start + visible) {
+                    Some(src) => dst[..visible].copy_from_slice(src),
+                    None => break,
+                }
             }
-            index += self.width;
         }
         Self { data, width, height }
     }
 
+    pub fn flip_horizontal(&self) -> Self {
+        let mut data = self.data.clone();
+        if self.width > 0 {
+            for row in data.chunks_mut(self.width) {
+                row.reverse();
+            }
+        }
+        Self {
+            data,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
     pub fn draw_buffer(&mut self, buf: &Buffer, x: usize, y: usize) {
-        let mut index = (self.width * (y * 8)) + (x * 8);
-        for src in buf.data.chunks(buf.width) {
-            if let Some(data) = self.data.get_mut(index..index + buf.width) {
-                data.copy_from_slice(src);
+        let x = x * 8;
+        if buf.width == 0 || x >= self.width {
+            return;
+        }
+        let width = buf.width.min(self.width - x);
+        for (row, src) in buf.data.chunks(buf.width).enumerate() {
+            let start = (y * 8 + row) * self.width + x;
+            match self.data.get_mut(start..start + width) {
+                Some(dst) => dst.copy_from_slice(&src[..width]),
+                None => break,
             }
-            index += self.width;
         }
     }
 }
@@ -179,6 +235,9 @@ lazy_static! {
 
 impl Buffer {
     pub fn border(&mut self, x: usize, y: usize, width: usize, height: usize, fg: Color, bg: Color) {
+        if width == 0 || height == 0 {
+            return;
+        }
         let borders = &BORDERS[(fg, bg).to_color_index()];
         for x in x + 1..x + width - 1 {
             self.draw_buffer(&borders.top, x, y);
@@ -203,6 +262,20 @@ impl Buffer {
     }
 }
 
+fn char_code(ch: char) -> Option<usize> {
+    if ch == '©' {
+        return Some(95);
+    }
+    match u8::try_from(ch) {
+        Ok(ascii_code @ 32..=126) => Some((ascii_code as usize) - 32),
+        _ => None,
+    }
+}
+
+pub fn is_renderable(ch: char) -> bool {
+    char_code(ch).is_some()
+}
+
 static TEXT_CHARS: &[u8; 1552] = include_bytes!("bin/text_characters.bin");
 
 lazy_static! {
@@ -226,14 +299,7 @@ impl Buffer {
     pub fn draw_text_with_bg(&mut self, text: &str, x: usize, y: usize, fg: Color, bg: Color) {
         for (ln, text) in text.split('\n').enumerate() {
             for (col, ch) in text.chars().enumerate() {
-                let char_code = if ch == '©' {
-                    95
-                } else {
-                    match u8::try_from(ch) {
-                        Ok(0..=31 | 128..=255) | Err(_) => 0,
-                        Ok(ascii_code) => (ascii_code as usize) - 32,
-                    }
-                };
+                let char_code = char_code(ch).unwrap_or(0);
                 if let Some(vec) = CHARMAP.get((fg, bg).to_color_index()) {
                     if let Some(buf) = vec.get(char_code) {
                         self.draw_buffer(buf, x + col, y + (ln * 2));
@@ -317,7 +383,7 @@ lazy_static! {
 
 impl Buffer {
     #[inline]
-    fn put_pixel(&mut self, x: usize, y: usize, color: Color) {
+    pub fn put_pixel(&mut self, x: usize, y: usize, color: Color) {
         if let Some(data) = self.data.get_mut((y * self.width) + x) {
             *data = color.into();
         }
@@ -402,3 +468,150 @@ impl Buffer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    fn points() -> Vec<(usize, usize)> {
+        (0..60).map(|i| (8 + i, 8 + i / 2)).collect()
+    }
+
+    #[test]
+    fn projectile_draws_the_ten_points_behind_start() {
+        let mut buf = Buffer::new(80, 48);
+        let points = points();
+        buf.draw_projectile(&points, 20, Color::BrightWhite);
+        for (i, &(x, y)) in points.iter().enumerate() {
+            let drawn = buf.get_pixel(x, y) == Some(Color::BrightWhite.into());
+            assert_eq!(drawn, (10..20).contains(&i), "point {i}");
+        }
+    }
+
+    #[test]
+    fn spell_line_erases_the_trailing_point() {
+        let mut buf = Buffer::new(80, 48);
+        let points = points();
+        for start in 0..=points.len() {
+            buf.draw_spell_line(&points, start);
+        }
+        let (x, y) = points[points.len() - 30];
+        assert_eq!(buf.get_pixel(x, y), Some(Color::Black.into()));
+        let (x, y) = points[points.len() - 1];
+        assert_eq!(buf.get_pixel(x, y), Some(Color::BrightCyan.into()));
+    }
+
+    #[test]
+    fn fireballs_leave_only_the_head_of_the_trail() {
+        let mut buf = Buffer::new(80, 48);
+        let points = points();
+        for start in 0..=40 {
+            buf.draw_fireballs(&points, start);
+        }
+        for (i, &(x, y)) in points.iter().enumerate().take(11).skip(1) {
+            assert_eq!(buf.get_pixel(x, y), Some(Color::Black.into()), "point {i}");
+        }
+        let (x, y) = points[37];
+        assert_eq!(buf.get_pixel(x, y), Some(Color::BrightYellow.into()));
+    }
+
+    #[test]
+    fn lightning_leaves_only_the_head_of_the_bolt() {
+        let mut buf = Buffer::new(80, 48);
+        let points = points();
+        for start in 0..=40 {
+            buf.draw_lightning(&points, start);
+        }
+        for (i, &(x, y)) in points.iter().enumerate().take(11).skip(1) {
+            assert_eq!(buf.get_pixel(x, y), Some(Color::Black.into()), "point {i}");
+        }
+        let (x, y) = points[37];
+        assert_eq!(buf.get_pixel(x, y), Some(Color::BrightWhite.into()));
+        assert!(painted(&buf, Color::BrightYellow).is_empty());
+    }
+
+    fn painted(buf: &Buffer, color: Color) -> Vec<(usize, usize)> {
+        let color = color.into();
+        (0..buf.height)
+            .flat_map(|y| (0..buf.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| buf.get_pixel(x, y) == Some(color))
+            .collect()
+    }
+
+    fn rect(xs: Range<usize>, ys: Range<usize>) -> Vec<(usize, usize)> {
+        ys.flat_map(|y| xs.clone().map(move |x| (x, y))).collect()
+    }
+
+    #[test]
+    fn crop_pads_what_lies_past_the_edges_with_zeros() {
+        let mut buf = Buffer::new(2, 2);
+        buf.fill_area(0, 0, 2, 2, Color::BrightRed);
+        let cropped = buf.crop(1, 1, 2, 2);
+        assert_eq!((cropped.width, cropped.height), (16, 16));
+        assert_eq!(painted(&cropped, Color::BrightRed), rect(0..8, 0..8));
+        assert_eq!(cropped.data.iter().filter(|&&pixel| pixel == 0).count(), 16 * 16 - 8 * 8);
+        for cropped in [buf.crop(2, 0, 1, 1), buf.crop(0, 2, 1, 1), buf.crop(5, 5, 2, 2)] {
+            assert!(cropped.data.iter().all(|&pixel| pixel == 0));
+        }
+    }
+
+    #[test]
+    fn draw_buffer_clips_at_the_right_and_bottom_edges() {
+        let mut src = Buffer::new(2, 2);
+        src.fill_area(0, 0, 2, 2, Color::BrightGreen);
+        let mut buf = Buffer::new(3, 3);
+        buf.draw_buffer(&src, 2, 2);
+        assert_eq!(painted(&buf, Color::BrightGreen), rect(16..24, 16..24));
+        let before = buf.clone();
+        buf.draw_buffer(&src, 3, 0);
+        buf.draw_buffer(&src, 0, 3);
+        assert_eq!(buf, before);
+    }
+
+    #[test]
+    fn draw_bytes_clips_at_the_bottom_and_never_wraps_past_the_right_edge() {
+        let mut buf = Buffer::new(3, 3);
+        buf.draw_bytes(&[0xff; 16], 2, 2, Color::BrightGreen, None);
+        assert_eq!(painted(&buf, Color::BrightGreen), rect(16..24, 16..24));
+        buf.draw_bytes(&[0xff; 8], 3, 0, Color::BrightRed, None);
+        assert!(painted(&buf, Color::BrightRed).is_empty());
+    }
+
+    #[test]
+    fn draw_shorts_clips_a_partly_visible_cursor() {
+        let mut buf = Buffer::new(3, 3);
+        buf.draw_shorts(&[0xff; 32], 2, 2, Color::BrightGreen, None);
+        assert_eq!(painted(&buf, Color::BrightGreen), rect(16..24, 16..24));
+        buf.draw_shorts(&[0x00, 0xff].repeat(16), 1, 0, Color::BrightRed, Some(Color::BrightBlue));
+        assert_eq!(painted(&buf, Color::BrightBlue), rect(8..16, 0..16));
+        assert_eq!(painted(&buf, Color::BrightRed), rect(16..24, 0..16));
+    }
+
+    #[test]
+    fn fill_area_clips_at_the_right_and_bottom_edges() {
+        let mut buf = Buffer::new(3, 3);
+        buf.fill_area(2, 2, 3, 3, Color::BrightGreen);
+        assert_eq!(painted(&buf, Color::BrightGreen), rect(16..24, 16..24));
+        buf.fill_area(3, 0, 2, 2, Color::BrightRed);
+        buf.fill_area(0, 3, 2, 2, Color::BrightRed);
+        assert!(painted(&buf, Color::BrightRed).is_empty());
+    }
+
+    #[test]
+    fn flipping_twice_gives_back_the_original() {
+        let mut buf = Buffer::new(3, 2);
+        buf.draw_text("AB", 0, 0, Color::BrightYellow);
+        buf.put_pixel(1, 15, Color::BrightRed);
+        let flipped = buf.flip_horizontal();
+        assert_ne!(flipped, buf);
+        assert_eq!(flipped.get_pixel(22, 15), Some(Color::BrightRed.into()));
+        assert_eq!(flipped.flip_horizontal(), buf);
+    }
+
+    #[test]
+    fn unrenderable_characters_are_reported() {
+        assert!(is_renderable(' ') && is_renderable('~') && is_renderable('©'));
+        assert!(!is_renderable('\x1f') && !is_renderable('\x7f') && !is_renderable('Ë') && !is_renderable('\t'));
+    }
+}
@@ -2,7 +2,7 @@ use crate::error::ChaosError;
 use serde::{Deserialize, Serialize};
 use std::{error, fmt};
 
-#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Copy, Default, Serialize, Deserialize)]
 pub enum Color {
     #[default]
     Black,
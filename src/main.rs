@@ -3,16 +3,18 @@ mod data;
 mod error;
 mod gfx;
 mod net;
+mod sound;
 mod ui;
 mod window;
 use clap::Parser;
-use config::{GameConfig, NetAddress, Player};
+use config::{CampaignWizard, GameConfig, HostOptions, NetAddress, Options, Player};
 use data::wizard::{WizardCharacter, WizardColor};
 use error::ChaosError;
 use gfx::buffer::{Buffer, LOGO, SNAKE};
 use gfx::color::Color::*;
-use net::{ChaosClient, ChaosServer};
-use ui::{choose_wizard, host_game, join_game, lobby};
+use net::{drive, ChaosClient, ChaosServer, Message, RandomPlayer};
+use std::collections::HashSet;
+use ui::{choose_wizard, host_game, join_game, lobby, options, practice_game};
 use window::Window;
 
 #[derive(Parser)]
@@ -21,29 +23,87 @@ struct Cli {
     debug_1: bool,
     #[clap(short = '2')]
     debug_2: bool,
+    #[clap(long)]
+    auto_ready: bool,
+    #[clap(long)]
+    title: Option<String>,
+    #[clap(long)]
+    skip_intro: bool,
+    #[clap(long, conflicts_with_all = ["debug_1", "debug_2"])]
+    headless: bool,
 }
 
+async fn practice_bot(addr: NetAddress, index: usize, players: usize) -> Result<(), ChaosError> {
+    let player = Player {
+        name: format!("CPU {}", index + 1),
+        character: WizardCharacter::try_from(index as isize % 8)?,
+        color: WizardColor::try_from((index as isize + 1) % 8)?,
+    };
+    let mut client = ChaosClient::new(&addr).await?;
+    let mut ai = RandomPlayer::default();
+    loop {
+        let mut joined = HashSet::new();
+        let mut ready = false;
+        client.send(Message::Join(player.clone()))?;
+        let respond = |id, msg: &Message| match msg {
+            Message::Join(_) => {
+                joined.insert(id);
+                if !ready && joined.len() == players {
+                    ready = true;
+                    Some(Message::Ready(true))
+                } else {
+                    None
+                }
+            }
+            Message::Leave(id) => {
+                joined.remove(id);
+                None
+            }
+            msg => ai.reply(msg),
+        };
+        if !drive(&mut client, respond).await? {
+            return Ok(());
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn start_game(
     win: &mut Window,
     player: Player,
     host_addr: Option<&NetAddress>,
     addr: &NetAddress,
+    options: &Options,
+    host_options: &HostOptions,
+    campaign: &mut Vec<CampaignWizard>,
+    bots: usize,
 ) -> Result<(), ChaosError> {
     let server = match host_addr {
-        Some(host) => Some(ChaosServer::new(host).await?),
+        Some(host) => Some(ChaosServer::new(host, host_options.clone(), campaign.clone()).await?),
         None => None,
     };
     let mut client = ChaosClient::new(addr).await?;
-    if let Err(err) = lobby(win, player, &mut client).await {
+    for index in 0..bots {
+        tokio::spawn(practice_bot(addr.clone(), index, bots + 1));
+    }
+    let context = match host_addr {
+        Some(_) => format!("Hosting {addr}"),
+        None => format!("Joined {addr}"),
+    };
+    win.set_title_context(Some(&context));
+    let result = lobby(win, player, &mut client, options).await;
+    win.set_title_context(None);
+    if let Err(err) = result {
         client.disconnect().ok();
         if let Some(server) = server {
-            server.shutdown()?;
+            server.shutdown().await?;
         }
         return Err(err);
     }
     client.disconnect().ok();
     if let Some(server) = server {
-        server.shutdown()?;
+        *campaign = server.campaign();
+        server.shutdown().await?;
     }
     Ok(())
 }
@@ -62,7 +122,21 @@ fn use_or_obtain_player(win: &mut Window, config: &mut GameConfig) -> Result<Opt
     }
 }
 
+fn intro_screen(win: &mut Window) -> Result<(), ChaosError> {
+    win.buf.clear();
+    win.buf.draw_buffer(&LOGO, 39, 2);
+    win.buf.center_text("THE BATTLE OF WIZARDS", 7, BrightMagenta);
+    win.buf.center_text("© Games Workshop 1985", 11, BrightYellow);
+    win.buf.center_text("By Julian Gollop", 13, BrightRed);
+    win.buf.center_text("*Raised From The Dead", 16, BrightGreen);
+    win.buf.center_text("By Andrew Herbert", 18, BrightRed);
+    win.buf.draw_text(env!("CARGO_PKG_VERSION"), 0, 22, BrightRed);
+    win.buf.draw_buffer(&SNAKE, 64, 9);
+    win.wait_for_any_key()
+}
+
 fn about_screen(win: &mut Window) -> Result<(), ChaosError> {
+    intro_screen(win)?;
     win.buf.clear();
     win.buf.screen_border("PRESS ANY KEY", BrightBlue, BrightCyan);
     win.buf.draw_text(include_str!("txt/about.txt"), 2, 2, BrightWhite);
@@ -77,8 +151,10 @@ fn error_screen(win: &mut Window, err: ChaosError) -> Result<(), ChaosError> {
     Ok(())
 }
 
-async fn main_menu(win: &mut Window) -> Result<(), ChaosError> {
+async fn main_menu(win: &mut Window, auto_ready: bool) -> Result<(), ChaosError> {
     let mut config = GameConfig::load()?;
+    config.options.auto_ready = auto_ready;
+    win.set_quit_combo(&config.options.quit_keys);
     loop {
         win.buf.clear();
         if let Some(ref player) = config.player {
@@ -86,13 +162,23 @@ async fn main_menu(win: &mut Window) -> Result<(), ChaosError> {
             win.buf.draw_buffer(&buf, 40 + player.name.len(), 3);
             win.buf.draw_text(&player.name, 40, 3, BrightYellow);
         }
-        win.buf.screen_border("PRESS KEYS 1 TO 5", BrightRed, BrightYellow);
-        win.buf.draw_text("1.CHANGE WIZARD", 40, 7, BrightCyan);
-        win.buf.draw_text("2.HOST GAME", 40, 9, BrightCyan);
-        win.buf.draw_text("3.JOIN GAME", 40, 11, BrightCyan);
-        win.buf.draw_text("4.ABOUT CHAOS", 40, 13, BrightCyan);
-        win.buf.draw_text("5.QUIT", 40, 15, BrightCyan);
-        match win.wait_for_number(1..=5)? {
+        win.buf.screen_border("PRESS KEYS 1 TO 7 OR CLICK", BrightRed, BrightYellow);
+        let entries = [
+            "1.CHANGE WIZARD",
+            "2.HOST GAME",
+            "3.JOIN GAME",
+            "4.ABOUT CHAOS",
+            "5.OPTIONS",
+            "6.PRACTICE VS AI",
+            "7.QUIT",
+        ];
+        let mut items = Vec::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let y = 7 + i * 2;
+            win.buf.draw_text(entry, 40, y, BrightCyan);
+            items.push((40, y, entry.len()));
+        }
+        match win.wait_for_menu(1..=7, &items)? {
             Some(1) => {
                 if let Some(player_config) = choose_wizard(win, &config.player)? {
                     config.player = Some(player_config);
@@ -101,33 +187,82 @@ async fn main_menu(win: &mut Window) -> Result<(), ChaosError> {
             }
             Some(2) => {
                 if let Some(player) = use_or_obtain_player(win, &mut config)? {
-                    if let Some(addr) = host_game(win, &config.last_host)? {
+                    if let Some(addr) = host_game(win, &config.last_host, &mut config.host_options)? {
                         config.last_host = Some(addr.clone());
                         config.save()?;
-                        start_game(win, player, Some(&addr), &addr).await?;
+                        let bind_addr = addr.bind_address(config.host_options.all_interfaces);
+                        start_game(
+                            win,
+                            player,
+                            Some(&bind_addr),
+                            &addr,
+                            &config.options,
+                            &config.host_options,
+                            &mut config.campaign,
+                            0,
+                        )
+                        .await?;
+                        config.save()?;
                     }
                 }
             }
             Some(3) => {
                 if let Some(player) = use_or_obtain_player(win, &mut config)? {
-                    if let Some(addr) = join_game(win, &config.last_host)? {
-                        config.last_host = Some(addr.clone());
+                    if let Some(addr) = join_game(win, &config.recent_hosts)? {
+                        config.add_recent_host(addr.clone());
                         config.save()?;
-                        start_game(win, player, None, &addr).await?;
+                        start_game(
+                            win,
+                            player,
+                            None,
+                            &addr,
+                            &config.options,
+                            &config.host_options,
+                            &mut config.campaign,
+                            0,
+                        )
+                        .await?;
                     }
                 }
             }
             Some(4) => about_screen(win)?,
-            Some(5) | None => win.quit()?,
+            Some(5) => {
+                options(win, &mut config.options)?;
+                config.save()?;
+            }
+            Some(6) => {
+                if let Some(player) = use_or_obtain_player(win, &mut config)? {
+                    if let Some(bots) = practice_game(win)? {
+                        let addr = NetAddress::default();
+                        let options = Options {
+                            auto_ready: true,
+                            ..config.options.clone()
+                        };
+                        let host_options = HostOptions {
+                            campaign: false,
+                            ..config.host_options.clone()
+                        };
+                        start_game(
+                            win,
+                            player,
+                            Some(&addr),
+                            &addr,
+                            &options,
+                            &host_options,
+                            &mut Vec::new(),
+                            bots,
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Some(7) | None => win.quit()?,
             _ => unreachable!("Invalid menu option"),
         }
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), ChaosError> {
-    let args = Cli::parse();
-    let win = &mut Window::new()?;
+async fn run(win: &mut Window, args: Cli) -> Result<(), ChaosError> {
     if args.debug_1 {
         let player = Player {
             name: "Gandalf".to_string(),
@@ -135,7 +270,22 @@ async fn main() -> Result<(), ChaosError> {
             color: WizardColor::BrightWhite,
         };
         let addr = NetAddress::default();
-        start_game(win, player, Some(&addr), &addr).await?;
+        let options = Options {
+            debug_line_of_sight: true,
+            auto_ready: args.auto_ready,
+            ..Default::default()
+        };
+        start_game(
+            win,
+            player,
+            Some(&addr),
+            &addr,
+            &options,
+            &HostOptions::default(),
+            &mut Vec::new(),
+            0,
+        )
+        .await?;
     } else if args.debug_2 {
         let player = Player {
             name: "Julian".to_string(),
@@ -143,20 +293,28 @@ async fn main() -> Result<(), ChaosError> {
             color: WizardColor::BrightYellow,
         };
         let addr = NetAddress::default();
-        start_game(win, player, None, &addr).await?;
+        let options = Options {
+            debug_line_of_sight: true,
+            auto_ready: args.auto_ready,
+            ..Default::default()
+        };
+        start_game(
+            win,
+            player,
+            None,
+            &addr,
+            &options,
+            &HostOptions::default(),
+            &mut Vec::new(),
+            0,
+        )
+        .await?;
     } else {
-        win.buf.clear();
-        win.buf.draw_buffer(&LOGO, 39, 2);
-        win.buf.center_text("THE BATTLE OF WIZARDS", 7, BrightMagenta);
-        win.buf.center_text("© Games Workshop 1985", 11, BrightYellow);
-        win.buf.center_text("By Julian Gollop", 13, BrightRed);
-        win.buf.center_text("*Raised From The Dead", 16, BrightGreen);
-        win.buf.center_text("By Andrew Herbert", 18, BrightRed);
-        win.buf.draw_text(env!("CARGO_PKG_VERSION"), 0, 22, BrightRed);
-        win.buf.draw_buffer(&SNAKE, 64, 9);
-        win.wait_for_any_key()?;
+        if !args.skip_intro {
+            intro_screen(win)?;
+        }
         loop {
-            if let Err(err) = main_menu(win).await {
+            if let Err(err) = main_menu(win, args.auto_ready).await {
                 if let ChaosError::Quit = err {
                     break;
                 } else {
@@ -167,3 +325,72 @@ async fn main() -> Result<(), ChaosError> {
     }
     Ok(())
 }
+
+async fn headless() -> Result<(), ChaosError> {
+    let player = Player {
+        name: "Bot".to_string(),
+        character: WizardCharacter::Gowin,
+        color: WizardColor::BrightGreen,
+    };
+    let mut client = ChaosClient::new(&NetAddress::default()).await?;
+    loop {
+        client.send(Message::Join(player.clone()))?;
+        client.send(Message::Ready(true))?;
+        if !drive(&mut client, |_, _| None).await? {
+            return Ok(());
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), ChaosError> {
+    let args = Cli::parse();
+    data::spells::load_spellbook();
+    if args.headless {
+        return headless().await;
+    }
+    let mut win = Window::new()?;
+    if let Some(ref title) = args.title {
+        win.set_title(title);
+    }
+    let result = run(&mut win, args).await;
+    win.close();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn random_bots_play_full_matches_to_their_results() {
+        for bots in [2, 4] {
+            let port = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let addr = NetAddress {
+                host: "127.0.0.1".to_string(),
+                port: port as usize,
+            };
+            let server = ChaosServer::new(&addr, HostOptions::default(), Vec::new()).await.unwrap();
+            let mut observer = ChaosClient::new(&addr).await.unwrap();
+            for index in 0..bots {
+                tokio::spawn(practice_bot(addr.clone(), index, bots));
+            }
+            loop {
+                let (_, msg) = timeout(Duration::from_secs(30), observer.next())
+                    .await
+                    .expect("match stalled")
+                    .expect("server dropped the connection");
+                if let Message::Results(players) = msg {
+                    assert!(!players.is_empty());
+                    break;
+                }
+            }
+            server.shutdown().await.unwrap();
+        }
+    }
+}
@@ -1,4 +1,5 @@
 mod client;
+mod driver;
 mod error;
 mod server;
 use crate::config::Player;
@@ -7,15 +8,17 @@ use crate::data::spells::Spell;
 use crate::data::stats::WizardStats;
 use crate::data::wizard::{GameWizard, Wizard};
 use crate::gfx::color::Color;
-pub use client::ChaosClient;
+pub use client::{ChaosClient, ClientSender};
+pub use driver::{dispatch, drive, RandomPlayer};
 pub use error::NetworkError;
 use serde::{Deserialize, Serialize};
 pub use server::chaos_server::ChaosServer;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
 use tokio::net::tcp::{ReadHalf, WriteHalf};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ServerMessage {
     OutgoingMessage { id: u32, msg: Message },
     ClientMessage { msg: Message },
@@ -30,11 +33,13 @@ pub enum ClientMessage {
     Latency(u128),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Message {
     Join(Player),
     Leave(u32),
     Ready(bool),
+    Responsive(bool),
+    ClaimPosition(usize),
     Start(Wizard),
     AddWizard {
         wizard: GameWizard,
@@ -72,7 +77,9 @@ pub enum Message {
     NoPossibleMoves,
     BuffWizard(WizardStats),
     DeBuffWizard(WizardStats),
-    ChooseSpell,
+    ChooseSpell {
+        only_disbelieve: bool,
+    },
     ChosenSpell(Option<(u32, bool)>),
     WaitingForOtherPlayers(u32),
     CastSpell {
@@ -88,6 +95,7 @@ pub enum Message {
         points: u8,
         tiles: Vec<(u8, u8)>,
     },
+    MovementStopped,
     UndeadCannotBeAttacked,
     FailedAttack {
         x: u8,
@@ -169,11 +177,14 @@ pub enum Message {
         y: u8,
     },
     NoLineOfSight,
+    TargetNoLongerValid,
     ChoosePiece(Vec<(u8, u8)>),
     ChooseTarget(Vec<(u8, u8)>),
     ChooseCombat(Vec<(u8, u8)>),
     EngagedInCombat(Vec<(u8, u8)>),
     ChooseRangedCombat {
+        x: u8,
+        y: u8,
         range: u8,
         tiles: Vec<(u8, u8)>,
     },
@@ -182,6 +193,7 @@ pub enum Message {
     SpellFails,
     Turn,
     TurnEnd,
+    SpellPhaseEnd,
     MoveWizard {
         x: u8,
         y: u8,
@@ -195,10 +207,18 @@ pub enum Message {
     AskForDismount,
     Dismount(Option<bool>),
     Results(Vec<Player>),
+    Pause(bool),
+    BannedSpells(Vec<String>),
+    CreationLimitReached,
+    TurnOrder(Vec<u32>),
+    Stranded(bool),
+    StateChecksum(u64),
+    SyncAnimations,
+    AnimationDone,
     Shutdown,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SendMsg {
     MessageToAll { id: Option<u32>, msg: Message },
     MessageToId { to: u32, id: u32, msg: Message },
@@ -214,20 +234,74 @@ pub enum RecieveMsg {
     Latency { id: u32, delta: u128 },
 }
 
+pub struct NetStats {
+    messages_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+impl NetStats {
+    const fn new() -> Self {
+        Self {
+            messages_sent: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            messages_received: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+        }
+    }
+
+    fn add_sent(&self, bytes: usize) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn add_received(&self, bytes: usize) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn sent(&self) -> (u64, u64) {
+        (
+            self.messages_sent.load(Ordering::Relaxed),
+            self.bytes_sent.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn received(&self) -> (u64, u64) {
+        (
+            self.messages_received.load(Ordering::Relaxed),
+            self.bytes_received.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn reset(&self) {
+        self.messages_sent.store(0, Ordering::Relaxed);
+        self.bytes_sent.store(0, Ordering::Relaxed);
+        self.messages_received.store(0, Ordering::Relaxed);
+        self.bytes_received.store(0, Ordering::Relaxed);
+    }
+}
+
+pub static CLIENT_STATS: NetStats = NetStats::new();
+pub static SERVER_STATS: NetStats = NetStats::new();
+
 pub struct MessageReader<'a> {
     reader: BufReader<&'a mut ReadHalf<'a>>,
+    stats: &'static NetStats,
 }
 
 impl<'a> MessageReader<'a> {
-    pub fn new(reader: &'a mut ReadHalf<'a>) -> Self {
+    pub fn new(reader: &'a mut ReadHalf<'a>, stats: &'static NetStats) -> Self {
         let reader = BufReader::new(reader);
-        Self { reader }
+        Self { reader, stats }
     }
 
     pub async fn read(&mut self) -> Result<ServerMessage, NetworkError> {
         let len = self.reader.read_u32().await?;
         let mut buf = vec![0; len as usize];
         self.reader.read_exact(&mut buf).await?;
+        self.stats.add_received(buf.len() + 4);
         let msg = bincode::deserialize(&buf)?;
         Ok(msg)
     }
@@ -235,12 +309,13 @@ impl<'a> MessageReader<'a> {
 
 pub struct MessageWriter<'a> {
     writer: BufWriter<&'a mut WriteHalf<'a>>,
+    stats: &'static NetStats,
 }
 
 impl<'a> MessageWriter<'a> {
-    pub fn new(writer: &'a mut WriteHalf<'a>) -> Self {
+    pub fn new(writer: &'a mut WriteHalf<'a>, stats: &'static NetStats) -> Self {
         let writer = BufWriter::new(writer);
-        Self { writer }
+        Self { writer, stats }
     }
 
     pub async fn write(&mut self, msg: ServerMessage) -> Result<(), NetworkError> {
@@ -248,6 +323,7 @@ impl<'a> MessageWriter<'a> {
         self.writer.write_u32(buf.len() as u32).await?;
         self.writer.write_all(&buf).await?;
         self.writer.flush().await?;
+        self.stats.add_sent(buf.len() + 4);
         Ok(())
     }
 
@@ -269,3 +345,238 @@ impl<'a> MessageWriter<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::spells::{create_spells, SpellKind};
+    use crate::data::wizard::LobbyWizard;
+    use rand::thread_rng;
+
+    fn player() -> Player {
+        Player {
+            name: "GANDALF".to_string(),
+            character: 5.try_into().unwrap(),
+            color: 3.try_into().unwrap(),
+        }
+    }
+
+    fn wizard() -> Wizard {
+        let lobby = LobbyWizard {
+            player: player(),
+            id: 3,
+            ready: true,
+            responsive: true,
+            position: Some(1),
+        };
+        Wizard::new(lobby, 4, 20, &[], None, &mut thread_rng())
+    }
+
+    fn creation() -> GameCreation {
+        let stats = create_spells(40, &[], &mut thread_rng())
+            .into_iter()
+            .find_map(|spell| match spell.kind {
+                SpellKind::Creation(stats) => Some(stats),
+                _ => None,
+            })
+            .expect("creation");
+        GameCreation::new(3, stats)
+    }
+
+    fn every_message() -> Vec<Message> {
+        let wizard = wizard();
+        let game_wizard = GameWizard::from(&wizard);
+        let spell = wizard.spells[1].clone();
+        let stats = wizard.stats.clone();
+        let tiles = vec![(0, 0), (14, 9)];
+        vec![
+            Message::Join(player()),
+            Message::Leave(3),
+            Message::Ready(true),
+            Message::Responsive(false),
+            Message::ClaimPosition(2),
+            Message::Start(wizard),
+            Message::AddWizard {
+                wizard: game_wizard,
+                x: 1,
+                y: 2,
+            },
+            Message::Disbelieve {
+                x: 1,
+                y: 2,
+                success: true,
+            },
+            Message::CreationSpell {
+                x: 1,
+                y: 2,
+                creation: Some(creation()),
+            },
+            Message::CreationSpell {
+                x: 1,
+                y: 2,
+                creation: None,
+            },
+            Message::CastFire {
+                x: 1,
+                y: 2,
+                fire: Some(creation()),
+            },
+            Message::CastBlob { x: 1, y: 2, blob: None },
+            Message::SendSpell { spell },
+            Message::NewSpell { x: 1, y: 2 },
+            Message::ShadowWoodInfo,
+            Message::NoPossibleMoves,
+            Message::BuffWizard(stats.clone()),
+            Message::DeBuffWizard(stats),
+            Message::ChooseSpell { only_disbelieve: true },
+            Message::ChosenSpell(Some((4, true))),
+            Message::ChosenSpell(None),
+            Message::WaitingForOtherPlayers(2),
+            Message::CastSpell {
+                spell_name: "LIGHTNING".to_string(),
+                range: 8,
+            },
+            Message::MovementRange {
+                range: 3,
+                flying: true,
+                tiles: tiles.clone(),
+            },
+            Message::MovementPoints {
+                points: 2,
+                tiles: tiles.clone(),
+            },
+            Message::MovementStopped,
+            Message::UndeadCannotBeAttacked,
+            Message::FailedAttack { x: 1, y: 2 },
+            Message::SuccessfulAttack {
+                x: 1,
+                y: 2,
+                corpse: true,
+            },
+            Message::FailedRangedAttack {
+                sx: 1,
+                sy: 2,
+                dx: 3,
+                dy: 4,
+                color: Color::BrightRed,
+            },
+            Message::SuccessfulRangedAttack {
+                sx: 1,
+                sy: 2,
+                dx: 3,
+                dy: 4,
+                corpse: false,
+                color: Color::BrightCyan,
+            },
+            Message::FailedDragonRangedAttack {
+                sx: 1,
+                sy: 2,
+                dx: 3,
+                dy: 4,
+            },
+            Message::SuccessfulDragonRangedAttack {
+                sx: 1,
+                sy: 2,
+                dx: 3,
+                dy: 4,
+            },
+            Message::Subversion {
+                x: 1,
+                y: 2,
+                success: false,
+            },
+            Message::RaiseDead {
+                x: 1,
+                y: 2,
+                success: true,
+            },
+            Message::MagicBolt {
+                x: 1,
+                y: 2,
+                success: true,
+            },
+            Message::Lightning {
+                x: 1,
+                y: 2,
+                success: false,
+            },
+            Message::ShelterDisappears { x: 1, y: 2 },
+            Message::MagicalAttack {
+                x: 1,
+                y: 2,
+                success: true,
+            },
+            Message::SpawnFire {
+                x: 1,
+                y: 2,
+                fire: Some(creation()),
+            },
+            Message::SpawnBlob {
+                x: 1,
+                y: 2,
+                blob: Some(creation()),
+            },
+            Message::RemoveSpawn { x: 1, y: 2 },
+            Message::NoLineOfSight,
+            Message::TargetNoLongerValid,
+            Message::ChoosePiece(tiles.clone()),
+            Message::ChooseTarget(tiles.clone()),
+            Message::ChooseCombat(tiles.clone()),
+            Message::EngagedInCombat(tiles.clone()),
+            Message::ChooseRangedCombat {
+                x: 1,
+                y: 2,
+                range: 6,
+                tiles,
+            },
+            Message::ChosenTile(Some(1)),
+            Message::ChosenTile(None),
+            Message::SpellSucceeds(-4),
+            Message::SpellFails,
+            Message::Turn,
+            Message::TurnEnd,
+            Message::SpellPhaseEnd,
+            Message::MoveWizard { x: 1, y: 2 },
+            Message::MoveCreation {
+                sx: 1,
+                sy: 2,
+                dx: 3,
+                dy: 4,
+            },
+            Message::AskForDismount,
+            Message::Dismount(Some(false)),
+            Message::Results(vec![player()]),
+            Message::Pause(true),
+            Message::BannedSpells(vec!["LIGHTNING".to_string()]),
+            Message::CreationLimitReached,
+            Message::TurnOrder(vec![2, 0, 1]),
+            Message::Stranded(true),
+            Message::StateChecksum(u64::MAX),
+            Message::SyncAnimations,
+            Message::AnimationDone,
+            Message::Shutdown,
+        ]
+    }
+
+    fn round_trip(msg: &ServerMessage) -> ServerMessage {
+        let buf = bincode::serialize(msg).unwrap();
+        bincode::deserialize(&buf).unwrap()
+    }
+
+    #[test]
+    fn every_message_round_trips() {
+        for msg in every_message() {
+            let outgoing = ServerMessage::OutgoingMessage { id: 7, msg: msg.clone() };
+            assert_eq!(round_trip(&outgoing), outgoing);
+            let client = ServerMessage::ClientMessage { msg };
+            assert_eq!(round_trip(&client), client);
+        }
+    }
+
+    #[test]
+    fn pings_round_trip() {
+        for msg in [ServerMessage::Ping(u128::MAX), ServerMessage::Pong(12)] {
+            assert_eq!(round_trip(&msg), msg);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use super::{ClientMessage, Message, MessageReader, MessageWriter, NetworkError, ServerMessage};
+use super::{ClientMessage, Message, MessageReader, MessageWriter, NetworkError, ServerMessage, CLIENT_STATS};
 use crate::config::NetAddress;
 use tokio::net::TcpStream;
 use tokio::select;
@@ -11,8 +11,8 @@ async fn client_loop(
     mut rx: mpsc::Receiver<ClientMessage>,
 ) -> Result<(), NetworkError> {
     let (mut reader, mut writer) = stream.split();
-    let mut reader = MessageReader::new(&mut reader);
-    let mut writer = MessageWriter::new(&mut writer);
+    let mut reader = MessageReader::new(&mut reader, &CLIENT_STATS);
+    let mut writer = MessageWriter::new(&mut writer, &CLIENT_STATS);
     let mut interval = interval(Duration::from_secs(5));
     loop {
         select! {
@@ -67,9 +67,21 @@ pub struct ChaosClient {
     rx: mpsc::Receiver<ClientMessage>,
 }
 
+#[derive(Clone)]
+pub struct ClientSender {
+    tx: mpsc::Sender<ClientMessage>,
+}
+
+impl ClientSender {
+    pub fn send(&self, msg: Message) -> Result<(), NetworkError> {
+        self.tx.try_send(ClientMessage::OutgoingMessage { msg })?;
+        Ok(())
+    }
+}
+
 impl ChaosClient {
     pub async fn new(addr: &NetAddress) -> Result<Self, NetworkError> {
-        let addr = format!("{}:{}", addr.host, addr.port);
+        let addr = addr.to_string();
         let stream = TcpStream::connect(addr).await?;
         let (conn_tx, conn_rx) = mpsc::channel(64);
         let (send_tx, send_rx) = mpsc::channel(64);
@@ -85,6 +97,10 @@ impl ChaosClient {
         Ok(())
     }
 
+    pub fn sender(&self) -> ClientSender {
+        ClientSender { tx: self.tx.clone() }
+    }
+
     pub fn recv(&mut self) -> Result<Option<(u32, Message)>, NetworkError> {
         match self.rx.try_recv() {
             Ok(ClientMessage::IncomingMessage { msg, id }) => Ok(Some((id, msg))),
@@ -96,6 +112,18 @@ impl ChaosClient {
         }
     }
 
+    pub async fn next(&mut self) -> Result<(u32, Message), NetworkError> {
+        loop {
+            match self.rx.recv().await {
+                Some(ClientMessage::IncomingMessage { msg, id }) => return Ok((id, msg)),
+                Some(ClientMessage::Disconnect) => return Err(NetworkError::Disconnected),
+                Some(ClientMessage::Latency(_)) => continue,
+                None => return Err(NetworkError::GenericError),
+                _ => unreachable!("unexpected message"),
+            }
+        }
+    }
+
     pub fn disconnect(self) -> Result<(), NetworkError> {
         self.tx.try_send(ClientMessage::Disconnect)?;
         Ok(())
@@ -0,0 +1,147 @@
+use super::{ChaosClient, Message, NetworkError};
+use rand::{thread_rng, Rng};
+
+impl Message {
+    pub fn pass_reply(&self) -> Option<Message> {
+        match self {
+            Message::ChooseSpell { .. } => Some(Message::ChosenSpell(None)),
+            Message::ChoosePiece(_)
+            | Message::ChooseTarget(_)
+            | Message::EngagedInCombat(_)
+            | Message::ChooseRangedCombat { .. }
+            | Message::MovementRange { .. }
+            | Message::MovementPoints { .. } => Some(Message::ChosenTile(None)),
+            Message::AskForDismount => Some(Message::Dismount(None)),
+            Message::SyncAnimations => Some(Message::AnimationDone),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RandomPlayer {
+    spells: usize,
+}
+
+impl RandomPlayer {
+    pub fn reply(&mut self, msg: &Message) -> Option<Message> {
+        let mut rng = thread_rng();
+        match msg {
+            Message::Start(wizard) => {
+                self.spells = wizard.spells.len();
+                None
+            }
+            Message::SendSpell { .. } => {
+                self.spells += 1;
+                None
+            }
+            Message::ChooseSpell { only_disbelieve: false } if self.spells > 1 => {
+                let index = rng.gen_range(1..self.spells);
+                self.spells -= 1;
+                Some(Message::ChosenSpell(Some((index as u32, false))))
+            }
+            Message::AskForDismount => Some(Message::Dismount(Some(rng.gen_bool(0.5)))),
+            Message::ChoosePiece(tiles)
+            | Message::ChooseTarget(tiles)
+            | Message::EngagedInCombat(tiles)
+            | Message::ChooseRangedCombat { tiles, .. }
+            | Message::MovementRange { tiles, .. }
+            | Message::MovementPoints { tiles, .. } => {
+                let index = rng.gen_range(0..=tiles.len());
+                Some(Message::ChosenTile((index < tiles.len()).then_some(index as u8)))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn dispatch<E: From<NetworkError>>(
+    client: &mut ChaosClient,
+    id: u32,
+    msg: Message,
+    respond: impl FnOnce(u32, Message) -> Result<Option<Message>, E>,
+) -> Result<Option<Message>, E> {
+    match msg {
+        Message::Shutdown | Message::Results(_) => Ok(Some(msg)),
+        msg => {
+            let pass = msg.pass_reply();
+            if let Some(reply) = respond(id, msg)?.or(pass) {
+                client.send(reply)?;
+            }
+            Ok(None)
+        }
+    }
+}
+
+pub async fn drive(
+    client: &mut ChaosClient,
+    mut respond: impl FnMut(u32, &Message) -> Option<Message>,
+) -> Result<bool, NetworkError> {
+    loop {
+        let (id, msg) = client.next().await?;
+        match dispatch(client, id, msg, |id, msg| Ok::<_, NetworkError>(respond(id, &msg)))? {
+            Some(Message::Results(_)) => return Ok(true),
+            Some(_) => return Ok(false),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{HostOptions, NetAddress, Player};
+    use crate::net::ChaosServer;
+    use tokio::time::{timeout, Duration};
+
+    #[tokio::test]
+    async fn passive_headless_clients_play_a_match_to_its_results() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr = NetAddress {
+            host: "127.0.0.1".to_string(),
+            port: port as usize,
+        };
+        let options = HostOptions {
+            stalemate_rounds: 1,
+            ..HostOptions::default()
+        };
+        let server = ChaosServer::new(&addr, options, Vec::new()).await.unwrap();
+        let bots = (0..2)
+            .map(|id| {
+                let addr = addr.clone();
+                tokio::spawn(async move {
+                    let mut client = ChaosClient::new(&addr).await?;
+                    client.send(Message::Join(Player {
+                        name: format!("BOT {id}"),
+                        character: (id as isize).try_into().expect("character"),
+                        color: (id as isize + 1).try_into().expect("color"),
+                    }))?;
+                    client.send(Message::Ready(true))?;
+                    let mut asked = 0;
+                    let finished = drive(&mut client, |_, msg| {
+                        if matches!(msg, Message::ChooseSpell { .. }) {
+                            asked += 1;
+                        }
+                        None
+                    })
+                    .await?;
+                    Ok::<_, NetworkError>((finished, asked))
+                })
+            })
+            .collect::<Vec<_>>();
+        for bot in bots {
+            let (finished, asked) = timeout(Duration::from_secs(10), bot)
+                .await
+                .expect("match never finished")
+                .unwrap()
+                .unwrap();
+            assert!(finished);
+            assert!(asked > 0);
+        }
+        server.shutdown().await.unwrap();
+    }
+}
@@ -3,7 +3,7 @@ use crate::data::arena::ArenaError;
 use std::net::AddrParseError;
 use std::time::SystemTimeError;
 use std::{error, fmt, io};
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::mpsc;
 
 #[derive(Debug)]
 pub enum NetworkError {
@@ -30,18 +30,6 @@ impl From<mpsc::error::SendError<SendMsg>> for NetworkError {
     }
 }
 
-impl From<broadcast::error::SendError<SendMsg>> for NetworkError {
-    fn from(_err: broadcast::error::SendError<SendMsg>) -> Self {
-        NetworkError::GenericError
-    }
-}
-
-impl From<broadcast::error::RecvError> for NetworkError {
-    fn from(_err: broadcast::error::RecvError) -> Self {
-        NetworkError::GenericError
-    }
-}
-
 impl From<mpsc::error::SendError<ClientMessage>> for NetworkError {
     fn from(_err: mpsc::error::SendError<ClientMessage>) -> Self {
         NetworkError::GenericError
@@ -1,80 +1,167 @@
 pub mod chaos_server;
+mod crash_dump;
 mod game_logic;
 mod sender;
 mod server_state;
-use super::{MessageReader, MessageWriter, NetworkError, RecieveMsg, SendMsg, ServerMessage};
+use super::{Message, MessageReader, MessageWriter, NetworkError, RecieveMsg, SendMsg, ServerMessage, SERVER_STATS};
 use crate::config::NetAddress;
+use std::collections::{HashMap, VecDeque};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::select;
-use tokio::sync::{broadcast, mpsc};
-use tokio::time::{interval, Duration};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep, Duration, Instant};
+
+const MESSAGE_BURST: f32 = 32.0;
+const MESSAGES_PER_SECOND: f32 = 16.0;
+
+struct RateLimiter {
+    tokens: f32,
+    last: Instant,
+    limited: bool,
+}
+
+impl RateLimiter {
+    fn new() -> Self {
+        Self {
+            tokens: MESSAGE_BURST,
+            last: Instant::now(),
+            limited: false,
+        }
+    }
+
+    fn delay(&mut self, id: u32) -> Duration {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f32() * MESSAGES_PER_SECOND;
+        self.tokens = (self.tokens + refill).min(MESSAGE_BURST);
+        self.last = now;
+        if self.tokens >= MESSAGE_BURST {
+            self.limited = false;
+        }
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            if !self.limited {
+                eprintln!("Connection {id} is sending too many messages, slowing down");
+                self.limited = true;
+            }
+            Duration::from_secs_f32(-self.tokens / MESSAGES_PER_SECOND)
+        }
+    }
+}
+
+async fn read_messages(mut reader: MessageReader<'_>, read_tx: mpsc::Sender<Result<ServerMessage, NetworkError>>, id: u32) {
+    let mut limiter = RateLimiter::new();
+    loop {
+        let read = reader.read().await;
+        let failed = read.is_err();
+        if !failed {
+            let delay = limiter.delay(id);
+            if !delay.is_zero() {
+                sleep(delay).await;
+            }
+        }
+        if read_tx.send(read).await.is_err() || failed {
+            return;
+        }
+    }
+}
 
 pub async fn connection_loop(
     mut stream: TcpStream,
     tx: mpsc::Sender<RecieveMsg>,
-    mut rx: broadcast::Receiver<SendMsg>,
+    mut rx: mpsc::Receiver<ServerMessage>,
     id: u32,
+    timeout: Option<Duration>,
 ) -> Result<(), NetworkError> {
     let (mut reader, mut writer) = stream.split();
     tx.send(RecieveMsg::Connected { id }).await?;
-    let mut reader = MessageReader::new(&mut reader);
-    let mut writer = MessageWriter::new(&mut writer);
-    let mut interval = interval(Duration::from_secs(5));
-    loop {
-        select! {
-            read = reader.read() => {
-                match read {
-                    Err(_) => {
-                        tx.send(RecieveMsg::Disconnected { id }).await?;
-                        return Ok(());
-                    }
-                    Ok(ServerMessage::ClientMessage {
-                        msg,
-                    }) => {
-                        tx.send(RecieveMsg::Message { id, msg }).await?;
-                    }
-                    Ok(ServerMessage::Ping(time)) => {
-                        writer.pong(time).await?;
-                    }
-                    Ok(ServerMessage::Pong(delta)) => {
-                        tx.send(RecieveMsg::Latency { id, delta }).await?;
-                    }
-                    _ => unreachable!(),
-                }
-            }
-            rx = rx.recv() => {
-                let rx = rx?;
-                match rx {
-                    SendMsg::MessageToAll { id: msg_id, msg } => {
-                        writer.write(ServerMessage::OutgoingMessage {
-                            id: msg_id.unwrap_or(id),
+    let (read_tx, mut read_rx) = mpsc::channel(1);
+    let reading = read_messages(MessageReader::new(&mut reader, &SERVER_STATS), read_tx, id);
+    let serving = async {
+        let mut writer = MessageWriter::new(&mut writer, &SERVER_STATS);
+        let mut interval = interval(Duration::from_secs(5));
+        let mut last_seen = Instant::now();
+        loop {
+            select! {
+                read = read_rx.recv() => {
+                    match read {
+                        Some(Ok(ServerMessage::ClientMessage {
                             msg,
-                        }).await?;
-                    }
-                    SendMsg::MessageToId { to, id: from, msg } => {
-                        if to == id {
-                            writer.write(ServerMessage::OutgoingMessage {
-                                id: from,
-                                msg,
-                            }).await?;
+                        })) => {
+                            last_seen = Instant::now();
+                            tx.send(RecieveMsg::Message { id, msg }).await?;
+                        }
+                        Some(Ok(ServerMessage::Ping(time))) => {
+                            last_seen = Instant::now();
+                            writer.pong(time).await?;
+                        }
+                        Some(Ok(ServerMessage::Pong(delta))) => {
+                            last_seen = Instant::now();
+                            tx.send(RecieveMsg::Latency { id, delta }).await?;
+                        }
+                        Some(Ok(_)) => {
+                            eprintln!("Connection {id} sent a message only the server should send, disconnecting");
+                            tx.send(RecieveMsg::Disconnected { id }).await?;
+                            writer.shutdown().await?;
+                            return Err(NetworkError::GenericError);
+                        }
+                        Some(Err(_)) | None => {
+                            tx.send(RecieveMsg::Disconnected { id }).await?;
+                            return Ok(());
                         }
                     }
-                    SendMsg::MessageToAllExcept { id: from, msg } => {
-                        if from != id {
-                            writer.write(ServerMessage::OutgoingMessage {
-                                id: from,
-                                msg,
-                            }).await?;
+                }
+                msg = rx.recv() => {
+                    match msg {
+                        Some(msg) => {
+                            writer.write(msg).await?;
+                        }
+                        None => {
+                            writer.shutdown().await?;
+                            return Ok(());
                         }
                     }
-                    SendMsg::Shutdown => {
+                }
+                _ = interval.tick() => {
+                    if timeout.is_some_and(|timeout| last_seen.elapsed() > timeout) {
+                        tx.send(RecieveMsg::Disconnected { id }).await?;
                         writer.shutdown().await?;
                         return Ok(());
                     }
+                    writer.ping().await?;
                 }
             }
-            _ = interval.tick() => {
-                writer.ping().await?;
+        }
+    };
+    tokio::pin!(reading, serving);
+    select! {
+        result = &mut serving => result,
+        () = &mut reading => serving.await,
+    }
+}
+
+// A connection whose queue fills up is disconnected rather than resynced. Dropping its
+// sender lets connection_loop flush what is queued and close the socket, and game
+// logic hears a Disconnected like any other, so the player is skipped from then on.
+fn send_to_connection(
+    connections: &mut HashMap<u32, mpsc::Sender<ServerMessage>>,
+    dropped: &mut VecDeque<u32>,
+    to: u32,
+    id: u32,
+    msg: Message,
+) {
+    if let Some(tx) = connections.get(&to) {
+        match tx.try_send(ServerMessage::OutgoingMessage { id, msg }) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                eprintln!("Connection {to} is not keeping up with outgoing messages, disconnecting");
+                connections.remove(&to);
+                dropped.push_back(to);
+            }
+            Err(TrySendError::Closed(_)) => {
+                connections.remove(&to);
             }
         }
     }
@@ -83,33 +170,229 @@ pub async fn connection_loop(
 async fn server_loop(
     listener: TcpListener,
     conn_tx: mpsc::Sender<RecieveMsg>,
-    broad_tx: broadcast::Sender<SendMsg>,
     mut rx: mpsc::Receiver<SendMsg>,
+    timeout: Option<Duration>,
 ) -> Result<(), NetworkError> {
+    let mut connections = HashMap::new();
+    let mut dropped = VecDeque::new();
     let mut id = 0;
     loop {
         select! {
+            Ok(permit) = conn_tx.reserve(), if !dropped.is_empty() => {
+                if let Some(id) = dropped.pop_front() {
+                    permit.send(RecieveMsg::Disconnected { id });
+                }
+            }
             Ok((stream, _addr)) = listener.accept() => {
-                tokio::spawn(connection_loop(stream, conn_tx.clone(), broad_tx.subscribe(), id));
+                let (tx, conn_rx) = mpsc::channel(256);
+                connections.insert(id, tx);
+                tokio::spawn(connection_loop(stream, conn_tx.clone(), conn_rx, id, timeout));
                 id += 1;
             }
-            Some(msg) = rx.recv() => {
-                if let SendMsg::Shutdown = msg {
-                    broad_tx.send(msg)?;
-                    return Ok(());
+            msg = rx.recv() => {
+                match msg {
+                    Some(SendMsg::MessageToAll { id, msg }) => {
+                        let ids = connections.keys().copied().collect::<Vec<_>>();
+                        for to in ids {
+                            send_to_connection(&mut connections, &mut dropped, to, id.unwrap_or(to), msg.clone());
+                        }
+                    }
+                    Some(SendMsg::MessageToId { to, id, msg }) => {
+                        send_to_connection(&mut connections, &mut dropped, to, id, msg);
+                    }
+                    Some(SendMsg::MessageToAllExcept { id, msg }) => {
+                        let ids = connections.keys().copied().filter(|to| *to != id).collect::<Vec<_>>();
+                        for to in ids {
+                            send_to_connection(&mut connections, &mut dropped, to, id, msg.clone());
+                        }
+                    }
+                    Some(SendMsg::Shutdown) | None => return Ok(()),
                 }
-                broad_tx.send(msg)?;
             }
         }
     }
 }
 
-pub async fn spawn_server(addr: &NetAddress) -> Result<(mpsc::Sender<SendMsg>, mpsc::Receiver<RecieveMsg>), NetworkError> {
+pub async fn spawn_server(
+    addr: &NetAddress,
+    timeout: Option<Duration>,
+) -> Result<
+    (
+        mpsc::Sender<SendMsg>,
+        mpsc::Receiver<RecieveMsg>,
+        JoinHandle<Result<(), NetworkError>>,
+    ),
+    NetworkError,
+> {
     let (tx, rx) = mpsc::channel(64);
     let (conn_tx, conn_rx) = mpsc::channel(64);
-    let (broad_tx, _broad_rx) = broadcast::channel(64);
-    let addr = format!("{}:{}", addr.host, addr.port);
+    let addr = addr.to_string();
     let listener = TcpListener::bind(addr).await?;
-    tokio::spawn(server_loop(listener, conn_tx, broad_tx, rx));
-    Ok((tx, conn_rx))
+    let handle = tokio::spawn(server_loop(listener, conn_tx, rx, timeout));
+    Ok((tx, conn_rx, handle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::error::TryRecvError;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn slow_reader_is_dropped_without_blocking_the_server_loop() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel(64);
+        let (conn_tx, mut conn_rx) = mpsc::channel(1);
+        let game_logic_busy = conn_tx.clone();
+        let server = tokio::spawn(server_loop(listener, conn_tx, rx, None));
+        let _slow_reader = TcpStream::connect(addr).await.unwrap();
+        assert!(matches!(conn_rx.recv().await, Some(RecieveMsg::Connected { id: 0 })));
+        game_logic_busy.send(RecieveMsg::Latency { id: 0, delta: 0 }).await.unwrap();
+        let msg = Message::BannedSpells(vec!["X".repeat(16 * 1024)]);
+        timeout(Duration::from_secs(10), async {
+            for _ in 0..2000 {
+                tx.send(SendMsg::MessageToAll {
+                    id: None,
+                    msg: msg.clone(),
+                })
+                .await
+                .unwrap();
+            }
+        })
+        .await
+        .expect("server loop blocked on a full receive channel");
+        assert!(matches!(conn_rx.recv().await, Some(RecieveMsg::Latency { .. })));
+        assert!(matches!(conn_rx.recv().await, Some(RecieveMsg::Disconnected { id: 0 })));
+        server.abort();
+    }
+
+    #[test]
+    fn a_full_queue_disconnects_the_connection_and_closes_its_channel() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut connections = HashMap::from([(3, tx)]);
+        let mut dropped = VecDeque::new();
+        send_to_connection(&mut connections, &mut dropped, 3, 3, Message::Turn);
+        assert!(connections.contains_key(&3) && dropped.is_empty());
+        send_to_connection(&mut connections, &mut dropped, 3, 3, Message::TurnEnd);
+        assert!(!connections.contains_key(&3));
+        assert_eq!(dropped, [3]);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ServerMessage::OutgoingMessage { msg: Message::Turn, .. })
+        ));
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Disconnected)));
+    }
+
+    #[test]
+    fn a_closed_queue_is_forgotten_without_a_second_disconnect() {
+        let (tx, rx) = mpsc::channel(1);
+        drop(rx);
+        let mut connections = HashMap::from([(3, tx)]);
+        let mut dropped = VecDeque::new();
+        send_to_connection(&mut connections, &mut dropped, 3, 3, Message::Turn);
+        assert!(connections.is_empty() && dropped.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn silent_connection_is_disconnected_after_the_keepalive_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _silent = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let (tx, mut rx) = mpsc::channel(64);
+        let (_conn_tx, conn_rx) = mpsc::channel(64);
+        let started = Instant::now();
+        let connection = tokio::spawn(connection_loop(stream, tx, conn_rx, 0, Some(Duration::from_secs(20))));
+        assert!(matches!(rx.recv().await, Some(RecieveMsg::Connected { id: 0 })));
+        assert!(matches!(rx.recv().await, Some(RecieveMsg::Disconnected { id: 0 })));
+        assert!(started.elapsed() > Duration::from_secs(20));
+        assert!(connection.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_client_sending_a_server_message_is_disconnected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let (tx, mut rx) = mpsc::channel(64);
+        let (_conn_tx, conn_rx) = mpsc::channel(64);
+        let connection = tokio::spawn(connection_loop(stream, tx, conn_rx, 0, None));
+        let (_, mut writer) = client.split();
+        let mut writer = MessageWriter::new(&mut writer, &SERVER_STATS);
+        let msg = ServerMessage::OutgoingMessage {
+            id: 1,
+            msg: Message::Turn,
+        };
+        writer.write(msg).await.unwrap();
+        assert!(matches!(rx.recv().await, Some(RecieveMsg::Connected { id: 0 })));
+        assert!(matches!(rx.recv().await, Some(RecieveMsg::Disconnected { id: 0 })));
+        assert!(matches!(connection.await.unwrap(), Err(NetworkError::GenericError)));
+    }
+
+    #[tokio::test]
+    async fn a_flooding_client_is_slowed_without_stalling_its_writes_or_others() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, mut rx) = mpsc::channel(64);
+        let mut flooder = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let (flood_tx, flood_rx) = mpsc::channel(256);
+        tokio::spawn(connection_loop(stream, tx.clone(), flood_rx, 0, None));
+        let mut quiet = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let (_quiet_tx, quiet_rx) = mpsc::channel(256);
+        tokio::spawn(connection_loop(stream, tx, quiet_rx, 1, None));
+
+        let (mut reader, mut writer) = flooder.split();
+        let mut writer = MessageWriter::new(&mut writer, &SERVER_STATS);
+        for _ in 0..200 {
+            let msg = ServerMessage::ClientMessage {
+                msg: Message::Ready(true),
+            };
+            writer.write(msg).await.unwrap();
+        }
+        let mut flooded = 0;
+        while flooded < MESSAGE_BURST as usize {
+            if let Some(RecieveMsg::Message { id: 0, .. }) = rx.recv().await {
+                flooded += 1;
+            }
+        }
+        for _ in 0..256 {
+            let msg = ServerMessage::OutgoingMessage {
+                id: 0,
+                msg: Message::Turn,
+            };
+            flood_tx.try_send(msg).unwrap();
+        }
+        let mut reader = MessageReader::new(&mut reader, &SERVER_STATS);
+        timeout(Duration::from_secs(2), async {
+            let mut received = 0;
+            while received < 256 {
+                if let ServerMessage::OutgoingMessage { .. } = reader.read().await.unwrap() {
+                    received += 1;
+                }
+            }
+        })
+        .await
+        .expect("outgoing messages stalled behind the rate limit");
+
+        let (_, mut writer) = quiet.split();
+        let mut writer = MessageWriter::new(&mut writer, &SERVER_STATS);
+        let msg = ServerMessage::ClientMessage {
+            msg: Message::Ready(false),
+        };
+        writer.write(msg).await.unwrap();
+        let flooded = timeout(Duration::from_secs(1), async {
+            loop {
+                match rx.recv().await.unwrap() {
+                    RecieveMsg::Message { id: 1, .. } => return flooded,
+                    RecieveMsg::Message { id: 0, .. } => flooded += 1,
+                    _ => {}
+                }
+            }
+        })
+        .await
+        .expect("a quiet client was held up by the flood");
+        assert!(flooded < 200, "the flood was not rate limited");
+    }
 }
@@ -1,29 +1,90 @@
-use super::game_logic::GameLogic;
-use crate::config::NetAddress;
+use super::{crash_dump, game_logic::GameLogic};
+use crate::config::{CampaignWizard, HostOptions, NetAddress};
 use crate::error::ChaosError;
 use crate::net::{server::spawn_server, NetworkError};
+use std::sync::{Arc, Mutex};
 use tokio::sync::oneshot::{self, Sender};
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration};
 
 pub struct ChaosServer {
     quit_tx: Sender<()>,
+    handle: JoinHandle<Result<(), ChaosError>>,
+    listener: JoinHandle<Result<(), NetworkError>>,
+    campaign: Arc<Mutex<Vec<CampaignWizard>>>,
 }
 
 impl ChaosServer {
-    pub async fn new(addr: &NetAddress) -> Result<Self, NetworkError> {
+    pub async fn new(addr: &NetAddress, options: HostOptions, campaign: Vec<CampaignWizard>) -> Result<Self, NetworkError> {
+        crash_dump::install_hook();
         let (quit_tx, quit_rx) = oneshot::channel();
-        let (tx, rx) = spawn_server(addr).await?;
-        tokio::spawn(async move {
-            let mut game = GameLogic::new(rx, tx, quit_rx);
-            if let Some(wizards) = game.lobby_loop().await? {
+        let timeout = match options.keepalive_timeout {
+            0 => None,
+            secs => Some(Duration::from_secs(secs as u64)),
+        };
+        let (tx, rx, listener) = spawn_server(addr, timeout).await?;
+        let campaign = Arc::new(Mutex::new(campaign));
+        let game_campaign = campaign.clone();
+        let handle = tokio::spawn(crash_dump::scoped(async move {
+            let mut game = GameLogic::new(rx, tx, quit_rx, options, game_campaign);
+            while let Some(wizards) = game.lobby_loop().await? {
                 let winners = game.game_loop(wizards).await?;
                 game.end(winners).await?;
             }
             Ok::<(), ChaosError>(())
-        });
-        Ok(Self { quit_tx })
+        }));
+        Ok(Self {
+            quit_tx,
+            handle,
+            listener,
+            campaign,
+        })
     }
 
-    pub fn shutdown(self) -> Result<(), NetworkError> {
-        self.quit_tx.send(()).map_err(|_| NetworkError::Shutdown)
+    pub fn campaign(&self) -> Vec<CampaignWizard> {
+        self.campaign.lock().expect("campaign").clone()
+    }
+
+    pub async fn shutdown(mut self) -> Result<(), NetworkError> {
+        let sent = self.quit_tx.send(());
+        if timeout(Duration::from_secs(1), &mut self.handle).await.is_err() {
+            self.handle.abort();
+        }
+        if timeout(Duration::from_secs(1), &mut self.listener).await.is_err() {
+            self.listener.abort();
+            (&mut self.listener).await.ok();
+        }
+        sent.map_err(|_| NetworkError::Shutdown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpStream;
+
+    #[tokio::test]
+    async fn cancelling_a_hosted_lobby_frees_the_port_and_drops_players() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr = NetAddress {
+            host: "127.0.0.1".to_string(),
+            port: port as usize,
+        };
+        for _ in 0..5 {
+            let server = ChaosServer::new(&addr, HostOptions::default(), Vec::new()).await.unwrap();
+            let mut player = TcpStream::connect(addr.to_string()).await.unwrap();
+            server.shutdown().await.unwrap();
+            drop(std::net::TcpListener::bind(addr.to_string()).expect("port still held after shutdown"));
+            let mut buf = Vec::new();
+            timeout(Duration::from_secs(2), player.read_to_end(&mut buf))
+                .await
+                .expect("player left connected after shutdown")
+                .ok();
+        }
     }
 }
@@ -0,0 +1,133 @@
+use super::server_state::ServerState;
+use crate::net::Message;
+use directories::BaseDirs;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fs::{create_dir_all, File};
+use std::future::{poll_fn, Future};
+use std::io::Write;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::sync::{Mutex, Once};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LOG_LENGTH: usize = 64;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct CrashDump {
+    pub state: Option<ServerState>,
+    pub log: VecDeque<(u32, Message)>,
+}
+
+lazy_static! {
+    static ref CRASH_DUMP: Mutex<CrashDump> = Mutex::new(CrashDump::default());
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+thread_local! {
+    static IN_SERVER: Cell<bool> = const { Cell::new(false) };
+}
+
+struct ServerScope;
+
+impl ServerScope {
+    fn enter() -> Self {
+        IN_SERVER.set(true);
+        ServerScope
+    }
+}
+
+impl Drop for ServerScope {
+    fn drop(&mut self) {
+        IN_SERVER.set(false);
+    }
+}
+
+// only panics raised while polling the server's game future write a dump
+pub async fn scoped<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    poll_fn(|cx| {
+        let _scope = ServerScope::enter();
+        future.as_mut().poll(cx)
+    })
+    .await
+}
+
+fn in_server() -> bool {
+    IN_SERVER.try_with(Cell::get).unwrap_or(false)
+}
+
+pub fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+            if !in_server() {
+                return;
+            }
+            if let Ok(dump) = CRASH_DUMP.try_lock() {
+                if dump.state.is_some() {
+                    match write_dump(&dump) {
+                        Some(path) => eprintln!("Game state written to {}", path.display()),
+                        None => eprintln!("Unable to write game state"),
+                    }
+                }
+            }
+        }));
+    });
+}
+
+fn write_dump(dump: &CrashDump) -> Option<PathBuf> {
+    let bytes = bincode::serialize(dump).ok()?;
+    let base = BaseDirs::new()?;
+    let path = Path::new(base.config_dir()).join("Chaos");
+    create_dir_all(&path).ok()?;
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let path = path.join(format!("Crash-{secs}.bin"));
+    File::create(&path).ok()?.write_all(&bytes).ok()?;
+    Some(path)
+}
+
+pub fn record_state(state: &ServerState) {
+    if let Ok(mut dump) = CRASH_DUMP.lock() {
+        dump.state = Some(state.clone());
+    }
+}
+
+pub fn record_message(id: u32, msg: &Message) {
+    if let Ok(mut dump) = CRASH_DUMP.lock() {
+        if dump.log.len() == LOG_LENGTH {
+            dump.log.pop_front();
+        }
+        dump.log.push_back((id, msg.clone()));
+    }
+}
+
+pub fn clear() {
+    if let Ok(mut dump) = CRASH_DUMP.lock() {
+        *dump = CrashDump::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn only_the_scoped_future_counts_as_the_server() {
+        assert!(!in_server());
+        assert!(scoped(async { in_server() }).await);
+        assert!(!in_server());
+        let panicked = panic::catch_unwind(|| {
+            let mut future = pin!(scoped(async { panic!("game logic") }));
+            let waker = std::task::Waker::noop();
+            let _ = future.as_mut().poll(&mut std::task::Context::from_waker(waker));
+        });
+        assert!(panicked.is_err());
+        assert!(!in_server());
+    }
+}
@@ -1,53 +1,156 @@
-use super::{sender::Sender, server_state::ServerState};
-use crate::config::Player;
-use crate::data::arena::{Arena, Spawn};
+use super::{crash_dump, sender::Sender, server_state::ServerState};
+use crate::config::{CampaignWizard, HostOptions, Player};
+use crate::data::arena::{Arena, AttackVerdict, Spawn};
 use crate::data::creation::GameCreation;
 use crate::data::spells::{Spell, SpellKind};
 use crate::data::stats::{AttackBuff, CreationStats, DefenceBuff};
-use crate::data::wizard::{GameWizard, LobbyWizards};
+use crate::data::wizard::{GameWizard, LobbyWizards, ServerWizards};
 use crate::error::ChaosError;
 use crate::gfx::color::Color::*;
-use crate::net::{Message, NetworkError, RecieveMsg, SendMsg};
+use crate::net::{Message, NetworkError, RecieveMsg, SendMsg, SERVER_STATS};
 use rand::SeedableRng;
 use rand::{rngs::StdRng, seq::SliceRandom, Rng};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
 use tokio::select;
 use tokio::sync::{mpsc, oneshot};
+use tokio::time::{interval, sleep, Duration, Instant};
 
-pub struct GameLogic {
+const LOBBY_RESPONSE_TIMEOUT: Duration = Duration::from_secs(8);
+
+struct Inbox {
     rx: mpsc::Receiver<RecieveMsg>,
+    held: VecDeque<RecieveMsg>,
+    paused: bool,
+}
+
+impl Inbox {
+    async fn recv(&mut self) -> Option<RecieveMsg> {
+        if !self.paused {
+            if let Some(msg) = self.held.pop_front() {
+                return Some(msg);
+            }
+        }
+        let msg = self.rx.recv().await;
+        if let Some(RecieveMsg::Message { id, msg }) = &msg {
+            crash_dump::record_message(*id, msg);
+        }
+        msg
+    }
+}
+
+pub struct GameLogic {
+    inbox: Inbox,
     tx: Sender,
     quit_rx: oneshot::Receiver<()>,
+    options: HostOptions,
+    host: Option<u32>,
+    campaign: Arc<Mutex<Vec<CampaignWizard>>>,
+    rng: StdRng,
 }
 
 impl GameLogic {
-    pub fn new(rx: mpsc::Receiver<RecieveMsg>, tx: mpsc::Sender<SendMsg>, quit_rx: oneshot::Receiver<()>) -> Self {
+    pub fn new(
+        rx: mpsc::Receiver<RecieveMsg>,
+        tx: mpsc::Sender<SendMsg>,
+        quit_rx: oneshot::Receiver<()>,
+        mut options: HostOptions,
+        campaign: Arc<Mutex<Vec<CampaignWizard>>>,
+    ) -> Self {
+        options.sanitize();
         let tx = Sender::new(tx);
-        Self { rx, tx, quit_rx }
+        let inbox = Inbox {
+            rx,
+            held: VecDeque::new(),
+            paused: false,
+        };
+        let rng = match options.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self {
+            inbox,
+            tx,
+            quit_rx,
+            options,
+            host: None,
+            campaign,
+            rng,
+        }
+    }
+
+    async fn hold_while_paused(&mut self, msg: RecieveMsg) -> Result<Option<RecieveMsg>, NetworkError> {
+        match msg {
+            RecieveMsg::Message {
+                id,
+                msg: Message::Pause(paused),
+            } => {
+                if self.host == Some(id) && self.inbox.paused != paused {
+                    self.inbox.paused = paused;
+                    self.tx.pause(id, paused).await?;
+                }
+                Ok(None)
+            }
+            RecieveMsg::Message { .. } if self.inbox.paused => {
+                self.inbox.held.push_back(msg);
+                Ok(None)
+            }
+            msg => Ok(Some(msg)),
+        }
     }
 
     pub async fn lobby_loop(&mut self) -> Result<Option<LobbyWizards>, NetworkError> {
         let mut wizards = LobbyWizards::new();
+        wizards.level = self.options.starting_level.min(8);
+        wizards.max_spells = self.options.max_spells.max(1);
+        wizards.banned_spells = self.options.banned_spells.clone();
+        if self.options.campaign {
+            wizards.campaign = self.campaign.lock().expect("campaign").clone();
+        }
+        self.inbox.paused = false;
+        self.inbox.held.clear();
+        let mut last_seen = HashMap::<u32, Instant>::new();
+        let mut check_responsive = interval(Duration::from_secs(1));
         loop {
             select! {
                 _ = &mut self.quit_rx => {
                     self.tx.shutdown().await?;
                 }
-                Some(msg) = self.rx.recv() => {
+                _ = check_responsive.tick() => {
+                    for wizard in wizards.players() {
+                        let responsive = last_seen
+                            .get(&wizard.id)
+                            .is_some_and(|seen| seen.elapsed() < LOBBY_RESPONSE_TIMEOUT);
+                        if wizards.responsive(wizard.id, responsive) {
+                            self.tx.responsive(wizard.id, responsive).await?;
+                        }
+                    }
+                }
+                Some(msg) = self.inbox.recv() => {
                     match msg {
+                        RecieveMsg::Latency { id, .. } => {
+                            last_seen.insert(id, Instant::now());
+                            if wizards.responsive(id, true) {
+                                self.tx.responsive(id, true).await?;
+                            }
+                        }
                         RecieveMsg::Connected { id } => {
+                            self.host.get_or_insert(id);
                             self.tx.send_all_wizards_to(id, &wizards).await?;
                         }
                         RecieveMsg::Disconnected { id } => {
+                            last_seen.remove(&id);
                             if wizards.leave(id).is_some() {
                                 self.tx.leave(id).await?;
                             }
                         }
                         RecieveMsg::Message { id, msg } => {
                             match msg {
-                                Message::Join(player) => {
+                                Message::Join(mut player) => {
+                                    player.sanitize();
                                     if wizards.join(id, player.clone()) {
                                         self.tx.join(id, &player).await?;
+                                        self.tx.banned_spells(id, &wizards.banned_spells).await?;
                                     }
                                 }
                                 Message::Ready(ready) => {
@@ -58,10 +161,12 @@ impl GameLogic {
                                         }
                                     }
                                 }
+                                Message::ClaimPosition(index) if wizards.claim_position(id, index) => {
+                                    self.tx.claim_position(id, index).await?;
+                                }
                                 _ => {}
                             }
                         }
-                        _ => {}
                     }
                 }
             }
@@ -69,18 +174,24 @@ impl GameLogic {
     }
 
     async fn select_spells(&mut self, state: &mut ServerState) -> Result<Vec<(u32, Spell, bool)>, ChaosError> {
-        let mut left_to_choose = HashSet::<u32>::from_iter(state.wizards.all_active_ids());
+        let ids = state.wizards.all_active_ids();
+        let mut left_to_choose = HashSet::<u32>::from_iter(ids.iter().copied());
         self.tx.waiting_for_other_players(left_to_choose.len()).await?;
         let mut spells = Vec::with_capacity(left_to_choose.len());
-        for id in left_to_choose.iter().copied() {
-            self.tx.choose_spell(id).await?;
+        for id in ids {
+            let only_disbelieve = state.wizards.get(id)?.spells.len() <= 1;
+            self.tx.choose_spell(id, only_disbelieve).await?;
         }
         'spell_loop: loop {
             select! {
                 _ = &mut self.quit_rx => {
                     self.tx.shutdown().await?;
                 }
-                Some(msg) = self.rx.recv() => {
+                Some(msg) = self.inbox.recv() => {
+                    let msg = match self.hold_while_paused(msg).await? {
+                        Some(msg) => msg,
+                        None => continue,
+                    };
                     match msg {
                         RecieveMsg::Disconnected { id } => {
                             if left_to_choose.remove(&id) {
@@ -136,6 +247,31 @@ impl GameLogic {
         Ok(spells)
     }
 
+    async fn chosen_target(
+        &mut self,
+        state: &mut ServerState,
+        id: u32,
+        mut tiles: Vec<(u8, u8)>,
+        valid_tiles: impl Fn(&Arena) -> Vec<(u8, u8)>,
+    ) -> Result<Option<(u8, u8)>, NetworkError> {
+        loop {
+            self.tx.choose_target(id, &tiles).await?;
+            match self.chosen_tile(state, id, tiles).await? {
+                Some(tile) => {
+                    tiles = valid_tiles(&state.arena);
+                    if tiles.contains(&tile) {
+                        return Ok(Some(tile));
+                    }
+                    self.tx.target_no_longer_valid(id).await?;
+                    if tiles.is_empty() {
+                        return Ok(None);
+                    }
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
     async fn chosen_tile(
         &mut self,
         state: &mut ServerState,
@@ -150,7 +286,11 @@ impl GameLogic {
                 _ = &mut self.quit_rx => {
                     self.tx.shutdown().await?;
                 }
-                Some(msg) = self.rx.recv() => {
+                Some(msg) = self.inbox.recv() => {
+                    let msg = match self.hold_while_paused(msg).await? {
+                        Some(msg) => msg,
+                        None => continue,
+                    };
                     match msg {
                         RecieveMsg::Disconnected { id: disconnected_id } => {
                             state.wizards.get_mut(disconnected_id)?.disconnected = true;
@@ -182,21 +322,98 @@ impl GameLogic {
         }
     }
 
+    async fn sync_animations(&mut self, state: &mut ServerState) -> Result<(), NetworkError> {
+        if !self.options.sync_animations {
+            return Ok(());
+        }
+        let mut left_to_finish = HashSet::<u32>::from_iter(state.wizards.all_active_ids());
+        self.tx.sync_animations().await?;
+        let timeout = sleep(Duration::from_secs(5));
+        tokio::pin!(timeout);
+        while !left_to_finish.is_empty() {
+            select! {
+                _ = &mut self.quit_rx => {
+                    self.tx.shutdown().await?;
+                }
+                _ = &mut timeout => {
+                    break;
+                }
+                Some(msg) = self.inbox.recv() => {
+                    let msg = match self.hold_while_paused(msg).await? {
+                        Some(msg) => msg,
+                        None => continue,
+                    };
+                    match msg {
+                        RecieveMsg::Disconnected { id } => {
+                            left_to_finish.remove(&id);
+                            state.wizards.get_mut(id)?.disconnected = true;
+                        }
+                        RecieveMsg::Message {
+                            id,
+                            msg: Message::AnimationDone,
+                        } => {
+                            left_to_finish.remove(&id);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn shutdown_if_abandoned(&mut self, state: &ServerState) -> Result<(), NetworkError> {
+        if state.wizards.all_active_ids().is_empty() {
+            return self.tx.shutdown().await;
+        }
+        Ok(())
+    }
+
+    async fn buff_fails(&mut self, id: u32) -> Result<(), NetworkError> {
+        if self.options.private_buff_failures {
+            self.tx.spell_fails_to(id).await
+        } else {
+            self.tx.spell_fails(id).await
+        }
+    }
+
+    async fn cast_spells(&mut self, state: &mut ServerState, spells: Vec<(u32, Spell, bool)>) -> Result<(), ChaosError> {
+        for (id, spell, illusion) in spells {
+            if state.arena.maybe_find_wizard_pos(id).is_none() {
+                continue;
+            }
+            crash_dump::record_state(state);
+            self.sync_animations(state).await?;
+            self.do_spell(state, id, spell, illusion).await?;
+            self.shutdown_if_abandoned(state).await?;
+            if state.wizards.check_for_winning_condition() {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
     async fn do_spell(&mut self, state: &mut ServerState, id: u32, spell: Spell, illusion: bool) -> Result<(), ChaosError> {
         let alignment = state.arena.alignment;
+        let limit = self.options.creation_limit as usize;
+        let at_limit = limit > 0 && spell.places_creation() && state.arena.creation_count(id) >= limit;
         let wizard = state.arena.find_wizard_mut(id);
         let spell_ability = wizard.stats.spell_ability;
         self.tx.cast_spell(id, &spell).await?;
+        if at_limit {
+            self.tx.creation_limit_reached(id).await?;
+            return Ok(());
+        }
         match spell.kind {
             SpellKind::Disbelieve => {
                 let (x, y) = state.arena.find_wizard_pos(id);
-                let tiles = state.arena.cast_spell_on_attackable_tiles(x, y, spell.range, id);
+                let valid_tiles = |arena: &Arena| arena.cast_spell_on_attackable_tiles(x, y, spell.range, id);
+                let tiles = valid_tiles(&state.arena);
                 if tiles.is_empty() {
                     self.tx.no_possible_moves(id).await?;
                     return Ok(());
                 }
-                self.tx.choose_target(id, &tiles).await?;
-                if let Some((x, y)) = self.chosen_tile(state, id, tiles).await? {
+                if let Some((x, y)) = self.chosen_target(state, id, tiles, valid_tiles).await? {
                     let tile = state.arena.get_mut(x, y);
                     if let Some(GameCreation { illusion: true, .. }) = tile.creation.as_mut() {
                         self.tx.disbelieve(id, x, y, true).await?;
@@ -205,25 +422,25 @@ impl GameLogic {
                         self.tx.spell_succeeds(state.arena.alignment).await?;
                     } else {
                         self.tx.disbelieve(id, x, y, false).await?;
-                        self.tx.spell_fails().await?;
+                        self.tx.spell_fails(id).await?;
                     }
                 }
             }
             SpellKind::Creation(ref stats) => {
                 let (sx, sy) = state.arena.find_wizard_pos(id);
                 loop {
-                    let tiles = state.arena.creation_spell_tiles(sx, sy, spell.range);
+                    let valid_tiles = |arena: &Arena| arena.creation_spell_tiles(sx, sy, spell.range);
+                    let tiles = valid_tiles(&state.arena);
                     if tiles.is_empty() {
                         self.tx.no_possible_moves(id).await?;
                         return Ok(());
                     }
-                    self.tx.choose_target(id, &tiles).await?;
-                    if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+                    if let Some((dx, dy)) = self.chosen_target(state, id, tiles, valid_tiles).await? {
                         if !state.arena.line_of_sight(sx, sy, dx, dy) {
                             self.tx.no_line_of_sight(id).await?;
                             continue;
                         }
-                        if illusion || spell.cast(alignment, spell_ability) {
+                        if illusion || spell.cast(alignment, spell_ability, &mut self.rng) {
                             let mut creation = GameCreation::new(id, stats.clone());
                             self.tx.creation_spell(id, dx, dy, Some(&creation)).await?;
                             let tile = state.arena.get_mut(dx, dy);
@@ -233,7 +450,7 @@ impl GameLogic {
                             self.tx.spell_succeeds(state.arena.alignment).await?;
                         } else {
                             self.tx.creation_spell(id, dx, dy, None).await?;
-                            self.tx.spell_fails().await?;
+                            self.tx.spell_fails(id).await?;
                         }
                     }
                     return Ok(());
@@ -242,18 +459,18 @@ impl GameLogic {
             SpellKind::MagicFire(ref stats) => {
                 let (sx, sy) = state.arena.find_wizard_pos(id);
                 loop {
-                    let tiles = state.arena.creation_spell_tiles(sx, sy, spell.range);
+                    let valid_tiles = |arena: &Arena| arena.creation_spell_tiles(sx, sy, spell.range);
+                    let tiles = valid_tiles(&state.arena);
                     if tiles.is_empty() {
                         self.tx.no_possible_moves(id).await?;
                         return Ok(());
                     }
-                    self.tx.choose_target(id, &tiles).await?;
-                    if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+                    if let Some((dx, dy)) = self.chosen_target(state, id, tiles, valid_tiles).await? {
                         if !state.arena.line_of_sight(sx, sy, dx, dy) {
                             self.tx.no_line_of_sight(id).await?;
                             continue;
                         }
-                        if spell.cast(alignment, spell_ability) {
+                        if spell.cast(alignment, spell_ability, &mut self.rng) {
                             let fire = GameCreation::new(id, stats.clone());
                             self.tx.cast_fire(id, dx, dy, Some(&fire)).await?;
                             state.arena.spawn_fire(dx, dy, fire);
@@ -261,7 +478,7 @@ impl GameLogic {
                             self.tx.spell_succeeds(state.arena.alignment).await?;
                         } else {
                             self.tx.cast_fire(id, dx, dy, None).await?;
-                            self.tx.spell_fails().await?;
+                            self.tx.spell_fails(id).await?;
                         }
                     }
                     return Ok(());
@@ -270,18 +487,18 @@ impl GameLogic {
             SpellKind::GooeyBlob(ref stats) => {
                 let (sx, sy) = state.arena.find_wizard_pos(id);
                 loop {
-                    let tiles = state.arena.creation_spell_tiles(sx, sy, spell.range);
+                    let valid_tiles = |arena: &Arena| arena.creation_spell_tiles(sx, sy, spell.range);
+                    let tiles = valid_tiles(&state.arena);
                     if tiles.is_empty() {
                         self.tx.no_possible_moves(id).await?;
                         return Ok(());
                     }
-                    self.tx.choose_target(id, &tiles).await?;
-                    if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+                    if let Some((dx, dy)) = self.chosen_target(state, id, tiles, valid_tiles).await? {
                         if !state.arena.line_of_sight(sx, sy, dx, dy) {
                             self.tx.no_line_of_sight(id).await?;
                             continue;
                         }
-                        if spell.cast(alignment, spell_ability) {
+                        if spell.cast(alignment, spell_ability, &mut self.rng) {
                             let blob = GameCreation::new(id, stats.clone());
                             self.tx.cast_blob(id, dx, dy, Some(&blob)).await?;
                             state.arena.spawn_blob(dx, dy, blob);
@@ -289,7 +506,7 @@ impl GameLogic {
                             self.tx.spell_succeeds(state.arena.alignment).await?;
                         } else {
                             self.tx.cast_blob(id, dx, dy, None).await?;
-                            self.tx.spell_fails().await?;
+                            self.tx.spell_fails(id).await?;
                         }
                     }
                     return Ok(());
@@ -299,19 +516,19 @@ impl GameLogic {
                 let (sx, sy) = state.arena.find_wizard_pos(id);
                 let mut cast = false;
                 let mut count = 0;
-                let mut rng = StdRng::from_entropy();
                 loop {
                     let mut tiles = state.arena.creation_spell_tiles(sx, sy, spell.range);
                     if tiles.is_empty() {
                         self.tx.no_possible_moves(id).await?;
                         return Ok(());
                     }
-                    tiles.shuffle(&mut rng);
+                    tiles.shuffle(&mut self.rng);
+                    let before = count;
                     for (dx, dy) in tiles {
                         if state.arena.line_of_sight(sx, sy, dx, dy) {
-                            if !cast && !spell.cast(alignment, spell_ability) {
+                            if !cast && !spell.cast(alignment, spell_ability, &mut self.rng) {
                                 self.tx.creation_spell(id, dx, dy, None).await?;
-                                self.tx.spell_fails().await?;
+                                self.tx.spell_fails(id).await?;
                                 return Ok(());
                             }
                             let wood = GameCreation::new(id, stats.clone());
@@ -329,6 +546,12 @@ impl GameLogic {
                             }
                         }
                     }
+                    if count == before {
+                        if !cast {
+                            self.tx.no_possible_moves(id).await?;
+                        }
+                        return Ok(());
+                    }
                 }
             }
             SpellKind::ShadowWood(ref stats) => {
@@ -336,13 +559,13 @@ impl GameLogic {
                 let mut count = 0;
                 let mut cast = false;
                 loop {
-                    let tiles = state.arena.creation_spell_tiles(sx, sy, spell.range);
+                    let valid_tiles = |arena: &Arena| arena.creation_spell_tiles(sx, sy, spell.range);
+                    let tiles = valid_tiles(&state.arena);
                     if tiles.is_empty() {
                         self.tx.no_possible_moves(id).await?;
                         return Ok(());
                     }
-                    self.tx.choose_target(id, &tiles).await?;
-                    if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+                    if let Some((dx, dy)) = self.chosen_target(state, id, tiles, valid_tiles).await? {
                         if !state.arena.line_of_sight(sx, sy, dx, dy) {
                             self.tx.no_line_of_sight(id).await?;
                             continue;
@@ -351,9 +574,9 @@ impl GameLogic {
                             self.tx.shadow_wood_info(id).await?;
                             continue;
                         }
-                        if !cast && !spell.cast(alignment, spell_ability) {
+                        if !cast && !spell.cast(alignment, spell_ability, &mut self.rng) {
                             self.tx.creation_spell(id, dx, dy, None).await?;
-                            self.tx.spell_fails().await?;
+                            self.tx.spell_fails(id).await?;
                             return Ok(());
                         }
                         let creation = GameCreation::new(id, stats.clone());
@@ -377,18 +600,18 @@ impl GameLogic {
             SpellKind::Shelter(ref stats) => {
                 let (sx, sy) = state.arena.find_wizard_pos(id);
                 loop {
-                    let tiles = state.arena.creation_spell_tiles(sx, sy, spell.range);
+                    let valid_tiles = |arena: &Arena| arena.creation_spell_tiles(sx, sy, spell.range);
+                    let tiles = valid_tiles(&state.arena);
                     if tiles.is_empty() {
                         self.tx.no_possible_moves(id).await?;
                         return Ok(());
                     }
-                    self.tx.choose_target(id, &tiles).await?;
-                    if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+                    if let Some((dx, dy)) = self.chosen_target(state, id, tiles, valid_tiles).await? {
                         if !state.arena.line_of_sight(sx, sy, dx, dy) {
                             self.tx.no_line_of_sight(id).await?;
                             continue;
                         }
-                        if spell.cast(alignment, spell_ability) {
+                        if spell.cast(alignment, spell_ability, &mut self.rng) {
                             state.arena.adjust_alignment(spell.alignment);
                             self.tx.spell_succeeds(state.arena.alignment).await?;
                             let creation = GameCreation::new(id, stats.clone());
@@ -396,7 +619,7 @@ impl GameLogic {
                             state.arena.get_mut(dx, dy).creation = Some(creation);
                         } else {
                             self.tx.creation_spell(id, dx, dy, None).await?;
-                            self.tx.spell_fails().await?;
+                            self.tx.spell_fails(id).await?;
                         }
                     }
                     return Ok(());
@@ -407,20 +630,20 @@ impl GameLogic {
                 let mut cast = false;
                 let mut count = 0;
                 loop {
-                    let tiles = state.arena.creation_spell_tiles(sx, sy, spell.range);
+                    let valid_tiles = |arena: &Arena| arena.creation_spell_tiles(sx, sy, spell.range);
+                    let tiles = valid_tiles(&state.arena);
                     if tiles.is_empty() {
                         self.tx.no_possible_moves(id).await?;
                         return Ok(());
                     }
-                    self.tx.choose_target(id, &tiles).await?;
-                    if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+                    if let Some((dx, dy)) = self.chosen_target(state, id, tiles, valid_tiles).await? {
                         if !state.arena.line_of_sight(sx, sy, dx, dy) {
                             self.tx.no_line_of_sight(id).await?;
                             continue;
                         }
-                        if !cast && !spell.cast(alignment, spell_ability) {
+                        if !cast && !spell.cast(alignment, spell_ability, &mut self.rng) {
                             self.tx.creation_spell(id, dx, dy, None).await?;
-                            self.tx.spell_fails().await?;
+                            self.tx.spell_fails(id).await?;
                             return Ok(());
                         }
                         let creation = GameCreation::new(id, stats.clone());
@@ -443,31 +666,32 @@ impl GameLogic {
             SpellKind::MagicBolt => {
                 let (sx, sy) = state.arena.find_wizard_pos(id);
                 let spell_ability = state.arena.find_wizard(id).stats.spell_ability;
-                if spell.cast(alignment, spell_ability) {
+                if spell.cast(alignment, spell_ability, &mut self.rng) {
                     loop {
-                        let tiles = state.arena.cast_spell_on_attackable_tiles(sx, sy, spell.range, id);
+                        let valid_tiles = |arena: &Arena| arena.cast_spell_on_attackable_tiles(sx, sy, spell.range, id);
+                        let tiles = valid_tiles(&state.arena);
                         if tiles.is_empty() {
                             self.tx.no_possible_moves(id).await?;
                             return Ok(());
                         }
-                        self.tx.choose_target(id, &tiles).await?;
-                        if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+                        if let Some((dx, dy)) = self.chosen_target(state, id, tiles, valid_tiles).await? {
                             if !state.arena.line_of_sight(sx, sy, dx, dy) {
                                 self.tx.no_line_of_sight(id).await?;
                                 continue;
                             }
                             let tile = state.arena.get_mut(dx, dy).clone();
                             if let Some(creation) = tile.creation {
-                                if creation.defend_against_attack(3) {
+                                if creation.defend_against_attack(3, self.options.combat_model, &mut self.rng) {
                                     self.tx.magic_bolt(id, dx, dy, true).await?;
                                     state.arena.kill_creation(dx, dy, false);
                                 } else {
                                     self.tx.magic_bolt(id, dx, dy, false).await?;
                                 }
                             } else if let Some(wizard) = tile.wizard {
-                                if wizard.defend_against_attack(3) {
+                                if wizard.defend_against_attack(3, self.options.combat_model, &mut self.rng) {
                                     self.tx.magic_bolt(id, dx, dy, true).await?;
                                     state.arena.kill_wizard_and_creations(wizard.id);
+                                    state.wizards.get_mut(wizard.id)?.alive = false;
                                     if state.wizards.check_for_winning_condition() {
                                         return Ok(());
                                     }
@@ -479,39 +703,40 @@ impl GameLogic {
                         return Ok(());
                     }
                 } else {
-                    self.tx.spell_fails().await?;
+                    self.tx.spell_fails(id).await?;
                 }
             }
             SpellKind::Lightning => {
                 let (sx, sy) = state.arena.find_wizard_pos(id);
                 let spell_ability = state.arena.find_wizard(id).stats.spell_ability;
-                if spell.cast(alignment, spell_ability) {
+                if spell.cast(alignment, spell_ability, &mut self.rng) {
                     state.arena.adjust_alignment(spell.alignment);
                     self.tx.spell_succeeds(state.arena.alignment).await?;
                     loop {
-                        let tiles = state.arena.cast_spell_on_attackable_tiles(sx, sy, spell.range, id);
+                        let valid_tiles = |arena: &Arena| arena.cast_spell_on_attackable_tiles(sx, sy, spell.range, id);
+                        let tiles = valid_tiles(&state.arena);
                         if tiles.is_empty() {
                             self.tx.no_possible_moves(id).await?;
                             return Ok(());
                         }
-                        self.tx.choose_target(id, &tiles).await?;
-                        if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+                        if let Some((dx, dy)) = self.chosen_target(state, id, tiles, valid_tiles).await? {
                             if !state.arena.line_of_sight(sx, sy, dx, dy) {
                                 self.tx.no_line_of_sight(id).await?;
                                 continue;
                             }
                             let tile = state.arena.get_mut(dx, dy).clone();
                             if let Some(creation) = tile.creation {
-                                if creation.defend_against_attack(6) {
+                                if creation.defend_against_attack(6, self.options.combat_model, &mut self.rng) {
                                     self.tx.lightning(id, dx, dy, true).await?;
                                     state.arena.kill_creation(dx, dy, false);
                                 } else {
                                     self.tx.lightning(id, dx, dy, false).await?;
                                 }
                             } else if let Some(wizard) = tile.wizard {
-                                if wizard.defend_against_attack(6) {
+                                if wizard.defend_against_attack(6, self.options.combat_model, &mut self.rng) {
                                     self.tx.lightning(id, dx, dy, true).await?;
                                     state.arena.kill_wizard_and_creations(wizard.id);
+                                    state.wizards.get_mut(wizard.id)?.alive = false;
                                     if state.wizards.check_for_winning_condition() {
                                         return Ok(());
                                     }
@@ -523,33 +748,34 @@ impl GameLogic {
                         return Ok(());
                     }
                 } else {
-                    self.tx.spell_fails().await?;
+                    self.tx.spell_fails(id).await?;
                 }
             }
             SpellKind::MagicalAttack(attempts) => {
                 let (sx, sy) = state.arena.find_wizard_pos(id);
                 let spell_ability = state.arena.find_wizard(id).stats.spell_ability;
-                if spell.cast(alignment, spell_ability) {
+                if spell.cast(alignment, spell_ability, &mut self.rng) {
                     state.arena.adjust_alignment(spell.alignment);
                     self.tx.spell_succeeds(state.arena.alignment).await?;
                     for _ in 0..attempts {
-                        let tiles = state.arena.cast_spell_on_attackable_tiles(sx, sy, spell.range, id);
+                        let valid_tiles = |arena: &Arena| arena.cast_spell_on_attackable_tiles(sx, sy, spell.range, id);
+                        let tiles = valid_tiles(&state.arena);
                         if tiles.is_empty() {
                             self.tx.no_possible_moves(id).await?;
                             return Ok(());
                         }
-                        self.tx.choose_target(id, &tiles).await?;
-                        if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+                        if let Some((dx, dy)) = self.chosen_target(state, id, tiles, valid_tiles).await? {
                             let tile = state.arena.get_mut(dx, dy).clone();
                             if let Some(creation) = tile.creation {
-                                if creation.defend_against_magical_attack(spell_ability) {
+                                if creation.defend_against_magical_attack(spell_ability, self.options.combat_model, &mut self.rng)
+                                {
                                     self.tx.magical_attack(id, dx, dy, true).await?;
                                     state.arena.kill_creation(dx, dy, false);
                                 } else {
                                     self.tx.magical_attack(id, dx, dy, false).await?;
                                 }
                             } else if let Some(wizard) = tile.wizard {
-                                if wizard.defend_against_magical_attack(spell_ability) {
+                                if wizard.defend_against_magical_attack(spell_ability, self.options.combat_model, &mut self.rng) {
                                     self.tx.magical_attack(id, dx, dy, true).await?;
                                     state.arena.destroy_all_wizard_creations(wizard.id);
                                 } else {
@@ -561,11 +787,11 @@ impl GameLogic {
                         }
                     }
                 } else {
-                    self.tx.spell_fails().await?;
+                    self.tx.spell_fails(id).await?;
                 }
             }
             SpellKind::WizardAttackBuff(ref buff) => {
-                if spell.cast(alignment, spell_ability) {
+                if spell.cast(alignment, spell_ability, &mut self.rng) {
                     wizard.stats.attack_buff = Some(buff.clone());
                     match buff {
                         AttackBuff::MagicKnife => wizard.stats.magic_knife(),
@@ -575,11 +801,11 @@ impl GameLogic {
                     state.arena.adjust_alignment(spell.alignment);
                     self.tx.spell_succeeds(state.arena.alignment).await?;
                 } else {
-                    self.tx.spell_fails().await?;
+                    self.buff_fails(id).await?;
                 }
             }
             SpellKind::WizardDefenceBuff(ref buff) => {
-                if spell.cast(alignment, spell_ability) {
+                if spell.cast(alignment, spell_ability, &mut self.rng) {
                     wizard.stats.defence_buff = Some(buff.clone());
                     match buff {
                         DefenceBuff::MagicShield => wizard.stats.magic_shield(),
@@ -589,73 +815,73 @@ impl GameLogic {
                     state.arena.adjust_alignment(spell.alignment);
                     self.tx.spell_succeeds(state.arena.alignment).await?;
                 } else {
-                    self.tx.spell_fails().await?;
+                    self.buff_fails(id).await?;
                 }
             }
             SpellKind::MagicBow => {
-                if spell.cast(alignment, spell_ability) {
+                if spell.cast(alignment, spell_ability, &mut self.rng) {
                     wizard.stats.magic_bow();
                     self.tx.buff_wizard(wizard.id, &wizard.stats).await?;
                     state.arena.adjust_alignment(spell.alignment);
                     self.tx.spell_succeeds(state.arena.alignment).await?;
                 } else {
-                    self.tx.spell_fails().await?;
+                    self.buff_fails(id).await?;
                 }
             }
             SpellKind::MagicWings => {
-                if spell.cast(alignment, spell_ability) {
+                if spell.cast(alignment, spell_ability, &mut self.rng) {
                     wizard.stats.magic_wings();
                     self.tx.buff_wizard(wizard.id, &wizard.stats).await?;
                     state.arena.adjust_alignment(spell.alignment);
                     self.tx.spell_succeeds(state.arena.alignment).await?;
                 } else {
-                    self.tx.spell_fails().await?;
+                    self.buff_fails(id).await?;
                 }
             }
             SpellKind::WorldAlignment => {
-                if spell.cast(alignment, spell_ability) {
+                if spell.cast(alignment, spell_ability, &mut self.rng) {
                     state.arena.adjust_alignment(spell.alignment);
                     self.tx.spell_succeeds(state.arena.alignment).await?;
                 } else {
-                    self.tx.spell_fails().await?;
+                    self.tx.spell_fails(id).await?;
                 }
             }
             SpellKind::ShadowForm => {
-                if spell.cast(alignment, spell_ability) {
+                if spell.cast(alignment, spell_ability, &mut self.rng) {
                     wizard.stats.shadow_form = true;
                     self.tx.buff_wizard(id, &wizard.stats).await?;
                     state.arena.adjust_alignment(spell.alignment);
                     self.tx.spell_succeeds(state.arena.alignment).await?;
                 } else {
-                    self.tx.spell_fails().await?;
+                    self.buff_fails(id).await?;
                 }
             }
             SpellKind::Subversion => {
                 let (sx, sy) = state.arena.find_wizard_pos(id);
                 let spell_ability = state.arena.find_wizard(id).stats.spell_ability;
                 loop {
-                    let tiles = state.arena.all_subvertable_opposition_tiles(sx, sy, spell.range, id);
+                    let valid_tiles = |arena: &Arena| arena.all_subvertable_opposition_tiles(sx, sy, spell.range, id);
+                    let tiles = valid_tiles(&state.arena);
                     if tiles.is_empty() {
                         self.tx.no_possible_moves(id).await?;
                         return Ok(());
                     }
-                    self.tx.choose_target(id, &tiles).await?;
-                    if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+                    if let Some((dx, dy)) = self.chosen_target(state, id, tiles, valid_tiles).await? {
                         if !state.arena.line_of_sight(sx, sy, dx, dy) {
                             self.tx.no_line_of_sight(id).await?;
                             continue;
                         }
                         let creation = state.arena.get_creation(dx, dy);
-                        if spell.cast(alignment, spell_ability)
+                        if spell.cast(alignment, spell_ability, &mut self.rng)
                             && !creation.illusion
-                            && creation.defend_against_magical_attack(spell_ability)
+                            && creation.defend_against_magical_attack(spell_ability, self.options.combat_model, &mut self.rng)
                         {
                             state.arena.adjust_alignment(spell.alignment);
                             self.tx.spell_succeeds(state.arena.alignment).await?;
                             self.tx.subversion(id, dx, dy, true).await?;
                             state.arena.subvert(dx, dy, id);
                         } else {
-                            self.tx.spell_fails().await?;
+                            self.tx.spell_fails(id).await?;
                         }
                     }
                     return Ok(());
@@ -665,19 +891,23 @@ impl GameLogic {
                 let (sx, sy) = state.arena.find_wizard_pos(id);
                 let spell_ability = state.arena.find_wizard(id).stats.spell_ability;
                 loop {
-                    let tiles = state.arena.visible_corpse_tiles(sx, sy, spell.range);
+                    let valid_tiles = |arena: &Arena| arena.visible_corpse_tiles(sx, sy, spell.range);
+                    let tiles = valid_tiles(&state.arena);
                     if tiles.is_empty() {
                         self.tx.no_possible_moves(id).await?;
                         return Ok(());
                     }
-                    self.tx.choose_target(id, &tiles).await?;
-                    if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+                    if let Some((dx, dy)) = self.chosen_target(state, id, tiles, valid_tiles).await? {
                         if !state.arena.line_of_sight(sx, sy, dx, dy) {
                             self.tx.no_line_of_sight(id).await?;
                             continue;
                         }
-                        if spell.cast(alignment, spell_ability)
-                            && state.arena.get_corpse(dx, dy).defend_against_magical_attack(spell_ability)
+                        if spell.cast(alignment, spell_ability, &mut self.rng)
+                            && state.arena.get_corpse(dx, dy).defend_against_magical_attack(
+                                spell_ability,
+                                self.options.combat_model,
+                                &mut self.rng,
+                            )
                         {
                             self.tx.raise_dead(id, dx, dy, true).await?;
                             state.arena.raise_dead(dx, dy, id);
@@ -685,7 +915,7 @@ impl GameLogic {
                             self.tx.spell_succeeds(state.arena.alignment).await?;
                         } else {
                             self.tx.raise_dead(id, dx, dy, false).await?;
-                            self.tx.spell_fails().await?;
+                            self.tx.spell_fails(id).await?;
                         }
                     }
                     return Ok(());
@@ -709,7 +939,11 @@ impl GameLogic {
         let tile = state.arena.get_mut(dx, dy).clone();
         if tile.spawn.is_some() {
             let combat = state.arena.get_creation(sx, sy).stats.base.combat;
-            if state.arena.get_blob(dx, dy).defend_against_attack(combat) {
+            if state
+                .arena
+                .get_blob(dx, dy)
+                .defend_against_attack(combat, self.options.combat_model, &mut self.rng)
+            {
                 self.tx.successful_attack(id, dx, dy, false).await?;
                 state.arena.remove_spawn(dx, dy);
                 if !shadow_wood && tile.creation.is_none() && tile.wizard.is_none() {
@@ -723,8 +957,8 @@ impl GameLogic {
             }
         } else if let Some(other) = tile.creation {
             let creation = state.arena.get_creation(sx, sy);
-            if other.defend_against_attack(creation.stats.base.combat) {
-                if other.stats.magic_wood {
+            if other.defend_against_attack(creation.stats.base.combat, self.options.combat_model, &mut self.rng) {
+                if other.stats.magic_wood && tile.wizard.is_some() {
                     let wizard_id = state.arena.get_wizard(dx, dy).id;
                     self.tx.successful_attack(id, dx, dy, false).await?;
                     state.arena.kill_wizard_and_creations(wizard_id);
@@ -732,7 +966,7 @@ impl GameLogic {
                     if state.wizards.check_for_winning_condition() {
                         return Ok(());
                     }
-                    if !shadow_wood {
+                    if !shadow_wood && state.arena.get(dx, dy).creation.is_none() {
                         self.tx.move_creation(id, sx, sy, dx, dy).await?;
                         state.arena.move_creation(sx, sy, dx, dy);
                         self.check_for_creation_ranged_combat(state, id, dx, dy).await?;
@@ -753,7 +987,7 @@ impl GameLogic {
             }
         } else if let Some(ref wizard) = tile.wizard {
             let creation = state.arena.get_creation(sx, sy);
-            if wizard.defend_against_attack(creation.stats.base.combat) {
+            if wizard.defend_against_attack(creation.stats.base.combat, self.options.combat_model, &mut self.rng) {
                 self.tx.successful_attack(id, dx, dy, false).await?;
                 state.arena.kill_wizard_and_creations(wizard.id);
                 state.wizards.get_mut(wizard.id)?.alive = false;
@@ -763,8 +997,8 @@ impl GameLogic {
                 if !shadow_wood {
                     self.tx.move_creation(id, sx, sy, dx, dy).await?;
                     state.arena.move_creation(sx, sy, dx, dy);
+                    self.check_for_creation_ranged_combat(state, id, dx, dy).await?;
                 }
-                self.check_for_creation_ranged_combat(state, id, dx, dy).await?;
             } else {
                 self.tx.failed_attack(id, dx, dy).await?;
                 self.check_for_creation_ranged_combat(state, id, sx, sy).await?;
@@ -790,7 +1024,11 @@ impl GameLogic {
         wizard.stats.shadow_form = false;
         if tile.spawn.is_some() {
             let combat = wizard.stats.get_combat();
-            if state.arena.get_blob(dx, dy).defend_against_attack(combat) {
+            if state
+                .arena
+                .get_blob(dx, dy)
+                .defend_against_attack(combat, self.options.combat_model, &mut self.rng)
+            {
                 self.tx.successful_attack(id, dx, dy, false).await?;
                 state.arena.remove_spawn(dx, dy);
                 if tile.creation.is_none() && tile.wizard.is_none() {
@@ -803,8 +1041,8 @@ impl GameLogic {
                 self.check_for_wizard_ranged_combat(state, id, sx, sy).await?;
             }
         } else if let Some(other) = tile.creation {
-            if other.defend_against_attack(wizard.stats.get_combat()) {
-                if other.stats.magic_wood {
+            if other.defend_against_attack(wizard.stats.get_combat(), self.options.combat_model, &mut self.rng) {
+                if other.stats.magic_wood && tile.wizard.is_some() {
                     let wizard_id = state.arena.get_wizard(dx, dy).id;
                     self.tx.successful_attack(id, dx, dy, false).await?;
                     if state.wizards.check_for_winning_condition() {
@@ -832,7 +1070,7 @@ impl GameLogic {
                 self.check_for_wizard_ranged_combat(state, id, sx, sy).await?;
             }
         } else if let Some(ref other) = tile.wizard {
-            if other.defend_against_attack(wizard.stats.get_combat()) {
+            if other.defend_against_attack(wizard.stats.get_combat(), self.options.combat_model, &mut self.rng) {
                 self.tx.successful_attack(id, dx, dy, false).await?;
                 state.arena.kill_wizard_and_creations(other.id);
                 state.wizards.get_mut(other.id)?.alive = false;
@@ -854,16 +1092,13 @@ impl GameLogic {
 
     async fn creation_engaged_in_combat(&mut self, state: &mut ServerState, id: u32, sx: u8, sy: u8) -> Result<(), ChaosError> {
         state.arena.get_mut_creation(sx, sy).moves_left = 0;
-        let undead = state.arena.get_creation(sx, sy).stats.undead;
         loop {
             let tiles = state.arena.creation_combat_tiles(sx, sy, id);
             self.tx.engaged_in_combat(id, &tiles).await?;
             if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
-                if let Some(ref creation) = state.arena.get(dx, dy).creation {
-                    if !undead && creation.stats.undead {
-                        self.tx.undead_cannot_be_attacked(id).await?;
-                        continue;
-                    }
+                if state.arena.can_attack((sx, sy), (dx, dy), id, false) == AttackVerdict::Undead {
+                    self.tx.undead_cannot_be_attacked(id).await?;
+                    continue;
                 }
                 return self.creation_attack(state, id, sx, sy, dx, dy).await;
             } else {
@@ -878,11 +1113,9 @@ impl GameLogic {
             let tiles = state.arena.wizard_combat_tiles(x, y, id);
             self.tx.engaged_in_combat(id, &tiles).await?;
             if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
-                if let Some(ref creation) = state.arena.get(dx, dy).creation {
-                    if creation.stats.undead && state.arena.get_wizard(x, y).stats.attack_buff.is_none() {
-                        self.tx.undead_cannot_be_attacked(id).await?;
-                        continue;
-                    }
+                if state.arena.can_attack((x, y), (dx, dy), id, true) == AttackVerdict::Undead {
+                    self.tx.undead_cannot_be_attacked(id).await?;
+                    continue;
                 }
                 return self.wizard_attack(state, id, x, y, dx, dy).await;
             } else {
@@ -893,10 +1126,12 @@ impl GameLogic {
 
     async fn move_wizard(&mut self, state: &mut ServerState, id: u32, mut sx: u8, mut sy: u8) -> Result<(), ChaosError> {
         loop {
-            let tiles = state.arena.wizard_movement_tiles(sx, sy, id);
+            let mut tiles = state.arena.wizard_movement_tiles(sx, sy, id);
             if tiles.is_empty() {
-                return Ok(());
+                state.arena.get_mut_wizard(sx, sy).moves_left = 0;
+                return self.check_for_wizard_ranged_combat(state, id, sx, sy).await;
             }
+            tiles.push((sx, sy));
             let wizard = state.arena.get_wizard(sx, sy);
             let shadow_form = wizard.stats.shadow_form;
             if wizard.moves_left == wizard.stats.get_movement() {
@@ -905,16 +1140,19 @@ impl GameLogic {
                 self.tx.movement_points(id, wizard.moves_left, &tiles).await?;
             }
             match self.chosen_tile(state, id, tiles).await? {
+                Some(tile) if tile == (sx, sy) => {
+                    self.tx.movement_stopped(id).await?;
+                    state.arena.get_mut_wizard(sx, sy).moves_left = 0;
+                    return self.check_for_wizard_ranged_combat(state, id, sx, sy).await;
+                }
                 Some((dx, dy)) => {
                     let tile = state.arena.get(dx, dy);
                     if tile.spawn.is_some() {
                         return self.wizard_attack(state, id, sx, sy, dx, dy).await;
                     }
-                    if let Some(ref creation) = tile.creation {
-                        if creation.stats.undead && state.arena.get_wizard(sx, sy).stats.attack_buff.is_none() {
-                            self.tx.undead_cannot_be_attacked(id).await?;
-                            continue;
-                        }
+                    if state.arena.can_attack((sx, sy), (dx, dy), id, true) == AttackVerdict::Undead {
+                        self.tx.undead_cannot_be_attacked(id).await?;
+                        continue;
                     }
                     if tile.wizard.is_some()
                         || tile
@@ -960,18 +1198,17 @@ impl GameLogic {
         wizard.moves_left = 0;
         let movement = 6;
         loop {
-            let tiles = state.arena.wizard_flying_tiles(x, y, movement, id);
+            let mut tiles = state.arena.wizard_flying_tiles(x, y, movement, id);
             if tiles.is_empty() {
-                return Ok(());
+                return self.check_for_wizard_ranged_combat(state, id, x, y).await;
             }
+            tiles.push((x, y));
             self.tx.movement_range(id, movement, true, &tiles).await?;
-            if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+            if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await?.filter(|&tile| tile != (x, y)) {
                 let tile = state.arena.get(dx, dy);
-                if let Some(ref creation) = tile.creation {
-                    if creation.stats.undead && state.arena.get_wizard(x, y).stats.attack_buff.is_none() {
-                        self.tx.undead_cannot_be_attacked(id).await?;
-                        continue;
-                    }
+                if state.arena.can_attack((x, y), (dx, dy), id, true) == AttackVerdict::Undead {
+                    self.tx.undead_cannot_be_attacked(id).await?;
+                    continue;
                 }
                 if tile.spawn.is_some()
                     || tile.wizard.is_some()
@@ -999,10 +1236,12 @@ impl GameLogic {
 
     async fn move_creation(&mut self, state: &mut ServerState, id: u32, mut sx: u8, mut sy: u8) -> Result<(), ChaosError> {
         loop {
-            let tiles = state.arena.creation_movement_tiles(sx, sy, id);
+            let mut tiles = state.arena.creation_movement_tiles(sx, sy, id);
             if tiles.is_empty() {
-                return Ok(());
+                state.arena.get_mut_creation(sx, sy).moves_left = 0;
+                return self.check_for_creation_ranged_combat(state, id, sx, sy).await;
             }
+            tiles.push((sx, sy));
             let creation = state.arena.get_creation(sx, sy);
             if creation.moves_left == creation.stats.base.movement {
                 self.tx
@@ -1012,16 +1251,19 @@ impl GameLogic {
                 self.tx.movement_points(id, creation.moves_left, &tiles).await?;
             }
             match self.chosen_tile(state, id, tiles).await? {
+                Some(tile) if tile == (sx, sy) => {
+                    self.tx.movement_stopped(id).await?;
+                    state.arena.get_mut_creation(sx, sy).moves_left = 0;
+                    return self.check_for_creation_ranged_combat(state, id, sx, sy).await;
+                }
                 Some((dx, dy)) => {
                     let tile = state.arena.get(dx, dy);
                     if tile.spawn.is_some() {
                         return self.creation_attack(state, id, sx, sy, dx, dy).await;
                     }
-                    if let Some(ref creation) = tile.creation {
-                        if creation.stats.undead && !state.arena.get_creation(sx, sy).stats.undead {
-                            self.tx.undead_cannot_be_attacked(id).await?;
-                            continue;
-                        }
+                    if state.arena.can_attack((sx, sy), (dx, dy), id, false) == AttackVerdict::Undead {
+                        self.tx.undead_cannot_be_attacked(id).await?;
+                        continue;
                     }
                     if tile.wizard.is_some() || tile.creation.is_some() {
                         return self.creation_attack(state, id, sx, sy, dx, dy).await;
@@ -1054,18 +1296,17 @@ impl GameLogic {
         creation.moves_left = 0;
         let movement = creation.stats.base.movement;
         loop {
-            let tiles = state.arena.creation_flying_tiles(x, y, movement, id);
+            let mut tiles = state.arena.creation_flying_tiles(x, y, movement, id);
             if tiles.is_empty() {
-                return Ok(());
+                return self.check_for_creation_ranged_combat(state, id, x, y).await;
             }
+            tiles.push((x, y));
             self.tx.movement_range(id, movement, true, &tiles).await?;
-            if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
+            if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await?.filter(|&tile| tile != (x, y)) {
                 let tile = state.arena.get(dx, dy);
-                if let Some(ref creation) = tile.creation {
-                    if creation.stats.undead && !state.arena.get_creation(x, y).stats.undead {
-                        self.tx.undead_cannot_be_attacked(id).await?;
-                        continue;
-                    }
+                if state.arena.can_attack((x, y), (dx, dy), id, false) == AttackVerdict::Undead {
+                    self.tx.undead_cannot_be_attacked(id).await?;
+                    continue;
                 }
                 if tile.spawn.is_some() || tile.wizard.is_some() || tile.creation.is_some() {
                     return self.creation_attack(state, id, x, y, dx, dy).await;
@@ -1091,7 +1332,11 @@ impl GameLogic {
                 _ = &mut self.quit_rx => {
                     self.tx.shutdown().await?;
                 }
-                Some(msg) = self.rx.recv() => {
+                Some(msg) = self.inbox.recv() => {
+                    let msg = match self.hold_while_paused(msg).await? {
+                        Some(msg) => msg,
+                        None => continue,
+                    };
                     match msg {
                         RecieveMsg::Disconnected { id: disconnected_id } => {
                             state.wizards.get_mut(disconnected_id)?.disconnected = true;
@@ -1122,7 +1367,7 @@ impl GameLogic {
         let color = creation.projectile_color();
         let tile = state.arena.get(dx, dy).clone();
         if let Some(Spawn::Blob(blob)) = tile.spawn {
-            if blob.defend_against_attack(creation.stats.base.ranged_combat) {
+            if blob.defend_against_attack(creation.stats.base.ranged_combat, self.options.combat_model, &mut self.rng) {
                 if creation.stats.dragon {
                     self.tx.successful_dragon_ranged_attack(id, sx, sy, dx, dy).await?;
                 } else {
@@ -1132,9 +1377,9 @@ impl GameLogic {
                 return Ok(());
             }
         } else if let Some(other) = tile.creation {
-            if other.stats.undead && !creation.stats.undead {
+            if state.arena.can_attack((sx, sy), (dx, dy), id, false) == AttackVerdict::Undead {
                 self.tx.undead_cannot_be_attacked(id).await?;
-            } else if other.defend_against_attack(creation.stats.base.ranged_combat) {
+            } else if other.defend_against_attack(creation.stats.base.ranged_combat, self.options.combat_model, &mut self.rng) {
                 if other.stats.magic_wood && tile.wizard.is_some() {
                     let wizard_id = state.arena.get_wizard(dx, dy).id;
                     if creation.stats.dragon {
@@ -1158,7 +1403,7 @@ impl GameLogic {
                 }
             }
         } else if let Some(wizard) = tile.wizard {
-            if wizard.defend_against_attack(creation.stats.base.ranged_combat) {
+            if wizard.defend_against_attack(creation.stats.base.ranged_combat, self.options.combat_model, &mut self.rng) {
                 if creation.stats.dragon {
                     self.tx.successful_dragon_ranged_attack(id, sx, sy, dx, dy).await?;
                 } else {
@@ -1189,7 +1434,7 @@ impl GameLogic {
         let wizard = state.arena.get_wizard(sx, sy);
         let tile = state.arena.get(dx, dy).clone();
         if let Some(Spawn::Blob(blob)) = tile.spawn {
-            if blob.defend_against_attack(wizard.stats.get_ranged_combat()) {
+            if blob.defend_against_attack(wizard.stats.get_ranged_combat(), self.options.combat_model, &mut self.rng) {
                 self.tx
                     .successful_ranged_attack(id, sx, sy, dx, dy, false, BrightWhite)
                     .await?;
@@ -1197,7 +1442,7 @@ impl GameLogic {
                 return Ok(());
             }
         } else if let Some(other) = tile.creation {
-            if other.defend_against_attack(wizard.stats.get_ranged_combat()) {
+            if other.defend_against_attack(wizard.stats.get_ranged_combat(), self.options.combat_model, &mut self.rng) {
                 if other.stats.magic_wood && tile.wizard.is_some() {
                     self.tx
                         .successful_ranged_attack(id, sx, sy, dx, dy, false, BrightWhite)
@@ -1215,7 +1460,7 @@ impl GameLogic {
                 }
             }
         } else if let Some(other) = tile.wizard {
-            if other.defend_against_attack(wizard.stats.get_ranged_combat()) {
+            if other.defend_against_attack(wizard.stats.get_ranged_combat(), self.options.combat_model, &mut self.rng) {
                 self.tx
                     .successful_ranged_attack(id, sx, sy, dx, dy, false, BrightWhite)
                     .await?;
@@ -1237,12 +1482,13 @@ impl GameLogic {
     ) -> Result<(), ChaosError> {
         let creation = state.arena.get_creation(x, y);
         let range = creation.stats.base.range;
-        if range > 0 {
+        if range > 0 && !creation.ranged_used {
             loop {
                 let tiles = state.arena.ranged_combat_tiles(x, y, range);
-                self.tx.choose_ranged_combat(id, range, &tiles).await?;
+                self.tx.choose_ranged_combat(id, x, y, range, &tiles).await?;
                 if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
                     if state.arena.line_of_sight(x, y, dx, dy) {
+                        state.arena.get_mut_creation(x, y).ranged_used = true;
                         return self.creation_ranged_combat(state, id, x, y, dx, dy).await;
                     } else {
                         self.tx.no_line_of_sight(id).await?;
@@ -1258,12 +1504,13 @@ impl GameLogic {
     async fn check_for_wizard_ranged_combat(&mut self, state: &mut ServerState, id: u32, x: u8, y: u8) -> Result<(), ChaosError> {
         let wizard = state.arena.get_wizard(x, y);
         let range = wizard.stats.get_range();
-        if range > 0 {
+        if range > 0 && !wizard.ranged_used {
             loop {
                 let tiles = state.arena.ranged_combat_tiles(x, y, range);
-                self.tx.choose_ranged_combat(id, range, &tiles).await?;
+                self.tx.choose_ranged_combat(id, x, y, range, &tiles).await?;
                 if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
                     if state.arena.line_of_sight(x, y, dx, dy) {
+                        state.arena.get_mut_wizard(x, y).ranged_used = true;
                         return self.wizard_ranged_combat(state, id, x, y, dx, dy).await;
                     } else {
                         self.tx.no_line_of_sight(id).await?;
@@ -1280,11 +1527,11 @@ impl GameLogic {
         for (dx, dy) in state.arena.neighbouring_foes(x, y, id) {
             let tile = state.arena.get(dx, dy).clone();
             if let Some(other) = tile.creation {
-                if other.is_engaged(manoeuvre) {
+                if other.is_engaged(manoeuvre, self.options.combat_model, &mut self.rng) {
                     return Ok(true);
                 }
             } else if let Some(other) = tile.wizard {
-                if other.is_engaged(manoeuvre) {
+                if other.is_engaged(manoeuvre, self.options.combat_model, &mut self.rng) {
                     return Ok(true);
                 }
             } else {
@@ -1296,7 +1543,6 @@ impl GameLogic {
 
     pub async fn shadow_wood_attack(&mut self, state: &mut ServerState, id: u32, x: u8, y: u8) -> Result<(), ChaosError> {
         state.arena.get_mut_creation(x, y).moves_left = 0;
-        let undead = state.arena.get_creation(x, y).stats.undead;
         loop {
             let tiles = state.arena.creation_combat_tiles(x, y, id);
             if tiles.is_empty() {
@@ -1305,11 +1551,9 @@ impl GameLogic {
             }
             self.tx.engaged_in_combat(id, &tiles).await?;
             if let Some((dx, dy)) = self.chosen_tile(state, id, tiles).await? {
-                if let Some(ref creation) = state.arena.get(dx, dy).creation {
-                    if !undead && creation.stats.undead {
-                        self.tx.undead_cannot_be_attacked(id).await?;
-                        continue;
-                    }
+                if state.arena.can_attack((x, y), (dx, dy), id, false) == AttackVerdict::Undead {
+                    self.tx.undead_cannot_be_attacked(id).await?;
+                    continue;
                 }
                 return self.creation_attack(state, id, x, y, dx, dy).await;
             } else {
@@ -1318,22 +1562,24 @@ impl GameLogic {
         }
     }
 
-    pub async fn movement_loop(&mut self, state: &mut ServerState, id: u32) -> Result<(), ChaosError> {
+    pub async fn movement_loop(&mut self, state: &mut ServerState, id: u32) -> Result<bool, ChaosError> {
         state.arena.reset_moves(id);
+        let mut acted = false;
         loop {
             if !state.wizards.is_alive(id)? {
-                return Ok(());
+                return Ok(acted);
             }
             if state.wizards.check_for_winning_condition() {
-                return Ok(());
+                return Ok(acted);
             }
             let tiles = state.arena.tiles_with_moves_left(id);
             if tiles.is_empty() {
-                return Ok(());
+                return Ok(acted);
             }
             self.tx.choose_piece(id, &tiles).await?;
             match self.chosen_tile(state, id, tiles).await? {
                 Some((x, y)) => {
+                    acted = true;
                     let tile = state.arena.get(x, y).clone();
                     if let Some(GameCreation {
                         stats:
@@ -1356,7 +1602,7 @@ impl GameLogic {
                             self.creation_engaged_in_combat(state, id, x, y).await?;
                             continue;
                         }
-                        if tile.wizard.is_some() {
+                        if tile.wizard.as_ref().is_some_and(|wizard| wizard.moves_left > 0) {
                             if magic_wood {
                                 self.move_wizard(state, id, x, y).await?;
                                 continue;
@@ -1394,7 +1640,7 @@ impl GameLogic {
                         unreachable!();
                     }
                 }
-                None => return Ok(()),
+                None => return Ok(acted),
             }
         }
     }
@@ -1403,7 +1649,7 @@ impl GameLogic {
         let tile = state.arena.get(x, y).clone();
         if let Some(ref creation) = tile.creation {
             if creation.id != fire.id && creation.stats.attackable {
-                if creation.defend_against_attack(5) {
+                if creation.defend_against_attack(5, self.options.combat_model, &mut self.rng) {
                     state.arena.kill_creation(x, y, false);
                     if tile.wizard.is_none() {
                         self.tx.spawn_fire(x, y, Some(fire)).await?;
@@ -1415,7 +1661,7 @@ impl GameLogic {
             }
         } else if let Some(ref wizard) = tile.wizard {
             if wizard.id != fire.id {
-                if wizard.defend_against_attack(5) {
+                if wizard.defend_against_attack(5, self.options.combat_model, &mut self.rng) {
                     state.arena.kill_wizard_and_creations(wizard.id);
                     state.wizards.get_mut(wizard.id).unwrap().alive = false;
                     self.tx.spawn_fire(x, y, Some(fire)).await?;
@@ -1443,7 +1689,7 @@ impl GameLogic {
             }
         } else if let Some(ref wizard) = tile.wizard {
             if wizard.id != blob.id {
-                if wizard.defend_against_attack(5) {
+                if wizard.defend_against_attack(5, self.options.combat_model, &mut self.rng) {
                     state.arena.kill_wizard_and_creations(wizard.id);
                     state.wizards.get_mut(wizard.id).unwrap().alive = false;
                     self.tx.spawn_blob(x, y, Some(blob)).await?;
@@ -1452,7 +1698,7 @@ impl GameLogic {
                         return Ok(());
                     }
                 } else {
-                    self.tx.spawn_fire(x, y, None).await?;
+                    self.tx.spawn_blob(x, y, None).await?;
                 }
             }
         } else {
@@ -1463,10 +1709,9 @@ impl GameLogic {
     }
 
     async fn do_fire(&mut self, state: &mut ServerState) -> Result<(), ChaosError> {
-        let mut rng = StdRng::from_entropy();
         for (x, y) in state.arena.all_spawn_tiles() {
             if let Some(spawn) = state.arena.get(x, y).spawn.clone() {
-                match rng.gen_range(0..=9) {
+                match self.rng.gen_range(0..=9) {
                     0 | 1 => {
                         self.tx.remove_spawn(x, y).await?;
                         state.arena.remove_spawn(x, y);
@@ -1579,7 +1824,7 @@ impl GameLogic {
 
     async fn do_shelter_turn(&mut self, state: &mut ServerState) -> Result<(), ChaosError> {
         for (x, y) in state.arena.all_combustable_shelter_tiles() {
-            if state.arena.get_creation(x, y).should_disappear() {
+            if state.arena.get_creation(x, y).should_disappear(&mut self.rng) {
                 self.tx.shelter_disappears(x, y).await?;
                 state.arena.kill_creation(x, y, false);
             }
@@ -1588,13 +1833,12 @@ impl GameLogic {
     }
 
     async fn do_magic_wood(&mut self, state: &mut ServerState) -> Result<(), ChaosError> {
-        let mut rng = StdRng::from_entropy();
         for (x, y) in state.arena.wizards_in_trees() {
-            if rng.gen_range(0..=9) >= 8 {
+            if self.rng.gen_range(0..100) < self.options.magic_wood_chance {
                 let id = state.arena.get_wizard(x, y).id;
                 let server_wizard = state.wizards.get_mut(id)?;
-                if server_wizard.spells.len() < 20 {
-                    let random_spell = Spell::random();
+                if server_wizard.spells.len() < self.options.max_spells as usize {
+                    let random_spell = Spell::random(&self.options.banned_spells, &mut self.rng);
                     let wizard = state.arena.get_mut_wizard(x, y);
                     wizard.stats.number_of_spells += 1;
                     self.tx.debuff_wizard(wizard.id, &wizard.stats).await?;
@@ -1609,10 +1853,84 @@ impl GameLogic {
     }
 
     pub async fn game_loop(&mut self, wizards: LobbyWizards) -> Result<Vec<Player>, ChaosError> {
+        SERVER_STATS.reset();
         let mut state = ServerState {
-            wizards: wizards.into(),
+            wizards: ServerWizards::new(wizards, &mut self.rng),
             arena: Arena::new(),
         };
+        self.play(&mut state).await?;
+        if self.options.campaign {
+            self.record_campaign(&state);
+        }
+        Ok(state.wizards.winners())
+    }
+
+    fn is_stranded(state: &ServerState, id: u32) -> Result<bool, ChaosError> {
+        if state.wizards.get(id)?.spells.len() > 1 {
+            return Ok(false);
+        }
+        let (x, y) = match state.arena.maybe_find_wizard_pos(id) {
+            Some(pos) => pos,
+            None => return Ok(false),
+        };
+        let wizard = state.arena.get_wizard(x, y);
+        if wizard.stats.magic_wings || wizard.stats.magic_bow {
+            return Ok(false);
+        }
+        let sheltered = state.arena.get(x, y).creation.is_some();
+        Ok(state.arena.creation_count(id) == usize::from(sheltered) && state.arena.wizard_movement_tiles(x, y, id).is_empty())
+    }
+
+    fn record_campaign(&mut self, state: &ServerState) {
+        let mut campaign = self.campaign.lock().expect("campaign");
+        let survivors = state
+            .wizards
+            .iter()
+            .filter(|wizard| wizard.alive && !wizard.disconnected)
+            .count();
+        for wizard in state.wizards.iter() {
+            let level = campaign
+                .iter()
+                .find(|progress| progress.player == wizard.player)
+                .map_or(self.options.starting_level.min(8), |progress| progress.level);
+            campaign.retain(|progress| progress.player != wizard.player);
+            let mut progress = CampaignWizard {
+                player: wizard.player.clone(),
+                level,
+                spells: Vec::new(),
+                attack_buff: None,
+                defence_buff: None,
+                magic_wings: false,
+                magic_bow: false,
+                shadow_form: false,
+            };
+            if wizard.alive && !wizard.disconnected {
+                if survivors == 1 {
+                    progress.level = (level + 1).min(8);
+                }
+                progress.spells = wizard.spells.iter().skip(1).map(|spell| spell.name.clone()).collect();
+                if let Some((x, y)) = state.arena.maybe_find_wizard_pos(wizard.id) {
+                    let stats = &state.arena.get_wizard(x, y).stats;
+                    progress.attack_buff = stats.attack_buff.clone();
+                    progress.defence_buff = stats.defence_buff.clone();
+                    progress.magic_wings = stats.magic_wings;
+                    progress.magic_bow = stats.magic_bow;
+                    progress.shadow_form = stats.shadow_form;
+                }
+            }
+            campaign.push(progress);
+        }
+    }
+
+    fn turn_order(&mut self, state: &ServerState) -> Vec<u32> {
+        let mut order = state.wizards.all_active_ids();
+        if self.options.shuffle_turn_order {
+            order.shuffle(&mut self.rng);
+        }
+        order
+    }
+
+    async fn play(&mut self, state: &mut ServerState) -> Result<(), ChaosError> {
         self.tx.send_wizards(&state.wizards).await?;
         for (x, y, wizard) in state.wizards.starting_positions()? {
             let game_wizard = GameWizard::from(wizard);
@@ -1620,38 +1938,1599 @@ impl GameLogic {
             state.arena.get_mut(x, y).wizard = Some(game_wizard);
         }
         let number_of_turns = state.wizards.len() * 2 + 15;
+        let mut idle_rounds = 0;
+        let mut stranded = HashSet::new();
         for _ in 0..number_of_turns {
-            let spells = self.select_spells(&mut state).await?;
-            for (id, spell, illusion) in spells {
-                self.do_spell(&mut state, id, spell, illusion).await?;
-                if state.wizards.check_for_winning_condition() {
-                    return Ok(state.wizards.winners());
+            let occupancy = state.arena.occupancy();
+            let order = self.turn_order(state);
+            for id in order.iter().copied() {
+                let now_stranded = Self::is_stranded(state, id)?;
+                if now_stranded != stranded.contains(&id) {
+                    if now_stranded {
+                        stranded.insert(id);
+                    } else {
+                        stranded.remove(&id);
+                    }
+                    self.tx.stranded(id, now_stranded).await?;
                 }
             }
-            self.do_shelter_turn(&mut state).await?;
-            self.do_magic_wood(&mut state).await?;
-            self.do_fire(&mut state).await?;
-            for id in state.wizards.all_active_ids() {
-                if !state.wizards.is_alive(id)? {
+            if self.options.shuffle_turn_order {
+                self.tx.turn_order(&order).await?;
+            }
+            crash_dump::record_state(state);
+            self.sync_animations(state).await?;
+            let mut spells = self.select_spells(state).await?;
+            self.shutdown_if_abandoned(state).await?;
+            spells.sort_by_key(|(id, _, _)| order.iter().position(|order_id| order_id == id));
+            let mut acted = !spells.is_empty();
+            self.cast_spells(state, spells).await?;
+            if state.wizards.check_for_winning_condition() {
+                return Ok(());
+            }
+            self.tx.spell_phase_end().await?;
+            self.do_shelter_turn(state).await?;
+            self.do_magic_wood(state).await?;
+            self.do_fire(state).await?;
+            for id in order {
+                if !state.wizards.is_alive(id)? || state.wizards.has_disconnected(id)? {
                     continue;
                 }
                 if state.wizards.get(id)?.alive {
+                    crash_dump::record_state(state);
+                    self.sync_animations(state).await?;
                     self.tx.turn(id).await?;
-                    self.movement_loop(&mut state, id).await?;
+                    acted |= self.movement_loop(state, id).await?;
+                    self.shutdown_if_abandoned(state).await?;
                     if state.wizards.check_for_winning_condition() {
-                        return Ok(state.wizards.winners());
+                        return Ok(());
                     }
                 }
             }
             self.tx.turn_end().await?;
+            self.tx.state_checksum(state.arena.checksum()).await?;
+            if acted || occupancy != state.arena.occupancy() {
+                idle_rounds = 0;
+            } else {
+                idle_rounds += 1;
+                if idle_rounds == self.options.stalemate_rounds {
+                    break;
+                }
+            }
         }
-        Ok(state.wizards.winners())
+        Ok(())
     }
 
-    pub async fn end(mut self, winners: Vec<Player>) -> Result<(), ChaosError> {
+    pub async fn end(&mut self, winners: Vec<Player>) -> Result<(), ChaosError> {
+        crash_dump::clear();
         self.tx.results(&winners).await?;
-        self.quit_rx.await.ok();
-        self.tx.shutdown().await.ok();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::spells::find_spells;
+    use crate::data::stats::{CombatModel, STAT_CAP};
+    use std::future::Future;
+
+    struct Table {
+        logic: GameLogic,
+        input: mpsc::Sender<RecieveMsg>,
+        output: mpsc::Receiver<SendMsg>,
+        _quit: oneshot::Sender<()>,
+    }
+
+    fn table(options: HostOptions) -> Table {
+        let options = HostOptions {
+            seed: options.seed.or(Some(0)),
+            ..options
+        };
+        let (input, rx) = mpsc::channel(1024);
+        let (tx, output) = mpsc::channel(1024);
+        let (quit, quit_rx) = oneshot::channel();
+        Table {
+            logic: GameLogic::new(rx, tx, quit_rx, options, Arc::default()),
+            input,
+            output,
+            _quit: quit,
+        }
+    }
+
+    fn deterministic() -> HostOptions {
+        HostOptions {
+            combat_model: CombatModel::Deterministic,
+            ..HostOptions::default()
+        }
+    }
+
+    fn player(id: u32) -> Player {
+        Player {
+            name: format!("WIZARD {id}"),
+            character: (id as isize % 8).try_into().expect("character"),
+            color: (id as isize % 8).try_into().expect("color"),
+        }
+    }
+
+    fn lobby(players: u32) -> LobbyWizards {
+        let mut wizards = LobbyWizards::new();
+        wizards.max_spells = 20;
+        for id in 0..players {
+            wizards.join(id, player(id));
+            wizards.ready(id, true);
+        }
+        wizards
+    }
+
+    fn scenario(positions: &[(u8, u8)]) -> ServerState {
+        let wizards = ServerWizards::new(lobby(positions.len() as u32), &mut StdRng::seed_from_u64(0));
+        let mut arena = Arena::new();
+        for (wizard, &(x, y)) in wizards.iter().zip(positions) {
+            arena.get_mut(x, y).wizard = Some(GameWizard::from(wizard));
+        }
+        ServerState { wizards, arena }
+    }
+
+    fn spell(name: &str) -> Spell {
+        find_spells(&[name.to_string()], &[]).remove(0)
+    }
+
+    fn creation(id: u32, name: &str) -> GameCreation {
+        match spell(name).kind {
+            SpellKind::Creation(stats) => GameCreation::new(id, stats),
+            _ => panic!("{name} is not a creation"),
+        }
+    }
+
+    fn placed(id: u32, name: &str) -> GameCreation {
+        match spell(name).kind {
+            SpellKind::MagicWood(stats) | SpellKind::ShadowWood(stats) | SpellKind::Wall(stats) => GameCreation::new(id, stats),
+            _ => panic!("{name} is not placed like a wood or wall"),
+        }
+    }
+
+    fn choose(id: u32, tiles: &[(u8, u8)], tile: (u8, u8)) -> Option<RecieveMsg> {
+        let index = tiles.iter().position(|&other| other == tile)?;
+        Some(RecieveMsg::Message {
+            id,
+            msg: Message::ChosenTile(Some(index as u8)),
+        })
+    }
+
+    fn target(tile: (u8, u8)) -> impl FnMut(u32, &Message) -> Option<RecieveMsg> {
+        move |to, msg| match msg {
+            Message::ChooseTarget(tiles) => choose(to, tiles, tile),
+            _ => None,
+        }
+    }
+
+    fn passive(_: u32, _: &Message) -> Option<RecieveMsg> {
+        None
+    }
+
+    async fn run<T>(
+        logic: impl Future<Output = T>,
+        input: &mpsc::Sender<RecieveMsg>,
+        output: &mut mpsc::Receiver<SendMsg>,
+        mut respond: impl FnMut(u32, &Message) -> Option<RecieveMsg>,
+    ) -> (T, Vec<SendMsg>) {
+        tokio::pin!(logic);
+        let mut sent = Vec::new();
+        loop {
+            select! {
+                biased;
+                Some(msg) = output.recv() => {
+                    if let SendMsg::MessageToId { to, msg: ref message, .. } = msg {
+                        let reply = respond(to, message)
+                            .or_else(|| message.pass_reply().map(|reply| RecieveMsg::Message { id: to, msg: reply }));
+                        if let Some(reply) = reply {
+                            input.try_send(reply).expect("reply");
+                        }
+                    }
+                    sent.push(msg);
+                }
+                result = &mut logic => {
+                    while let Ok(msg) = output.try_recv() {
+                        sent.push(msg);
+                    }
+                    return (result, sent);
+                }
+            }
+        }
+    }
+
+    fn to_all(sent: &[SendMsg]) -> Vec<Message> {
+        sent.iter()
+            .filter_map(|msg| match msg {
+                SendMsg::MessageToAll { msg, .. } => Some(msg.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn cast_spell(spell: &Spell) -> Message {
+        Message::CastSpell {
+            spell_name: spell.name.clone(),
+            range: spell.range,
+        }
+    }
+
+    #[tokio::test]
+    async fn magic_bolt_kills_a_wizard_and_wins() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (4, 4)]);
+        state.arena.get_mut_wizard(4, 4).stats.base.defence = 3;
+        let mut bolt = spell("MAGIC BOLT");
+        bolt.chance = STAT_CAP;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, bolt.clone(), false),
+            &input,
+            &mut output,
+            target((4, 4)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(
+            to_all(&sent),
+            vec![
+                cast_spell(&bolt),
+                Message::MagicBolt {
+                    x: 4,
+                    y: 4,
+                    success: true
+                }
+            ]
+        );
+        assert!(state.arena.get(4, 4).wizard.is_none());
+        assert!(!state.wizards.is_alive(1).unwrap());
+        assert!(state.wizards.check_for_winning_condition());
+    }
+
+    #[tokio::test]
+    async fn lightning_kills_a_wizard_and_wins() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (4, 4)]);
+        state.arena.get_mut_wizard(4, 4).stats.base.defence = 3;
+        state.arena.get_mut(6, 6).creation = Some(creation(1, "GOBLIN"));
+        let mut lightning = spell("LIGHTNING");
+        lightning.chance = STAT_CAP;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, lightning.clone(), false),
+            &input,
+            &mut output,
+            target((4, 4)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(to_all(&sent).contains(&Message::Lightning {
+            x: 4,
+            y: 4,
+            success: true
+        }));
+        assert!(state.arena.get(4, 4).wizard.is_none());
+        assert!(state.arena.get(6, 6).creation.is_none());
+        assert!(!state.wizards.is_alive(1).unwrap());
+        assert!(state.wizards.check_for_winning_condition());
+    }
+
+    #[tokio::test]
+    async fn magic_bolt_miscast_sends_spell_fails() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (4, 4)]);
+        state.arena.get_mut_wizard(1, 4).stats.spell_ability = 0;
+        let mut bolt = spell("MAGIC BOLT");
+        // the first casting roll with seed 0 is above zero
+        bolt.chance = 0;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, bolt.clone(), false),
+            &input,
+            &mut output,
+            target((4, 4)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(to_all(&sent), vec![cast_spell(&bolt), Message::SpellFails]);
+        assert!(state.arena.get(4, 4).wizard.is_some());
+    }
+
+    #[tokio::test]
+    async fn lightning_is_resisted_by_a_strong_defence() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (4, 4)]);
+        state.arena.get_mut_wizard(4, 4).stats.base.defence = 7;
+        let mut lightning = spell("LIGHTNING");
+        lightning.chance = STAT_CAP;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, lightning.clone(), false),
+            &input,
+            &mut output,
+            target((4, 4)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(
+            to_all(&sent),
+            vec![
+                cast_spell(&lightning),
+                Message::SpellSucceeds(lightning.alignment),
+                Message::Lightning {
+                    x: 4,
+                    y: 4,
+                    success: false
+                }
+            ]
+        );
+        assert!(state.wizards.is_alive(1).unwrap());
+    }
+
+    #[tokio::test]
+    async fn lightning_kills_a_creation() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        state.arena.get_mut(3, 4).creation = Some(creation(1, "GOBLIN"));
+        let mut lightning = spell("LIGHTNING");
+        lightning.chance = STAT_CAP;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, lightning.clone(), false),
+            &input,
+            &mut output,
+            target((3, 4)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(to_all(&sent).contains(&Message::Lightning {
+            x: 3,
+            y: 4,
+            success: true
+        }));
+        assert!(state.arena.get(3, 4).creation.is_none());
+        assert!(state.arena.get(3, 4).corpse.is_none());
+    }
+
+    #[tokio::test]
+    async fn subversion_takes_over_a_real_creation() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        state.arena.get_mut_wizard(1, 4).stats.spell_ability = STAT_CAP;
+        state.arena.get_mut(3, 4).creation = Some(creation(1, "GOBLIN"));
+        let mut subversion = spell("SUBVERSION");
+        subversion.chance = STAT_CAP;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, subversion.clone(), false),
+            &input,
+            &mut output,
+            target((3, 4)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(
+            to_all(&sent),
+            vec![
+                cast_spell(&subversion),
+                Message::SpellSucceeds(subversion.alignment),
+                Message::Subversion {
+                    x: 3,
+                    y: 4,
+                    success: true
+                }
+            ]
+        );
+        assert_eq!(state.arena.get_creation(3, 4).id, 0);
+    }
+
+    #[tokio::test]
+    async fn subversion_fails_on_an_illusion() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        state.arena.get_mut_wizard(1, 4).stats.spell_ability = STAT_CAP;
+        let mut goblin = creation(1, "GOBLIN");
+        goblin.illusion = true;
+        state.arena.get_mut(3, 4).creation = Some(goblin);
+        let mut subversion = spell("SUBVERSION");
+        subversion.chance = STAT_CAP;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, subversion.clone(), false),
+            &input,
+            &mut output,
+            target((3, 4)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(to_all(&sent), vec![cast_spell(&subversion), Message::SpellFails]);
+        assert_eq!(state.arena.get_creation(3, 4).id, 1);
+    }
+
+    #[tokio::test]
+    async fn raise_dead_turns_a_corpse_into_an_undead_creation() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        state.arena.get_mut_wizard(1, 4).stats.spell_ability = STAT_CAP;
+        state.arena.get_mut(3, 4).corpse = Some(creation(1, "GOBLIN"));
+        let mut raise_dead = spell("RAISE DEAD");
+        raise_dead.chance = STAT_CAP;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, raise_dead.clone(), false),
+            &input,
+            &mut output,
+            target((3, 4)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(
+            to_all(&sent),
+            vec![
+                cast_spell(&raise_dead),
+                Message::RaiseDead {
+                    x: 3,
+                    y: 4,
+                    success: true
+                },
+                Message::SpellSucceeds(raise_dead.alignment)
+            ]
+        );
+        let tile = state.arena.get(3, 4);
+        assert!(tile.corpse.is_none());
+        let raised = tile.creation.as_ref().unwrap();
+        assert_eq!(raised.id, 0);
+        assert!(raised.stats.undead);
+    }
+
+    #[tokio::test]
+    async fn raise_dead_without_corpses_has_no_targets() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        let raise_dead = spell("RAISE DEAD");
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, raise_dead.clone(), false),
+            &input,
+            &mut output,
+            passive,
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(sent.iter().any(|msg| matches!(
+            msg,
+            SendMsg::MessageToId {
+                to: 0,
+                msg: Message::NoPossibleMoves,
+                ..
+            }
+        )));
+    }
+
+    #[tokio::test]
+    async fn a_corpse_under_a_creation_is_raised_once_the_creation_is_gone() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        state.arena.get_mut_wizard(1, 4).stats.spell_ability = STAT_CAP;
+        state.arena.get_mut(3, 4).corpse = Some(creation(1, "GOBLIN"));
+        state.arena.get_mut(3, 4).creation = Some(creation(1, "GORILLA"));
+        let mut raise_dead = spell("RAISE DEAD");
+        raise_dead.chance = STAT_CAP;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, raise_dead.clone(), false),
+            &input,
+            &mut output,
+            passive,
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(sent_to(&sent, 0).contains(&Message::NoPossibleMoves));
+        state.arena.kill_creation(3, 4, false);
+        state.arena.kill_creation(3, 4, true);
+        assert_eq!(state.arena.get(3, 4).corpse.as_ref().unwrap().stats.base.name, "GOBLIN");
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, raise_dead.clone(), false),
+            &input,
+            &mut output,
+            target((3, 4)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(to_all(&sent).contains(&Message::RaiseDead {
+            x: 3,
+            y: 4,
+            success: true
+        }));
+        let raised = state.arena.get_creation(3, 4);
+        assert_eq!((raised.id, raised.stats.base.name.as_str()), (0, "GOBLIN"));
+        assert!(raised.stats.undead);
+    }
+
+    #[tokio::test]
+    async fn attack_buff_changes_the_casters_stats() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        let mut knife = spell("MAGIC KNIFE");
+        knife.chance = STAT_CAP;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, knife.clone(), false),
+            &input,
+            &mut output,
+            passive,
+        )
+        .await;
+        assert!(result.is_ok());
+        let stats = state.arena.get_wizard(1, 4).stats.clone();
+        assert_eq!(stats.attack_buff, Some(AttackBuff::MagicKnife));
+        assert_eq!(
+            to_all(&sent),
+            vec![
+                cast_spell(&knife),
+                Message::BuffWizard(stats),
+                Message::SpellSucceeds(knife.alignment)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn attack_buff_miscast_leaves_the_caster_unchanged() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        state.arena.get_mut_wizard(1, 4).stats.spell_ability = 0;
+        let before = state.arena.get_wizard(1, 4).stats.clone();
+        let mut sword = spell("MAGIC SWORD");
+        sword.chance = 0;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, sword.clone(), false),
+            &input,
+            &mut output,
+            passive,
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(to_all(&sent), vec![cast_spell(&sword), Message::SpellFails]);
+        assert_eq!(state.arena.get_wizard(1, 4).stats, before);
+    }
+
+    fn turn_ends(sent: &[SendMsg]) -> usize {
+        to_all(sent).iter().filter(|msg| **msg == Message::TurnEnd).count()
+    }
+
+    #[tokio::test]
+    async fn passive_wizards_stalemate_after_the_configured_rounds() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(HostOptions {
+            stalemate_rounds: 2,
+            ..HostOptions::default()
+        });
+        let (result, sent) = run(logic.game_loop(lobby(2)), &input, &mut output, passive).await;
+        assert_eq!(result.unwrap().len(), 2);
+        assert_eq!(turn_ends(&sent), 2);
+    }
+
+    #[tokio::test]
+    async fn stalemate_detection_is_off_by_default() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(HostOptions::default());
+        let (result, sent) = run(logic.game_loop(lobby(2)), &input, &mut output, passive).await;
+        assert_eq!(result.unwrap().len(), 2);
+        assert_eq!(turn_ends(&sent), 2 * 2 + 15);
+    }
+
+    #[tokio::test]
+    async fn the_spell_phase_ends_once_a_round_when_everyone_passes() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(HostOptions {
+            stalemate_rounds: 2,
+            ..HostOptions::default()
+        });
+        let (result, sent) = run(logic.game_loop(lobby(2)), &input, &mut output, passive).await;
+        assert_eq!(result.unwrap().len(), 2);
+        let phases = to_all(&sent)
+            .into_iter()
+            .filter(|msg| matches!(msg, Message::SpellPhaseEnd | Message::TurnEnd | Message::CastSpell { .. }))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            phases,
+            vec![
+                Message::SpellPhaseEnd,
+                Message::TurnEnd,
+                Message::SpellPhaseEnd,
+                Message::TurnEnd
+            ]
+        );
+    }
+
+    fn eager() -> impl FnMut(u32, &Message) -> Option<RecieveMsg> {
+        let mut hands = HashMap::new();
+        let mut last_prompt = None;
+        move |to, msg| {
+            let reply = match msg {
+                Message::Start(wizard) => {
+                    hands.insert(to, wizard.spells.len());
+                    None
+                }
+                Message::ChooseSpell { only_disbelieve: false } if hands[&to] > 1 => {
+                    *hands.get_mut(&to)? -= 1;
+                    Some(Message::ChosenSpell(Some((1, false))))
+                }
+                Message::ChoosePiece(tiles)
+                | Message::ChooseTarget(tiles)
+                | Message::EngagedInCombat(tiles)
+                | Message::ChooseRangedCombat { tiles, .. }
+                | Message::MovementRange { tiles, .. }
+                | Message::MovementPoints { tiles, .. }
+                    if !tiles.is_empty() && last_prompt.as_ref() != Some(msg) =>
+                {
+                    last_prompt = Some(msg.clone());
+                    Some(Message::ChosenTile(Some(0)))
+                }
+                _ => None,
+            }?;
+            Some(RecieveMsg::Message { id: to, msg: reply })
+        }
+    }
+
+    async fn seeded_game(seed: u64) -> Vec<SendMsg> {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(HostOptions {
+            seed: Some(seed),
+            ..HostOptions::default()
+        });
+        let (result, sent) = run(logic.game_loop(lobby(4)), &input, &mut output, eager()).await;
+        assert!(result.is_ok());
+        sent
+    }
+
+    #[tokio::test]
+    async fn games_with_the_same_seed_send_the_same_messages() {
+        let first = seeded_game(632).await;
+        assert!(to_all(&first).iter().any(|msg| matches!(msg, Message::CastSpell { .. })));
+        assert_eq!(first, seeded_game(632).await);
+        assert_ne!(first, seeded_game(633).await);
+    }
+
+    #[tokio::test]
+    async fn private_buff_failures_are_only_sent_to_the_caster() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(HostOptions {
+            private_buff_failures: true,
+            ..deterministic()
+        });
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        state.arena.get_mut_wizard(1, 4).stats.spell_ability = 0;
+        let mut shield = spell("MAGIC SHIELD");
+        shield.chance = 0;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, shield.clone(), false),
+            &input,
+            &mut output,
+            passive,
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(to_all(&sent), vec![cast_spell(&shield)]);
+        assert!(sent.iter().any(|msg| matches!(
+            msg,
+            SendMsg::MessageToId {
+                to: 0,
+                msg: Message::SpellFails,
+                ..
+            }
+        )));
+    }
+
+    fn message(id: u32, msg: Message) -> RecieveMsg {
+        RecieveMsg::Message { id, msg }
+    }
+
+    #[tokio::test]
+    async fn input_is_held_while_the_host_has_paused() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(HostOptions::default());
+        logic.host = Some(0);
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        let tiles = vec![(2, 4), (3, 4)];
+        input.send(message(0, Message::Pause(true))).await.unwrap();
+        input.send(message(1, Message::ChosenTile(Some(1)))).await.unwrap();
+        let waiting = tokio::time::timeout(Duration::from_millis(50), logic.chosen_tile(&mut state, 1, tiles.clone())).await;
+        assert!(waiting.is_err());
+        input.send(message(0, Message::Pause(false))).await.unwrap();
+        let (chosen, sent) = run(logic.chosen_tile(&mut state, 1, tiles), &input, &mut output, passive).await;
+        assert_eq!(chosen.unwrap(), Some((3, 4)));
+        assert_eq!(to_all(&sent), vec![Message::Pause(true), Message::Pause(false)]);
+    }
+
+    #[tokio::test]
+    async fn only_the_host_can_pause() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(HostOptions::default());
+        logic.host = Some(0);
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        input.send(message(1, Message::Pause(true))).await.unwrap();
+        input.send(message(1, Message::ChosenTile(Some(0)))).await.unwrap();
+        let (chosen, sent) = run(logic.chosen_tile(&mut state, 1, vec![(2, 4)]), &input, &mut output, passive).await;
+        assert_eq!(chosen.unwrap(), Some((2, 4)));
+        assert!(sent.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn animation_sync_gives_up_on_a_silent_client_after_five_seconds() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(HostOptions {
+            sync_animations: true,
+            ..HostOptions::default()
+        });
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        input.send(message(0, Message::AnimationDone)).await.unwrap();
+        let started = Instant::now();
+        let (result, sent) = run(logic.sync_animations(&mut state), &input, &mut output, passive).await;
+        assert!(result.is_ok());
+        assert_eq!(started.elapsed(), Duration::from_secs(5));
+        assert_eq!(to_all(&sent), vec![Message::SyncAnimations]);
+        assert!(!state.wizards.has_disconnected(1).unwrap());
+    }
+
+    #[tokio::test]
+    async fn abandoned_match_shuts_down() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(HostOptions::default());
+        let leave = |to: u32, msg: &Message| match msg {
+            Message::ChooseSpell { .. } => Some(RecieveMsg::Disconnected { id: to }),
+            _ => None,
+        };
+        let (result, sent) = run(logic.game_loop(lobby(2)), &input, &mut output, leave).await;
+        assert!(matches!(result, Err(ChaosError::NetworkError)));
+        assert_eq!(turn_ends(&sent), 0);
+        assert!(to_all(&sent).contains(&Message::Shutdown));
+        assert!(matches!(sent.last(), Some(SendMsg::Shutdown)));
+    }
+
+    fn banned_spells_sent_to(sent: &[SendMsg]) -> Vec<u32> {
+        sent.iter()
+            .filter_map(|msg| match msg {
+                SendMsg::MessageToId {
+                    to,
+                    msg: Message::BannedSpells(_),
+                    ..
+                } => Some(*to),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn banned_spells_are_sent_each_time_a_player_enters_the_lobby() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(HostOptions {
+            banned_spells: vec!["LIGHTNING".to_string()],
+            ..HostOptions::default()
+        });
+        for _ in 0..2 {
+            for id in 0..2 {
+                input.send(RecieveMsg::Connected { id }).await.unwrap();
+                input.send(message(id, Message::Join(player(id)))).await.unwrap();
+            }
+            for id in 0..2 {
+                input.send(message(id, Message::Ready(true))).await.unwrap();
+            }
+            let (wizards, sent) = run(logic.lobby_loop(), &input, &mut output, passive).await;
+            assert!(wizards.unwrap().is_some());
+            assert_eq!(banned_spells_sent_to(&sent), vec![0, 1]);
+            assert!(sent.iter().any(|msg| matches!(
+                msg,
+                SendMsg::MessageToId {
+                    msg: Message::BannedSpells(banned),
+                    ..
+                } if *banned == ["LIGHTNING"]
+            )));
+        }
+    }
+
+    #[tokio::test]
+    async fn summoning_is_refused_at_the_creation_limit() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(HostOptions {
+            creation_limit: 2,
+            ..deterministic()
+        });
+        let mut state = scenario(&[(1, 4), (8, 4)]);
+        state.arena.get_mut(1, 6).creation = Some(creation(0, "GOBLIN"));
+        let mut goblin = spell("GOBLIN");
+        goblin.chance = STAT_CAP;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, goblin.clone(), false),
+            &input,
+            &mut output,
+            target((2, 4)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(!sent.iter().any(|msg| matches!(
+            msg,
+            SendMsg::MessageToId {
+                msg: Message::CreationLimitReached,
+                ..
+            }
+        )));
+        assert_eq!(state.arena.creation_count(0), 2);
+
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, goblin.clone(), false),
+            &input,
+            &mut output,
+            target((2, 5)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(sent.iter().any(|msg| matches!(
+            msg,
+            SendMsg::MessageToId {
+                to: 0,
+                msg: Message::CreationLimitReached,
+                ..
+            }
+        )));
+        assert!(!sent.iter().any(|msg| matches!(
+            msg,
+            SendMsg::MessageToId {
+                msg: Message::ChooseTarget(_),
+                ..
+            }
+        )));
+        assert_eq!(state.arena.creation_count(0), 2);
+        assert!(state.arena.get(2, 5).creation.is_none());
+    }
+
+    #[test]
+    fn a_fixed_seed_gives_a_fixed_turn_order() {
+        let options = HostOptions {
+            shuffle_turn_order: true,
+            seed: Some(7),
+            ..HostOptions::default()
+        };
+        let mut state = scenario(&[(0, 0), (2, 0), (4, 0), (6, 0), (8, 0)]);
+        state.wizards.get_mut(1).unwrap().alive = false;
+        state.wizards.get_mut(2).unwrap().disconnected = true;
+        let mut orders = Vec::new();
+        for _ in 0..2 {
+            let mut logic = table(options.clone()).logic;
+            orders.push((0..10).map(|_| logic.turn_order(&state)).collect::<Vec<_>>());
+        }
+        assert_eq!(orders[0], orders[1]);
+        for order in &orders[0] {
+            let mut ids = order.clone();
+            ids.sort();
+            assert_eq!(ids, vec![0, 3, 4]);
+        }
+        assert!(orders[0].iter().any(|order| *order != orders[0][0]));
+    }
+
+    fn step_then_stop(step: (u8, u8)) -> impl FnMut(u32, &Message) -> Option<RecieveMsg> {
+        move |to, msg| match msg {
+            Message::MovementRange { tiles, .. } => choose(to, tiles, step),
+            Message::MovementPoints { tiles, .. } => choose(to, tiles, step),
+            _ => None,
+        }
+    }
+
+    fn sent_to(sent: &[SendMsg], id: u32) -> Vec<Message> {
+        sent.iter()
+            .filter_map(|msg| match msg {
+                SendMsg::MessageToId { to, msg, .. } if *to == id => Some(msg.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn a_wizard_left_with_only_disbelieve_is_offered_just_that() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (13, 4)]);
+        state.wizards.get_mut(0).unwrap().spells.truncate(1);
+        let (spells, sent) = run(logic.select_spells(&mut state), &input, &mut output, |to, msg| match msg {
+            Message::ChooseSpell { only_disbelieve: true } => Some(message(to, Message::ChosenSpell(Some((0, false))))),
+            _ => None,
+        })
+        .await;
+        let choose = |id| {
+            sent_to(&sent, id)
+                .into_iter()
+                .filter(|msg| matches!(msg, Message::ChooseSpell { .. }))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(choose(0), vec![Message::ChooseSpell { only_disbelieve: true }]);
+        assert_eq!(choose(1), vec![Message::ChooseSpell { only_disbelieve: false }]);
+        let spells = spells.unwrap();
+        assert_eq!(spells.len(), 1);
+        assert_eq!((spells[0].0, spells[0].1.kind.clone()), (0, SpellKind::Disbelieve));
+        assert_eq!(state.wizards.get(0).unwrap().spells.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_wizard_can_stop_part_way_and_still_shoot() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (12, 4)]);
+        let stats = &mut state.arena.get_mut_wizard(1, 4).stats;
+        stats.magic_bow();
+        stats.base.movement = 3;
+        state.arena.reset_moves(0);
+        let (result, sent) = run(
+            logic.move_wizard(&mut state, 0, 1, 4),
+            &input,
+            &mut output,
+            step_then_stop((2, 4)),
+        )
+        .await;
+        assert!(result.is_ok());
+        let wizard = state.arena.get_wizard(2, 4);
+        assert_eq!(wizard.moves_left, 0);
+        let to_caster = sent_to(&sent, 0);
+        let stopped = to_caster.iter().position(|msg| *msg == Message::MovementStopped);
+        let ranged = to_caster
+            .iter()
+            .position(|msg| matches!(msg, Message::ChooseRangedCombat { x: 2, y: 4, .. }));
+        assert!(stopped.is_some() && stopped < ranged);
+    }
+
+    #[tokio::test]
+    async fn a_creation_can_stop_part_way_and_still_shoot() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (12, 4)]);
+        state.arena.get_mut(1, 6).creation = Some(creation(0, "CENTAUR"));
+        state.arena.reset_moves(0);
+        let (result, sent) = run(
+            logic.move_creation(&mut state, 0, 1, 6),
+            &input,
+            &mut output,
+            step_then_stop((2, 6)),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(state.arena.get_creation(2, 6).moves_left, 0);
+        let to_caster = sent_to(&sent, 0);
+        let stopped = to_caster.iter().position(|msg| *msg == Message::MovementStopped);
+        let ranged = to_caster
+            .iter()
+            .position(|msg| matches!(msg, Message::ChooseRangedCombat { x: 2, y: 6, .. }));
+        assert!(stopped.is_some() && stopped < ranged);
+    }
+
+    #[tokio::test]
+    async fn escape_ends_movement_without_the_stop_prompt() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (12, 4)]);
+        state.arena.get_mut_wizard(1, 4).stats.magic_bow();
+        state.arena.reset_moves(0);
+        let (result, sent) = run(logic.move_wizard(&mut state, 0, 1, 4), &input, &mut output, passive).await;
+        assert!(result.is_ok());
+        assert_eq!(state.arena.get_wizard(1, 4).moves_left, 0);
+        let to_caster = sent_to(&sent, 0);
+        assert!(!to_caster.contains(&Message::MovementStopped));
+        assert!(to_caster.iter().any(|msg| matches!(msg, Message::ChooseRangedCombat { .. })));
+    }
+
+    fn campaign_level(logic: &GameLogic, id: u32) -> u8 {
+        let campaign = logic.campaign.lock().expect("campaign");
+        campaign
+            .iter()
+            .find(|progress| progress.player == player(id))
+            .expect("progress")
+            .level
+    }
+
+    #[test]
+    fn a_sole_survivor_gains_a_campaign_level_each_match() {
+        let options = HostOptions {
+            campaign: true,
+            starting_level: 2,
+            ..HostOptions::default()
+        };
+        let Table { mut logic, .. } = table(options);
+        let mut state = scenario(&[(1, 1), (8, 8)]);
+        state.wizards.get_mut(1).expect("wizard").alive = false;
+        state.arena.get_mut(8, 8).wizard = None;
+        logic.record_campaign(&state);
+        assert_eq!((campaign_level(&logic, 0), campaign_level(&logic, 1)), (3, 2));
+        logic.record_campaign(&state);
+        assert_eq!((campaign_level(&logic, 0), campaign_level(&logic, 1)), (4, 2));
+        let campaign = logic.campaign.lock().expect("campaign").clone();
+        assert_eq!(campaign.len(), 2);
+        let loser = campaign
+            .iter()
+            .find(|progress| progress.player == player(1))
+            .expect("progress");
+        assert!(loser.spells.is_empty());
+    }
+
+    #[test]
+    fn campaign_levels_stop_at_eight_and_need_a_sole_survivor() {
+        let options = HostOptions {
+            campaign: true,
+            starting_level: 8,
+            ..HostOptions::default()
+        };
+        let Table { mut logic, .. } = table(options);
+        let mut state = scenario(&[(1, 1), (8, 8)]);
+        logic.record_campaign(&state);
+        assert_eq!((campaign_level(&logic, 0), campaign_level(&logic, 1)), (8, 8));
+        *logic.campaign.lock().expect("campaign") = Vec::new();
+        logic.options.starting_level = 0;
+        logic.record_campaign(&state);
+        assert_eq!((campaign_level(&logic, 0), campaign_level(&logic, 1)), (0, 0));
+        state.wizards.get_mut(1).expect("wizard").disconnected = true;
+        logic.record_campaign(&state);
+        assert_eq!((campaign_level(&logic, 0), campaign_level(&logic, 1)), (1, 0));
+    }
+
+    fn blob(id: u32) -> GameCreation {
+        match spell("GOOEY BLOB").kind {
+            SpellKind::GooeyBlob(stats) => GameCreation::new(id, stats),
+            _ => panic!("GOOEY BLOB is not a blob"),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_blob_spreading_onto_an_empty_tile_leaves_the_client_in_sync() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 1), (8, 8)]);
+        let mut client = state.arena.clone();
+        let (result, sent) = run(logic.blob_mutate(&blob(0), &mut state, 4, 4), &input, &mut output, passive).await;
+        assert!(result.is_ok());
+        for msg in to_all(&sent) {
+            match msg {
+                Message::SpawnBlob { x, y, blob: Some(blob) } => client.spawn_blob(x, y, blob),
+                msg => panic!("unexpected {msg:?}"),
+            }
+        }
+        assert_eq!(client.checksum(), state.arena.checksum());
+    }
+
+    #[tokio::test]
+    async fn a_blob_that_fails_to_engulf_a_wizard_sends_spawn_blob() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 1), (8, 8)]);
+        state.arena.get_mut_wizard(8, 8).stats.base.defence = STAT_CAP;
+        let (result, sent) = run(logic.blob_mutate(&blob(0), &mut state, 8, 8), &input, &mut output, passive).await;
+        assert!(result.is_ok());
+        assert_eq!(to_all(&sent), vec![Message::SpawnBlob { x: 8, y: 8, blob: None }]);
+        assert!(state.arena.get(8, 8).spawn.is_none());
+        assert!(state.wizards.is_alive(1).unwrap());
+    }
+
+    fn shoot(tile: (u8, u8)) -> impl FnMut(u32, &Message) -> Option<RecieveMsg> {
+        move |to, msg| match msg {
+            Message::ChooseRangedCombat { tiles, .. } => choose(to, tiles, tile),
+            _ => None,
+        }
+    }
+
+    fn ranged_offers(sent: &[SendMsg]) -> usize {
+        sent_to(sent, 0)
+            .iter()
+            .filter(|msg| matches!(msg, Message::ChooseRangedCombat { .. }))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn pieces_shoot_once_until_their_moves_are_reset() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (12, 4)]);
+        state.arena.get_mut_wizard(1, 4).stats.magic_bow();
+        state.arena.get_mut(1, 6).creation = Some(creation(0, "CENTAUR"));
+        state.arena.get_mut(3, 5).creation = Some(creation(1, "GOLDEN DRAGON"));
+        for _ in 0..2 {
+            state.arena.reset_moves(0);
+            for expected in [1, 0] {
+                let (result, sent) = run(
+                    logic.check_for_wizard_ranged_combat(&mut state, 0, 1, 4),
+                    &input,
+                    &mut output,
+                    shoot((3, 5)),
+                )
+                .await;
+                assert!(result.is_ok());
+                assert_eq!(ranged_offers(&sent), expected);
+                let (result, sent) = run(
+                    logic.check_for_creation_ranged_combat(&mut state, 0, 1, 6),
+                    &input,
+                    &mut output,
+                    shoot((3, 5)),
+                )
+                .await;
+                assert!(result.is_ok());
+                assert_eq!(ranged_offers(&sent), expected);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn a_stale_target_is_rejected_and_the_player_asked_again() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 1), (12, 8)]);
+        state.arena.get_mut(5, 5).creation = Some(creation(1, "GOBLIN"));
+        let valid_tiles = |arena: &Arena| arena.cast_spell_on_attackable_tiles(1, 1, 40, 0);
+        let mut picks = vec![(3, 3), (5, 5)].into_iter();
+        let (result, sent) = run(
+            logic.chosen_target(&mut state, 0, vec![(3, 3), (5, 5)], valid_tiles),
+            &input,
+            &mut output,
+            move |to, msg| match msg {
+                Message::ChooseTarget(tiles) => choose(to, tiles, picks.next()?),
+                _ => None,
+            },
+        )
+        .await;
+        assert_eq!(result.expect("target"), Some((5, 5)));
+        assert_eq!(
+            sent_to(&sent, 0),
+            vec![
+                Message::ChooseTarget(vec![(3, 3), (5, 5)]),
+                Message::TargetNoLongerValid,
+                Message::ChooseTarget(vec![(5, 5)])
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_stale_target_with_nothing_left_in_range_cancels_the_spell() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 1), (12, 8)]);
+        let valid_tiles = |arena: &Arena| arena.cast_spell_on_attackable_tiles(1, 1, 3, 0);
+        let (result, sent) = run(
+            logic.chosen_target(&mut state, 0, vec![(3, 3)], valid_tiles),
+            &input,
+            &mut output,
+            target((3, 3)),
+        )
+        .await;
+        assert_eq!(result.expect("target"), None);
+        assert_eq!(
+            sent_to(&sent, 0),
+            vec![Message::ChooseTarget(vec![(3, 3)]), Message::TargetNoLongerValid]
+        );
+    }
+
+    fn ride(piece: (u8, u8), dismount: bool, step: (u8, u8)) -> impl FnMut(u32, &Message) -> Option<RecieveMsg> {
+        let mut chosen = false;
+        move |to, msg| match msg {
+            Message::ChoosePiece(tiles) if !chosen => {
+                chosen = true;
+                choose(to, tiles, piece)
+            }
+            Message::AskForDismount => Some(message(to, Message::Dismount(Some(dismount)))),
+            Message::MovementRange { tiles, .. } | Message::MovementPoints { tiles, .. } => choose(to, tiles, step),
+            _ => None,
+        }
+    }
+
+    fn pieces_offered(sent: &[SendMsg]) -> Vec<Vec<(u8, u8)>> {
+        sent_to(sent, 0)
+            .into_iter()
+            .filter_map(|msg| match msg {
+                Message::ChoosePiece(tiles) => Some(tiles),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn a_dismounted_mount_cannot_act_the_same_turn() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(3, 3), (12, 8)]);
+        state.arena.get_mut(3, 3).creation = Some(creation(0, "HORSE"));
+        let (result, sent) = run(
+            logic.movement_loop(&mut state, 0),
+            &input,
+            &mut output,
+            ride((3, 3), true, (3, 4)),
+        )
+        .await;
+        assert!(result.expect("movement"));
+        assert_eq!(state.arena.get_wizard(3, 4).id, 0);
+        assert_eq!(state.arena.get_creation(3, 3).moves_left, 0);
+        assert_eq!(pieces_offered(&sent), vec![vec![(3, 3)]]);
+    }
+
+    #[tokio::test]
+    async fn a_rider_that_stays_mounted_moves_with_its_mount_once() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(3, 3), (12, 8)]);
+        state.arena.get_mut(3, 3).creation = Some(creation(0, "HORSE"));
+        let (result, sent) = run(
+            logic.movement_loop(&mut state, 0),
+            &input,
+            &mut output,
+            ride((3, 3), false, (3, 4)),
+        )
+        .await;
+        assert!(result.expect("movement"));
+        let tile = state.arena.get(3, 4);
+        assert_eq!(tile.wizard.as_ref().map(|wizard| wizard.moves_left), Some(0));
+        assert!(tile.creation.is_some());
+        assert!(pieces_offered(&sent).iter().skip(1).all(|tiles| !tiles.contains(&(3, 4))));
+    }
+
+    #[tokio::test]
+    async fn a_walled_in_magic_wood_ends_without_planting() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(0, 0), (14, 9)]);
+        for (x, y) in (0..3).flat_map(|x| (0..3).map(move |y| (x, y))).skip(1) {
+            state.arena.get_mut(x, y).creation = Some(placed(1, "WALL"));
+        }
+        let mut wood = spell("MAGIC WOOD");
+        wood.chance = STAT_CAP;
+        let (result, sent) = run(
+            logic.do_spell(&mut state, 0, wood.clone(), false),
+            &input,
+            &mut output,
+            passive,
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(to_all(&sent), vec![cast_spell(&wood)]);
+        assert!(sent.iter().any(|msg| matches!(
+            msg,
+            SendMsg::MessageToId {
+                to: 0,
+                msg: Message::NoPossibleMoves,
+                ..
+            }
+        )));
+        assert_eq!(state.arena.creation_count(0), 0);
+    }
+
+    #[tokio::test]
+    async fn magic_wood_grants_spells_up_to_a_raised_hand_size() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(HostOptions {
+            max_spells: 30,
+            magic_wood_chance: 100,
+            ..deterministic()
+        });
+        let mut state = scenario(&[(1, 4), (13, 4)]);
+        let spells = &mut state.wizards.get_mut(0).unwrap().spells;
+        while spells.len() < 21 {
+            spells.push(spell("MAGIC BOLT"));
+        }
+        for expected in 22..=30 {
+            state.arena.get_mut(1, 4).creation = Some(placed(0, "MAGIC WOOD"));
+            let (result, _) = run(logic.do_magic_wood(&mut state), &input, &mut output, passive).await;
+            assert!(result.is_ok());
+            assert_eq!(state.wizards.get(0).unwrap().spells.len(), expected);
+            assert!(state.arena.get(1, 4).creation.is_none());
+        }
+        state.arena.get_mut(1, 4).creation = Some(placed(0, "MAGIC WOOD"));
+        let (result, sent) = run(logic.do_magic_wood(&mut state), &input, &mut output, passive).await;
+        assert!(result.is_ok());
+        assert!(sent.is_empty());
+        assert_eq!(state.wizards.get(0).unwrap().spells.len(), 30);
+        assert!(state.arena.get(1, 4).creation.is_some());
+    }
+
+    #[tokio::test]
+    async fn magic_wood_never_grants_at_zero_percent_and_always_grants_at_a_hundred() {
+        for (chance, granted) in [(0, false), (100, true)] {
+            for seed in 0..50 {
+                let Table {
+                    mut logic,
+                    input,
+                    mut output,
+                    _quit,
+                } = table(HostOptions {
+                    max_spells: 30,
+                    magic_wood_chance: chance,
+                    seed: Some(seed),
+                    ..deterministic()
+                });
+                let mut state = scenario(&[(1, 4), (13, 4)]);
+                state.arena.get_mut(1, 4).creation = Some(placed(0, "MAGIC WOOD"));
+                let before = state.wizards.get(0).unwrap().spells.len();
+                let (result, _) = run(logic.do_magic_wood(&mut state), &input, &mut output, passive).await;
+                assert!(result.is_ok());
+                let after = state.wizards.get(0).unwrap().spells.len();
+                assert_eq!(after > before, granted, "chance {chance}, seed {seed}");
+                assert_eq!(
+                    state.arena.get(1, 4).creation.is_none(),
+                    granted,
+                    "chance {chance}, seed {seed}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn a_creation_attacking_an_empty_magic_wood_destroys_it_and_moves_in() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 1), (13, 8)]);
+        state.arena.get_mut(5, 4).creation = Some(creation(0, "GORILLA"));
+        state.arena.get_mut(5, 5).creation = Some(placed(1, "MAGIC WOOD"));
+        let (result, sent) = run(logic.creation_attack(&mut state, 0, 5, 4, 5, 5), &input, &mut output, passive).await;
+        assert!(result.is_ok());
+        assert!(to_all(&sent).contains(&Message::MoveCreation {
+            sx: 5,
+            sy: 4,
+            dx: 5,
+            dy: 5
+        }));
+        assert_eq!(state.arena.get_creation(5, 5).stats.base.name, "GORILLA");
+        assert!(state.arena.get(5, 4).creation.is_none());
+    }
+
+    #[tokio::test]
+    async fn a_wizard_attacking_an_empty_magic_wood_destroys_it_and_moves_in() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(5, 4), (13, 8)]);
+        state.arena.get_mut_wizard(5, 4).stats.base.combat = 9;
+        state.arena.get_mut(5, 5).creation = Some(placed(1, "MAGIC WOOD"));
+        let (result, sent) = run(logic.wizard_attack(&mut state, 0, 5, 4, 5, 5), &input, &mut output, passive).await;
+        assert!(result.is_ok());
+        assert!(to_all(&sent).contains(&Message::MoveWizard { x: 5, y: 5 }));
+        assert_eq!(state.arena.get_wizard(5, 5).id, 0);
+        assert!(state.arena.get(5, 5).creation.is_none());
+    }
+
+    #[tokio::test]
+    async fn killing_a_wizard_in_another_wizards_magic_wood_leaves_the_wood_in_place() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 1), (13, 8), (5, 5)]);
+        state.arena.get_mut(5, 4).creation = Some(creation(0, "GORILLA"));
+        state.arena.get_mut(5, 5).creation = Some(placed(1, "MAGIC WOOD"));
+        let (result, sent) = run(logic.creation_attack(&mut state, 0, 5, 4, 5, 5), &input, &mut output, passive).await;
+        assert!(result.is_ok());
+        assert!(!state.wizards.is_alive(2).unwrap());
+        assert!(!to_all(&sent).iter().any(|msg| matches!(msg, Message::MoveCreation { .. })));
+        assert_eq!(state.arena.get_creation(5, 4).stats.base.name, "GORILLA");
+        assert_eq!(state.arena.get_creation(5, 5).id, 1);
+        assert!(state.arena.get(5, 5).creation.as_ref().unwrap().stats.magic_wood);
+    }
+
+    #[tokio::test]
+    async fn a_shadow_wood_that_kills_a_wizard_stays_rooted() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 1), (3, 4), (13, 8)]);
+        state.arena.get_mut(3, 3).creation = Some(placed(0, "SHADOW WOOD"));
+        state.arena.get_mut_wizard(3, 4).stats.base.defence = 0;
+        let (result, sent) = run(logic.creation_attack(&mut state, 0, 3, 3, 3, 4), &input, &mut output, passive).await;
+        assert!(result.is_ok());
+        assert!(!state.wizards.is_alive(1).unwrap());
+        assert!(!to_all(&sent).iter().any(|msg| matches!(msg, Message::MoveCreation { .. })));
+        assert!(state.arena.get_creation(3, 3).stats.shadow_wood);
+        assert!(state.arena.get(3, 4).creation.is_none());
+    }
+
+    #[tokio::test]
+    async fn a_rider_engaged_after_mounting_is_carried_without_a_dismount_prompt() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(3, 3), (12, 8)]);
+        state.arena.get_mut(3, 4).creation = Some(creation(0, "HORSE"));
+        let mut goblin = creation(1, "GOBLIN");
+        goblin.stats.base.manoeuvre = STAT_CAP;
+        state.arena.get_mut(4, 5).creation = Some(goblin);
+        let mut pieces = vec![(3, 3), (3, 4)].into_iter();
+        let mut steps = vec![(3, 4), (2, 3)].into_iter();
+        let (result, sent) = run(logic.movement_loop(&mut state, 0), &input, &mut output, |to, msg| match msg {
+            Message::ChoosePiece(tiles) => pieces.next().and_then(|piece| choose(to, tiles, piece)),
+            Message::MovementRange { tiles, .. } | Message::MovementPoints { tiles, .. } => {
+                steps.next().and_then(|step| choose(to, tiles, step))
+            }
+            _ => None,
+        })
+        .await;
+        assert!(result.expect("movement"));
+        assert!(!sent_to(&sent, 0).contains(&Message::AskForDismount));
+        let tile = state.arena.get(2, 3);
+        assert_eq!(tile.wizard.as_ref().map(|wizard| wizard.id), Some(0));
+        assert!(tile.creation.as_ref().is_some_and(|creation| creation.stats.mount));
+    }
+
+    #[tokio::test]
+    async fn spells_queued_by_wizards_killed_earlier_in_the_phase_are_skipped() {
+        let Table {
+            mut logic,
+            input,
+            mut output,
+            _quit,
+        } = table(deterministic());
+        let mut state = scenario(&[(1, 4), (4, 4), (13, 8)]);
+        state.arena.get_mut_wizard(4, 4).stats.base.defence = 3;
+        let mut bolt = spell("MAGIC BOLT");
+        bolt.chance = STAT_CAP;
+        let spells = vec![(0, bolt.clone(), false), (1, bolt.clone(), false)];
+        let (result, sent) = run(logic.cast_spells(&mut state, spells), &input, &mut output, target((4, 4))).await;
+        assert!(result.is_ok());
+        assert!(!state.wizards.is_alive(1).unwrap());
+        assert_eq!(
+            to_all(&sent)
+                .iter()
+                .filter(|msg| matches!(msg, Message::CastSpell { .. }))
+                .count(),
+            1
+        );
+        assert!(sent_to(&sent, 1).is_empty());
+    }
+}
@@ -48,10 +48,22 @@ impl Sender {
             if wizard.ready {
                 self.send_to_id(id, wizard.id, Message::Ready(true)).await?;
             }
+            if wizard.responsive {
+                self.send_to_id(id, wizard.id, Message::Responsive(true)).await?;
+            }
+        }
+        for wizard in wizards.players() {
+            if let Some(index) = wizard.position {
+                self.send_to_id(id, wizard.id, Message::ClaimPosition(index)).await?;
+            }
         }
         Ok(())
     }
 
+    pub async fn banned_spells(&mut self, id: u32, banned_spells: &[String]) -> Result<(), NetworkError> {
+        self.send_to_id(id, id, Message::BannedSpells(banned_spells.to_vec())).await
+    }
+
     pub async fn join(&mut self, id: u32, player: &Player) -> Result<(), NetworkError> {
         self.send_to_all(SendMsg::MessageToAll {
             id: Some(id),
@@ -68,6 +80,22 @@ impl Sender {
         .await
     }
 
+    pub async fn responsive(&mut self, id: u32, responsive: bool) -> Result<(), NetworkError> {
+        self.send_to_all(SendMsg::MessageToAll {
+            id: Some(id),
+            msg: Message::Responsive(responsive),
+        })
+        .await
+    }
+
+    pub async fn claim_position(&mut self, id: u32, index: usize) -> Result<(), NetworkError> {
+        self.send_to_all(SendMsg::MessageToAll {
+            id: Some(id),
+            msg: Message::ClaimPosition(index),
+        })
+        .await
+    }
+
     pub async fn leave(&mut self, id: u32) -> Result<(), NetworkError> {
         self.send_to_all(SendMsg::MessageToAll {
             id: Some(id),
@@ -95,8 +123,8 @@ impl Sender {
         .await
     }
 
-    pub async fn choose_spell(&mut self, id: u32) -> Result<(), NetworkError> {
-        self.send_to_id(id, id, Message::ChooseSpell).await
+    pub async fn choose_spell(&mut self, id: u32, only_disbelieve: bool) -> Result<(), NetworkError> {
+        self.send_to_id(id, id, Message::ChooseSpell { only_disbelieve }).await
     }
 
     pub async fn cast_spell(&mut self, id: u32, spell: &Spell) -> Result<(), NetworkError> {
@@ -142,14 +170,18 @@ impl Sender {
         .await
     }
 
-    pub async fn spell_fails(&mut self) -> Result<(), NetworkError> {
+    pub async fn spell_fails(&mut self, id: u32) -> Result<(), NetworkError> {
         self.send_to_all(SendMsg::MessageToAll {
-            id: None,
+            id: Some(id),
             msg: Message::SpellFails,
         })
         .await
     }
 
+    pub async fn spell_fails_to(&mut self, id: u32) -> Result<(), NetworkError> {
+        self.send_to_id(id, id, Message::SpellFails).await
+    }
+
     pub async fn creation_spell(&mut self, id: u32, x: u8, y: u8, creation: Option<&GameCreation>) -> Result<(), NetworkError> {
         self.send_to_all(SendMsg::MessageToAll {
             id: Some(id),
@@ -235,6 +267,10 @@ impl Sender {
         .await
     }
 
+    pub async fn movement_stopped(&mut self, id: u32) -> Result<(), NetworkError> {
+        self.send_to_id(id, id, Message::MovementStopped).await
+    }
+
     pub async fn ask_for_dismount(&mut self, id: u32) -> Result<(), NetworkError> {
         self.send_to_id(id, id, Message::AskForDismount).await
     }
@@ -251,10 +287,42 @@ impl Sender {
         self.send_to_id(id, id, Message::ChooseTarget(tiles.to_vec())).await
     }
 
+    pub async fn turn_order(&mut self, order: &[u32]) -> Result<(), NetworkError> {
+        self.send_to_all(SendMsg::MessageToAll {
+            id: None,
+            msg: Message::TurnOrder(order.to_vec()),
+        })
+        .await
+    }
+
+    pub async fn stranded(&mut self, id: u32, stranded: bool) -> Result<(), NetworkError> {
+        self.send_to_all(SendMsg::MessageToAll {
+            id: Some(id),
+            msg: Message::Stranded(stranded),
+        })
+        .await
+    }
+
     pub async fn turn(&mut self, id: u32) -> Result<(), NetworkError> {
         self.send_to_all_except(id, Message::Turn).await
     }
 
+    pub async fn sync_animations(&mut self) -> Result<(), NetworkError> {
+        self.send_to_all(SendMsg::MessageToAll {
+            id: None,
+            msg: Message::SyncAnimations,
+        })
+        .await
+    }
+
+    pub async fn pause(&mut self, id: u32, paused: bool) -> Result<(), NetworkError> {
+        self.send_to_all(SendMsg::MessageToAll {
+            id: Some(id),
+            msg: Message::Pause(paused),
+        })
+        .await
+    }
+
     pub async fn turn_end(&mut self) -> Result<(), NetworkError> {
         self.send_to_all(SendMsg::MessageToAll {
             id: None,
@@ -263,6 +331,22 @@ impl Sender {
         .await
     }
 
+    pub async fn state_checksum(&mut self, checksum: u64) -> Result<(), NetworkError> {
+        self.send_to_all(SendMsg::MessageToAll {
+            id: None,
+            msg: Message::StateChecksum(checksum),
+        })
+        .await
+    }
+
+    pub async fn spell_phase_end(&mut self) -> Result<(), NetworkError> {
+        self.send_to_all(SendMsg::MessageToAll {
+            id: None,
+            msg: Message::SpellPhaseEnd,
+        })
+        .await
+    }
+
     pub async fn failed_attack(&mut self, id: u32, x: u8, y: u8) -> Result<(), NetworkError> {
         self.send_to_all(SendMsg::MessageToAll {
             id: Some(id),
@@ -279,11 +363,20 @@ impl Sender {
         .await
     }
 
-    pub async fn choose_ranged_combat(&mut self, id: u32, range: u8, tiles: &[(u8, u8)]) -> Result<(), NetworkError> {
+    pub async fn choose_ranged_combat(
+        &mut self,
+        id: u32,
+        x: u8,
+        y: u8,
+        range: u8,
+        tiles: &[(u8, u8)],
+    ) -> Result<(), NetworkError> {
         self.send_to_id(
             id,
             id,
             Message::ChooseRangedCombat {
+                x,
+                y,
                 range,
                 tiles: tiles.to_vec(),
             },
@@ -364,6 +457,10 @@ impl Sender {
         self.send_to_id(id, id, Message::NoLineOfSight).await
     }
 
+    pub async fn target_no_longer_valid(&mut self, id: u32) -> Result<(), NetworkError> {
+        self.send_to_id(id, id, Message::TargetNoLongerValid).await
+    }
+
     pub async fn subversion(&mut self, id: u32, x: u8, y: u8, success: bool) -> Result<(), NetworkError> {
         self.send_to_all(SendMsg::MessageToAll {
             id: Some(id),
@@ -448,6 +545,10 @@ impl Sender {
         self.send_to_id(id, id, Message::ShadowWoodInfo).await
     }
 
+    pub async fn creation_limit_reached(&mut self, id: u32) -> Result<(), NetworkError> {
+        self.send_to_id(id, id, Message::CreationLimitReached).await
+    }
+
     pub async fn no_possible_moves(&mut self, id: u32) -> Result<(), NetworkError> {
         self.send_to_id(id, id, Message::NoPossibleMoves).await
     }
@@ -1,6 +1,8 @@
 use crate::data::arena::Arena;
 use crate::data::wizard::ServerWizards;
+use serde::{Deserialize, Serialize};
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ServerState {
     pub wizards: ServerWizards,
     pub arena: Arena,
@@ -0,0 +1,9 @@
+pub trait SoundSink {
+    fn set_volume(&mut self, volume: u8);
+}
+
+pub struct Silence;
+
+impl SoundSink for Silence {
+    fn set_volume(&mut self, _volume: u8) {}
+}
@@ -2,6 +2,11 @@ mod choose_wizard;
 mod game;
 mod lobby;
 mod net;
+mod options;
+// Menu screens return Ok(None) when Escape is pressed, taking the player back
+// one screen without applying anything chosen so far. Inside a match, Escape
+// only cancels the current selection.
 pub use choose_wizard::choose_wizard;
 pub use lobby::lobby;
-pub use net::{host_game, join_game};
+pub use net::{host_game, join_game, practice_game};
+pub use options::options;
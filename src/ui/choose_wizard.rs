@@ -1,59 +1,141 @@
-use crate::config::Player;
+use crate::config::{Player, MAX_NAME_LEN};
 use crate::data::wizard::{WizardCharacter, WizardColor};
 use crate::error::ChaosError;
+use crate::gfx::buffer::Buffer;
 use crate::gfx::color::Color::*;
 use crate::window::Window;
 
-pub fn choose_wizard(win: &mut Window, player: &Option<Player>) -> Result<Option<Player>, ChaosError> {
-    win.buf.clear();
-    win.buf.screen_border("CHOOSE YOUR WIZARD", BrightBlue, BrightCyan);
-    win.buf.draw_text("PLAYER", 36, 4, BrightYellow);
-    win.buf.draw_text("Enter name (12 letters max.)", 36, 6, BrightMagenta);
-    let name = match player {
-        Some(player) => player.name.clone(),
-        None => String::new(),
-    };
-    let name = match win.wizard_name(name, 36, 8, 12, BrightCyan)? {
-        Some(name) => name,
-        None => return Ok(None),
-    };
-    win.buf.draw_text("Which character?", 36, 10, BrightMagenta);
-    win.buf.draw_text("1  2  3  4  5  6  7  8", 36, 12, BrightCyan);
+fn enter_name(win: &mut Window, name: String, y: usize) -> Result<Option<String>, ChaosError> {
+    win.buf.draw_text("Enter name (12 letters max.)", 36, y, BrightMagenta);
+    win.wizard_name(name, 36, y + 2, MAX_NAME_LEN, BrightCyan)
+}
+
+fn choose_character(win: &mut Window, y: usize) -> Result<Option<WizardCharacter>, ChaosError> {
+    win.buf.draw_text("Which character?", 36, y, BrightMagenta);
+    win.buf.draw_text("1  2  3  4  5  6  7  8", 36, y + 2, BrightCyan);
     for index in 0..8 {
         let wizard: WizardCharacter = index.try_into()?;
         let buf = wizard.as_buffer(WizardColor::BrightCyan);
-        win.buf.draw_buffer(&buf, 37 + (index as usize * 3), 12);
+        win.buf.draw_buffer(&buf, 37 + (index as usize * 3), y + 2);
     }
-    let character_num = match win.wait_for_number(1..=8)? {
+    let items: Vec<_> = (0..8).map(|index| (36 + index * 3, y + 2, 3)).collect();
+    let character_num = match win.wait_for_menu(1..=8, &items)? {
         Some(character_num) => {
             let text = format!("{}", character_num);
-            win.buf.draw_text(&text, 53, 10, BrightWhite);
+            win.buf.draw_text(&text, 53, y, BrightWhite);
             character_num - 1
         }
         None => return Ok(None),
     };
     let character = WizardCharacter::try_from(character_num)?;
     let buf = character.as_buffer(WizardColor::BrightWhite);
-    win.buf.draw_buffer(&buf, 54, 10);
-    win.buf.draw_text("Which colour?", 36, 14, BrightMagenta);
-    win.buf.draw_text("1  2  3  4  5  6  7  8", 36, 16, BrightYellow);
+    win.buf.draw_buffer(&buf, 54, y);
+    Ok(Some(character))
+}
+
+fn choose_color(win: &mut Window, character: &WizardCharacter, y: usize) -> Result<Option<WizardColor>, ChaosError> {
+    win.buf.draw_text("Which colour?", 36, y, BrightMagenta);
+    win.buf.draw_text("1  2  3  4  5  6  7  8", 36, y + 2, BrightYellow);
     for index in 0..8 {
         let color: WizardColor = index.try_into()?;
         let buf = character.as_buffer(color);
-        win.buf.draw_buffer(&buf, 37 + (index as usize * 3), 16);
+        win.buf.draw_buffer(&buf, 37 + (index as usize * 3), y + 2);
     }
-    let color_num = match win.wait_for_number(1..=8)? {
+    let items: Vec<_> = (0..8).map(|index| (36 + index * 3, y + 2, 3)).collect();
+    let color_num = match win.wait_for_menu(1..=8, &items)? {
         Some(color_num) => {
             let text = format!("{}", color_num);
-            win.buf.draw_text(&text, 50, 14, BrightWhite);
+            win.buf.draw_text(&text, 50, y, BrightWhite);
             color_num - 1
         }
         None => return Ok(None),
     };
     let color = color_num.try_into()?;
     let buf = character.as_buffer(color);
-    win.buf.draw_buffer(&buf, 51, 14);
+    win.buf.draw_buffer(&buf, 51, y);
+    Ok(Some(color))
+}
+
+fn draw_stats_note(win: &mut Window) {
+    win.buf
+        .draw_text("Stats are rolled at the start of each", 36, 18, BrightGreen);
+    win.buf
+        .draw_text("match; higher host levels are stronger", 36, 20, BrightGreen);
+}
+
+fn new_wizard(win: &mut Window) -> Result<Option<Player>, ChaosError> {
+    win.buf.clear();
+    win.buf.screen_border("CHOOSE YOUR WIZARD", BrightBlue, BrightCyan);
+    win.buf.draw_text("PLAYER", 36, 4, BrightYellow);
+    draw_stats_note(win);
+    let name = match enter_name(win, String::new(), 6)? {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let character = match choose_character(win, 10)? {
+        Some(character) => character,
+        None => return Ok(None),
+    };
+    let color = match choose_color(win, &character, 14)? {
+        Some(color) => color,
+        None => return Ok(None),
+    };
     win.wait(900)?;
     let player_config = Player { name, character, color };
     Ok(Some(player_config))
 }
+
+fn edit_wizard(win: &mut Window, mut player: Player) -> Result<Option<Player>, ChaosError> {
+    loop {
+        win.buf.clear();
+        win.buf
+            .screen_border("PRESS A NUMBER TO CHANGE, ESC TO CANCEL", BrightBlue, BrightCyan);
+        let buf = Buffer::from(&player);
+        win.buf.draw_buffer(&buf, 40 + player.name.len(), 3);
+        win.buf.draw_text(&player.name, 40, 3, BrightYellow);
+        win.buf.draw_text("1.NAME", 36, 7, BrightCyan);
+        win.buf.draw_text(&player.name, 50, 7, BrightWhite);
+        win.buf.draw_text("2.CHARACTER", 36, 9, BrightCyan);
+        win.buf
+            .draw_buffer(&player.character.as_buffer(WizardColor::BrightWhite), 50, 9);
+        win.buf.draw_text("3.COLOUR", 36, 11, BrightCyan);
+        win.buf.draw_buffer(&player.character.as_buffer(player.color), 50, 11);
+        win.buf.draw_text("4.CONFIRM", 36, 13, BrightCyan);
+        draw_stats_note(win);
+        let items: Vec<_> = (0..4).map(|index| (36, 7 + index * 2, 26)).collect();
+        let field = match win.wait_for_menu(1..=4, &items)? {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+        win.buf.clear();
+        win.buf.screen_border("CHOOSE YOUR WIZARD", BrightBlue, BrightCyan);
+        match field {
+            1 => {
+                if let Some(name) = enter_name(win, player.name.clone(), 6)? {
+                    player.name = name;
+                }
+            }
+            2 => {
+                if let Some(character) = choose_character(win, 6)? {
+                    player.character = character;
+                    win.wait(400)?;
+                }
+            }
+            3 => {
+                if let Some(color) = choose_color(win, &player.character, 6)? {
+                    player.color = color;
+                    win.wait(400)?;
+                }
+            }
+            4 => return Ok(Some(player)),
+            _ => unreachable!("Invalid field"),
+        }
+    }
+}
+
+pub fn choose_wizard(win: &mut Window, player: &Option<Player>) -> Result<Option<Player>, ChaosError> {
+    match player {
+        Some(player) => edit_wizard(win, player.clone()),
+        None => new_wizard(win),
+    }
+}
@@ -1,10 +1,24 @@
 use crate::data::arena::Arena;
+use crate::data::creation::GameCreation;
+use crate::data::spells::Spell;
 use crate::data::wizard::Wizard;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+const ALIGNMENT_HISTORY: usize = 32;
 
 pub struct ClientState {
     pub wizard: Wizard,
     pub arena: Arena,
     pub turns_left: usize,
+    pub casting: Option<Spell>,
+    pub casting_illusion: bool,
+    pub illusions: Vec<(u8, u8)>,
+    pub spectating: bool,
+    pub alignment_history: VecDeque<i8>,
+    pub started: Option<Instant>,
+    pub turn_started: Option<Instant>,
 }
 
 impl ClientState {
@@ -13,6 +27,246 @@ impl ClientState {
             wizard,
             arena: Arena::new(),
             turns_left: 0,
+            casting: None,
+            casting_illusion: false,
+            illusions: Vec::new(),
+            spectating: false,
+            alignment_history: VecDeque::new(),
+            started: None,
+            turn_started: None,
+        }
+    }
+
+    pub fn record_alignment(&mut self) {
+        if self.alignment_history.len() == ALIGNMENT_HISTORY {
+            self.alignment_history.pop_front();
+        }
+        self.alignment_history.push_back(self.arena.alignment);
+    }
+
+    pub fn add_creation(&mut self, id: u32, x: u8, y: u8, creation: GameCreation) {
+        self.arena.get_mut(x, y).creation = Some(creation);
+        if id == self.wizard.id && self.casting_illusion {
+            self.illusions.push((x, y));
+        }
+    }
+
+    pub fn remove_wizard(&mut self, id: u32) {
+        self.arena.kill_wizard_and_creations(id);
+        if id == self.wizard.id {
+            self.spectating = true;
+        }
+    }
+
+    pub fn move_illusion(&mut self, sx: u8, sy: u8, dx: u8, dy: u8) {
+        if let Some(pos) = self.illusions.iter_mut().find(|pos| **pos == (sx, sy)) {
+            *pos = (dx, dy);
+        }
+    }
+
+    pub fn own_illusions(&mut self) -> &[(u8, u8)] {
+        let id = self.wizard.id;
+        let arena = &self.arena;
+        self.illusions
+            .retain(|&(x, y)| arena.get(x, y).creation.as_ref().is_some_and(|creation| creation.id == id));
+        &self.illusions
+    }
+
+    pub fn has_disbelieve_targets(&self) -> bool {
+        let id = self.wizard.id;
+        let (x, y) = match self.arena.maybe_find_wizard_pos(id) {
+            Some(pos) => pos,
+            None => return false,
+        };
+        let range = self.wizard.spells.first().map_or(0, |spell| spell.range);
+        self.arena
+            .cast_spell_on_attackable_tiles(x, y, range, id)
+            .into_iter()
+            .any(|(x, y)| {
+                self.arena
+                    .get(x, y)
+                    .creation
+                    .as_ref()
+                    .is_some_and(|creation| creation.id != id)
+            })
+    }
+
+    pub fn board_presence(&self, id: u32) -> usize {
+        1 + self.arena.creation_count(id)
+    }
+
+    pub fn suggested_spell(&self) -> Option<usize> {
+        let (alignment, spell_ability) = (self.arena.alignment, self.wizard.stats.spell_ability);
+        self.wizard
+            .spells
+            .iter()
+            .enumerate()
+            .filter(|(_, spell)| spell.is_beneficial())
+            .min_by_key(|(_, spell)| Reverse(spell.casting_percentage(alignment, spell_ability)))
+            .map(|(index, _)| index)
+    }
+
+    pub fn nearest_target(&mut self, x: u8, y: u8, tiles: &[(u8, u8)]) -> Option<u8> {
+        let id = self.wizard.id;
+        tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, &(dx, dy))| self.arena.has_attackable_opposition(dx, dy, id) && self.arena.line_of_sight(x, y, dx, dy))
+            .min_by_key(|(_, &(dx, dy))| {
+                let distance = (dx as isize - x as isize).pow(2) + (dy as isize - y as isize).pow(2);
+                (distance, dy, dx)
+            })
+            .map(|(index, _)| index as u8)
+    }
+
+    pub fn visible_targets(&mut self, tiles: &[(u8, u8)]) -> Vec<usize> {
+        let placement = self.casting.as_ref().is_some_and(Spell::is_placement);
+        let (sx, sy) = self.arena.find_wizard_pos(self.wizard.id);
+        let visible = (0..tiles.len())
+            .filter(|&index| {
+                let (dx, dy) = tiles[index];
+                !placement || self.arena.line_of_sight(sx, sy, dx, dy)
+            })
+            .collect::<Vec<_>>();
+        if visible.is_empty() {
+            (0..tiles.len()).collect()
+        } else {
+            visible
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Player;
+    use crate::data::spells::{find_spells, SpellKind};
+    use crate::data::wizard::{GameWizard, LobbyWizards, ServerWizards};
+    use rand::thread_rng;
+
+    fn wizard(id: u32) -> Wizard {
+        let mut lobby = LobbyWizards::new();
+        lobby.join(
+            id,
+            Player {
+                name: format!("WIZARD {id}"),
+                character: (id as isize % 8).try_into().expect("character"),
+                color: (id as isize % 8).try_into().expect("color"),
+            },
+        );
+        ServerWizards::new(lobby, &mut thread_rng()).get(id).expect("wizard").clone()
+    }
+
+    fn goblin(id: u32) -> GameCreation {
+        match find_spells(&["GOBLIN".to_string()], &[]).remove(0).kind {
+            SpellKind::Creation(stats) => GameCreation::new(id, stats),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn a_creation_spell_from_a_wizard_not_on_the_board_still_places_the_creation() {
+        let mut state = ClientState::new(wizard(0));
+        state.casting_illusion = true;
+        assert_eq!(state.arena.maybe_find_wizard_pos(7), None);
+        state.add_creation(7, 4, 5, goblin(7));
+        assert_eq!(state.arena.get_creation(4, 5).id, 7);
+        assert!(state.illusions.is_empty());
+        assert!(!state.has_disbelieve_targets());
+        assert_eq!(state.board_presence(7), 2);
+        state.add_creation(0, 6, 5, goblin(0));
+        assert_eq!(state.illusions, vec![(6, 5)]);
+    }
+
+    #[test]
+    fn only_the_players_own_death_makes_them_a_spectator() {
+        let mut state = ClientState::new(wizard(0));
+        state.arena.get_mut(1, 1).wizard = Some(GameWizard::from(&wizard(0)));
+        state.arena.get_mut(8, 8).wizard = Some(GameWizard::from(&wizard(1)));
+        state.arena.get_mut(2, 1).creation = Some(goblin(0));
+        state.remove_wizard(1);
+        assert!(!state.spectating);
+        assert!(state.arena.maybe_find_wizard_pos(1).is_none());
+        state.remove_wizard(0);
+        assert!(state.spectating);
+        assert!(state.arena.get(2, 1).creation.is_none());
+    }
+
+    #[test]
+    fn only_enemy_creations_count_as_disbelieve_targets() {
+        let mut state = ClientState::new(wizard(0));
+        assert!(!state.has_disbelieve_targets());
+        state.arena.get_mut(1, 1).wizard = Some(GameWizard::from(&wizard(0)));
+        state.arena.get_mut(13, 8).wizard = Some(GameWizard::from(&wizard(1)));
+        assert!(!state.has_disbelieve_targets());
+        state.arena.get_mut(2, 1).creation = Some(goblin(0));
+        assert!(!state.has_disbelieve_targets());
+        state.arena.get_mut(14, 9).creation = Some(goblin(1));
+        assert!(state.has_disbelieve_targets());
+    }
+
+    #[test]
+    fn the_nearest_target_breaks_ties_by_row_then_column() {
+        let mut state = ClientState::new(wizard(0));
+        state.arena.get_mut(7, 5).wizard = Some(GameWizard::from(&wizard(0)));
+        state.arena.get_mut(7, 6).creation = Some(goblin(0));
+        for (x, y) in [(9, 5), (5, 5), (7, 3)] {
+            state.arena.get_mut(x, y).creation = Some(goblin(1));
+        }
+        let mut tiles = vec![(7, 6), (8, 5), (9, 5), (5, 5), (7, 3), (14, 9)];
+        let nearest =
+            |state: &mut ClientState, tiles: &[(u8, u8)]| state.nearest_target(7, 5, tiles).map(|index| tiles[index as usize]);
+        assert_eq!(nearest(&mut state, &tiles), Some((7, 3)));
+        tiles.reverse();
+        assert_eq!(nearest(&mut state, &tiles), Some((7, 3)));
+        state.arena.get_mut(7, 3).creation = None;
+        assert_eq!(nearest(&mut state, &tiles), Some((5, 5)));
+        tiles.reverse();
+        assert_eq!(nearest(&mut state, &tiles), Some((5, 5)));
+        state.arena.get_mut(5, 5).creation = None;
+        state.arena.get_mut(9, 5).creation = None;
+        assert_eq!(nearest(&mut state, &tiles), None);
+    }
+
+    fn hand(spells: &[(&str, u8, i8)]) -> Vec<Spell> {
+        spells
+            .iter()
+            .map(|&(name, chance, alignment)| Spell {
+                chance,
+                alignment,
+                ..find_spells(&[name.to_string()], &[]).remove(0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_suggestion_is_the_first_beneficial_spell_with_the_best_chance() {
+        let mut state = ClientState::new(wizard(0));
+        state.wizard.spells = hand(&[("MAGIC BOLT", 9, 0)]);
+        assert_eq!(state.suggested_spell(), None);
+        state.wizard.spells = hand(&[
+            ("MAGIC BOLT", 9, 0),
+            ("GOBLIN", 3, -1),
+            ("HORSE", 6, 0),
+            ("MAGIC WINGS", 6, 0),
+        ]);
+        assert_eq!(state.suggested_spell(), Some(2));
+        state.arena.alignment = -16;
+        assert_eq!(state.suggested_spell(), Some(1));
+        state.arena.alignment = 16;
+        assert_eq!(state.suggested_spell(), Some(2));
+    }
+
+    #[test]
+    fn subverted_creations_count_towards_their_new_owners_presence() {
+        let mut state = ClientState::new(wizard(0));
+        state.arena.get_mut(1, 1).wizard = Some(GameWizard::from(&wizard(0)));
+        state.arena.get_mut(13, 8).wizard = Some(GameWizard::from(&wizard(1)));
+        for (id, x) in [(0, 2), (0, 3), (1, 11), (1, 12)] {
+            state.arena.get_mut(x, 5).creation = Some(goblin(id));
         }
+        assert_eq!((state.board_presence(0), state.board_presence(1)), (3, 3));
+        state.arena.subvert(2, 5, 1);
+        assert_eq!((state.board_presence(0), state.board_presence(1)), (2, 4));
     }
 }
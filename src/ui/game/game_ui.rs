@@ -1,27 +1,92 @@
 use super::client_state::ClientState;
-use crate::config::Player;
+use crate::config::{Options, Player};
 use crate::data::arena::Arena;
 use crate::data::spells::Spell;
 use crate::data::stats::Frame;
 use crate::data::wizard::WizardColor;
+use crate::data::{frame_timing, ANIMATION_SPEED};
 use crate::error::ChaosError;
 use crate::gfx::buffer::{Buffer, MouseCursor};
 use crate::gfx::color::Color::{self, *};
 use crate::gfx::fx::{ATTACK_FX, DRAGON_BURN_FX, EXPLODING_CIRCLE_FX, EXPLOSION_FX, TWIRL_FX};
+use crate::net::{ClientSender, Message, CLIENT_STATS, SERVER_STATS};
+use crate::sound::{Silence, SoundSink};
 use crate::window::{Key, Window};
 use std::cmp::Ordering;
+use std::sync::atomic;
 use std::time::Instant;
 
-fn preview_spell_coords(x: usize, y: usize, spells: &[Spell]) -> Option<usize> {
-    if (2..22).contains(&y) && (1..31).contains(&x) {
-        let index = ((y - 2) / 2 * 2) + (x - 1) / 16;
+const SPELL_ROWS: usize = 10;
+const DEATH_FLASH_FRAMES: u8 = 8;
+const PRESENCE_BAR_WIDTH: usize = 60;
+const TIMER_X: usize = 24;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SpellLayout {
+    Names(usize),
+    Icons,
+}
+
+impl SpellLayout {
+    pub fn from_options(options: &Options) -> Self {
+        if options.spell_icons {
+            SpellLayout::Icons
+        } else if options.single_column_spells {
+            SpellLayout::Names(1)
+        } else {
+            SpellLayout::Names(2)
+        }
+    }
+
+    fn columns(&self) -> usize {
+        match self {
+            SpellLayout::Names(columns) => *columns,
+            SpellLayout::Icons => 10,
+        }
+    }
+
+    fn pitch(&self) -> usize {
+        match self {
+            SpellLayout::Names(_) => 16,
+            SpellLayout::Icons => 3,
+        }
+    }
+
+    fn name_width(&self) -> usize {
+        match self {
+            SpellLayout::Names(1) => 30,
+            _ => 15,
+        }
+    }
+
+    fn slot_len(&self, spell: &Spell, show_percentages: bool) -> usize {
+        match self {
+            SpellLayout::Icons => 1,
+            SpellLayout::Names(_) if show_percentages => self.name_width() - 1,
+            SpellLayout::Names(_) => spell.name.len(),
+        }
+    }
+
+    fn slot_coords(&self, slot: usize) -> (usize, usize) {
+        let columns = self.columns();
+        (1 + (slot % columns) * self.pitch(), 2 + (slot / columns) * 2)
+    }
+}
+
+fn preview_spell_coords(
+    x: usize,
+    y: usize,
+    spells: &[Spell],
+    show_percentages: bool,
+    scroll: usize,
+    layout: SpellLayout,
+) -> Option<usize> {
+    if (2..22).contains(&y) && (1..32).contains(&x) {
+        let columns = layout.columns();
+        let column = ((x - 1) / layout.pitch()).min(columns - 1);
+        let index = (scroll + (y - 2) / 2) * columns + column;
         if let Some(spell) = spells.get(index) {
-            let len = spell.name.len();
-            if index % 2 == 0 {
-                if (x - 1) <= len {
-                    return Some(index);
-                }
-            } else if (x - 17) <= len {
+            if (x - 1 - column * layout.pitch()) <= layout.slot_len(spell, show_percentages) {
                 return Some(index);
             }
         }
@@ -29,6 +94,47 @@ fn preview_spell_coords(x: usize, y: usize, spells: &[Spell]) -> Option<usize> {
     None
 }
 
+fn alignment_symbols(symbol: &str, count: usize) -> String {
+    if count > 16 {
+        format!("{symbol}X{count}")
+    } else {
+        symbol.repeat(count)
+    }
+}
+
+fn alignment_text(alignment: i8) -> Option<String> {
+    let count = (alignment.unsigned_abs() / 2) as usize;
+    match alignment.cmp(&0) {
+        Ordering::Less => Some(format!("(CHAOS {})", alignment_symbols("*", count))),
+        Ordering::Greater => Some(format!("(LAW {})", alignment_symbols("^", count))),
+        Ordering::Equal => None,
+    }
+}
+
+// spectators get an empty list if the last wizards disconnect as the final round ends
+fn risky_percentage(spell: &Spell, alignment: i8, spell_ability: u8, threshold: u8) -> Option<u16> {
+    let percentage = spell.casting_percentage(alignment, spell_ability);
+    (percentage < threshold as u16).then_some(percentage)
+}
+
+fn elapsed_text(secs: u64) -> String {
+    let secs = secs.min(10 * 3600 - 1);
+    if secs < 3600 {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    } else {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    }
+}
+
+fn contest_drawn(winners: &[Player]) -> bool {
+    winners.len() != 1
+}
+
+fn death_flash_frames(frames: u8) -> impl Iterator<Item = bool> {
+    let frames = frames.max(2);
+    (0..frames).map(move |frame| frame % 2 == 0 && frame + 1 < frames)
+}
+
 fn preview_arena_coords(x: usize, y: usize) -> Option<(u8, u8)> {
     if (33..63).contains(&x) && (1..21).contains(&y) {
         let (x, y) = ((x - 33) / 2, (y - 1) / 2);
@@ -61,15 +167,54 @@ pub struct InfoPanel {
     pos: MousePosition,
     current_buf_index: usize,
     wizards: Vec<(u32, String)>,
+    focus: Option<usize>,
+    spell_percentages: bool,
+    chance_thresholds: bool,
+    spell_layout: SpellLayout,
+    spell_scroll: usize,
+    stranded: Vec<u32>,
+    presence_bars: bool,
 }
 
 impl InfoPanel {
-    pub fn new() -> Self {
+    pub fn new(spell_percentages: bool, chance_thresholds: bool, spell_layout: SpellLayout, presence_bars: bool) -> Self {
         Self {
             buf: Buffer::new(32, 24),
             pos: MousePosition::None,
             current_buf_index: 0,
             wizards: Vec::new(),
+            focus: None,
+            spell_percentages,
+            chance_thresholds,
+            spell_layout,
+            spell_scroll: 0,
+            stranded: Vec::new(),
+            presence_bars,
+        }
+    }
+
+    fn cycle_focus(&mut self, win: &mut Window, state: &ClientState) {
+        if state.arena.maybe_find_wizard_pos(state.wizard.id).is_some() {
+            self.focus = None;
+        } else if win.key_pressed(Key::Tab) {
+            self.focus = match self.focus {
+                None if !self.wizards.is_empty() => Some(0),
+                Some(index) if index + 1 < self.wizards.len() => Some(index + 1),
+                _ => None,
+            };
+        }
+    }
+
+    fn highlight_pieces(win: &mut Window, state: &mut ClientState, id: u32) {
+        if let Some((x, y)) = state.arena.maybe_find_wizard_pos(id) {
+            let frame = state.arena.get_visible_frame(x, y).swap_colors();
+            let buf = Buffer::from(&frame);
+            win.buf.draw_buffer(&buf, x as usize * 2 + 33, y as usize * 2 + 1);
+            for (x, y) in state.arena.get_topmost_creations_and_corpses_coords(id) {
+                let frame = state.arena.get_visible_frame(x, y).swap_colors();
+                let buf = Buffer::from(&frame);
+                win.buf.draw_buffer(&buf, x as usize * 2 + 33, y as usize * 2 + 1);
+            }
         }
     }
 
@@ -77,6 +222,43 @@ impl InfoPanel {
         self.wizards.push((id, name.to_string()));
     }
 
+    pub fn set_stranded(&mut self, id: u32, stranded: bool) {
+        self.stranded.retain(|stranded_id| *stranded_id != id);
+        if stranded {
+            self.stranded.push(id);
+        }
+    }
+
+    pub fn set_order(&mut self, order: &[u32]) {
+        self.wizards
+            .sort_by_key(|(id, _)| order.iter().position(|order_id| order_id == id).unwrap_or(order.len()));
+    }
+
+    fn draw_presence_bars(&mut self, state: &ClientState) {
+        let presence: Vec<_> = self
+            .wizards
+            .iter()
+            .map(|(id, _)| {
+                let (x, y) = state.arena.maybe_find_wizard_pos(*id)?;
+                Some((state.board_presence(*id), state.arena.get_wizard(x, y).color))
+            })
+            .collect();
+        let most = presence.iter().flatten().map(|(count, _)| *count).max().unwrap_or(1);
+        for (i, (count, color)) in presence
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, bar)| bar.map(|bar| (i, bar)))
+        {
+            let width = (count * PRESENCE_BAR_WIDTH / most).max(2);
+            let y = (4 + i * 2) * 8;
+            for px in 12..12 + width {
+                for py in y + 5..y + 11 {
+                    self.buf.put_pixel(px, py, color.into());
+                }
+            }
+        }
+    }
+
     pub fn draw_names(&mut self, win: &mut Window, state: &mut ClientState) -> Result<(), ChaosError> {
         self.buf.clear();
         let text = if state.turns_left == 1 {
@@ -85,11 +267,21 @@ impl InfoPanel {
             format!("{} TURNS LEFT", state.turns_left)
         };
         self.buf.screen_border(&text, BrightGreen, Black);
+        if self.presence_bars {
+            self.draw_presence_bars(state);
+        }
         for (i, (id, name)) in self.wizards.iter().enumerate() {
             let mut name_x = (32 - name.len()) / 2;
             let name_y = 4 + i * 2;
             if let Some((x, y)) = state.arena.maybe_find_wizard_pos(*id) {
-                self.buf.draw_text(name, name_x, name_y, BrightYellow);
+                let color = if self.focus == Some(i) {
+                    BrightCyan
+                } else if self.stranded.contains(id) {
+                    Magenta
+                } else {
+                    BrightYellow
+                };
+                self.buf.draw_text(name, name_x, name_y, color);
                 name_x += name.len();
                 let buf = state.arena.get_visible_buffer(x, y);
                 self.buf.draw_buffer(buf, name_x, name_y);
@@ -97,26 +289,26 @@ impl InfoPanel {
                 self.buf.draw_text(name, name_x, name_y, BrightRed);
             }
         }
-        if let MousePosition::Name(index) = self.pos {
-            if let Some((id, _)) = self.wizards.get(index) {
-                if let Some((x, y)) = state.arena.maybe_find_wizard_pos(*id) {
-                    let frame = state.arena.get_visible_frame(x, y).swap_colors();
-                    let buf = Buffer::from(&frame);
-                    win.buf.draw_buffer(&buf, x as usize * 2 + 33, y as usize * 2 + 1);
-                    for (x, y) in state.arena.get_topmost_creations_and_corpses_coords(*id) {
-                        let frame = state.arena.get_visible_frame(x, y).swap_colors();
-                        let buf = Buffer::from(&frame);
-                        win.buf.draw_buffer(&buf, x as usize * 2 + 33, y as usize * 2 + 1);
-                    }
-                }
-            }
+        let index = match self.pos {
+            MousePosition::Name(index) => Some(index),
+            _ => self.focus,
+        };
+        if let Some((id, _)) = index.and_then(|index| self.wizards.get(index)) {
+            Self::highlight_pieces(win, state, *id);
         }
         Ok(())
     }
 
     fn get_mouse_over(&mut self, win: &mut Window, state: &mut ClientState) -> MousePosition {
         if let Some((x, y)) = win.mouse_coords() {
-            if let Some(index) = preview_spell_coords(x, y, &state.wizard.spells) {
+            if let Some(index) = preview_spell_coords(
+                x,
+                y,
+                &state.wizard.spells,
+                self.spell_percentages,
+                self.spell_scroll,
+                self.spell_layout,
+            ) {
                 return MousePosition::Spell(index);
             } else if let Some((x, y)) = preview_arena_coords(x, y) {
                 return MousePosition::Tile(x, y);
@@ -128,12 +320,17 @@ impl InfoPanel {
     }
 
     pub fn render(&mut self, win: &mut Window, state: &mut ClientState) -> Result<(), ChaosError> {
+        self.cycle_focus(win, state);
         let now = self.get_mouse_over(win, state);
         if self.pos != now {
             match now {
                 MousePosition::Spell(index) => {
                     if let Some(spell) = state.wizard.spells.get(index) {
-                        let buf = spell.as_info_buffer(state.arena.alignment, state.wizard.stats.spell_ability);
+                        let buf = spell.as_info_buffer(
+                            state.arena.alignment,
+                            state.wizard.stats.spell_ability,
+                            self.chance_thresholds,
+                        );
                         self.buf.draw_buffer(&buf, 0, 0);
                     }
                 }
@@ -181,11 +378,48 @@ impl InfoPanel {
 
 pub struct GameUI {
     pub panel: InfoPanel,
+    pub paused: bool,
+    pub out_of_sync: bool,
+    options: Options,
+    cursor_tic: u8,
+    line_of_sight_source: Option<(u8, u8)>,
+    help: bool,
+    net_stats: bool,
+    sound: Box<dyn SoundSink>,
+    last_affected: Vec<(u8, u8)>,
+    targeted: bool,
+    spell_filter: Option<String>,
+    sender: ClientSender,
+    border_color: Color,
 }
 
 impl GameUI {
-    pub fn new(win: &mut Window, state: &mut ClientState) -> Self {
-        let ui = GameUI { panel: InfoPanel::new() };
+    pub fn new(win: &mut Window, state: &mut ClientState, options: Options, sender: ClientSender) -> Self {
+        state.arena.show_facing = options.sprite_facing;
+        ANIMATION_SPEED.store(options.animation_speed, atomic::Ordering::Relaxed);
+        let mut sound = Box::new(Silence);
+        sound.set_volume(options.effective_volume());
+        let mut ui = GameUI {
+            panel: InfoPanel::new(
+                options.spell_percentages,
+                options.chance_thresholds,
+                SpellLayout::from_options(&options),
+                options.presence_bars,
+            ),
+            paused: false,
+            out_of_sync: false,
+            options,
+            cursor_tic: 0,
+            line_of_sight_source: None,
+            help: false,
+            net_stats: false,
+            sound,
+            last_affected: Vec::new(),
+            targeted: false,
+            spell_filter: None,
+            sender,
+            border_color: BrightBlue,
+        };
         win.buf.clear();
         let text = format!("{}'S SPELLS", state.wizard.player.name);
         win.buf.draw_text(&text, 2, 0, BrightYellow);
@@ -195,7 +429,8 @@ impl GameUI {
         ui
     }
 
-    pub fn border(&self, win: &mut Window, color: Color) {
+    pub fn border(&mut self, win: &mut Window, color: Color) {
+        self.border_color = color;
         win.buf.border(32, 0, 32, 22, color, BrightBlack);
     }
 
@@ -240,6 +475,7 @@ impl GameUI {
         spell_name: String,
         range: u8,
     ) -> Result<(), ChaosError> {
+        self.targeted = false;
         let wizard_name = state.arena.find_wizard(id).name.clone();
         self.draw_spell_cast_info(win, &wizard_name, None, None);
         self.wait_for(win, state, 800)?;
@@ -252,31 +488,51 @@ impl GameUI {
 
     pub fn update_alignment(&self, win: &mut Window, state: &mut ClientState) {
         let mut buf = Buffer::new(32, 2);
-        let text = match state.arena.alignment.cmp(&0) {
-            Ordering::Less => {
-                let symbols = vec!["*"; (state.arena.alignment.abs() / 2) as usize];
-                Some(format!("(CHAOS {symbols})", symbols = symbols.join("")))
-            }
-            Ordering::Greater => {
-                let symbols = vec!["^"; (state.arena.alignment / 2) as usize];
-                Some(format!("(LAW {symbols})", symbols = symbols.join("")))
+        if let Some(text) = alignment_text(state.arena.alignment) {
+            buf.center_text(&text, 0, BrightYellow);
+        }
+        if self.options.alignment_trend {
+            for (i, alignment) in state.alignment_history.iter().enumerate() {
+                let x = i * 8;
+                let y = (12 - (alignment / 4).clamp(-3, 3)) as usize;
+                let color = match alignment.cmp(&0) {
+                    Ordering::Less => BrightRed,
+                    Ordering::Greater => BrightCyan,
+                    Ordering::Equal => BrightWhite,
+                };
+                for px in x + 1..x + 7 {
+                    buf.put_pixel(px, 12, Blue);
+                    buf.put_pixel(px, y, color);
+                }
             }
-            Ordering::Equal => None,
-        };
-        if let Some(ref text) = text {
-            buf.center_text(text, 0, BrightYellow);
         }
         win.buf.draw_buffer(&buf, 0, 22);
     }
 
     pub fn update_spells(&self, win: &mut Window, state: &mut ClientState) {
         let mut buf = Buffer::new(32, 20);
+        let layout = self.panel.spell_layout;
+        let first = self.panel.spell_scroll * layout.columns();
+        let visible = first..state.wizard.spells.len().min(first + SPELL_ROWS * layout.columns());
+        let suggested = self.suggested_spell(state);
         for (i, spell) in state.wizard.spells.iter_mut().enumerate() {
-            let name_buf = spell.as_name_buffer(state.arena.alignment, state.wizard.stats.spell_ability);
-            if i % 2 == 0 {
-                buf.draw_buffer(&name_buf, 1, (i / 2) * 2);
-            } else {
-                buf.draw_buffer(&name_buf, 17, (i / 2) * 2);
+            if !visible.contains(&i) || !self.spell_shown(spell) {
+                continue;
+            }
+            let spell_buf = match layout {
+                SpellLayout::Icons => spell.as_icon_buffer(state.arena.alignment, state.wizard.stats.spell_ability),
+                SpellLayout::Names(_) => spell.as_name_buffer(
+                    state.arena.alignment,
+                    state.wizard.stats.spell_ability,
+                    self.options.spell_percentages,
+                    self.options.chance_thresholds,
+                    layout.name_width(),
+                ),
+            };
+            let (x, y) = layout.slot_coords(i - first);
+            buf.draw_buffer(&spell_buf, x, y - 2);
+            if suggested == Some(i) {
+                buf.draw_text_with_bg(spell.alignment_symbol(), x, y - 2, BrightWhite, Blue);
             }
         }
         win.buf.draw_buffer(&buf, 0, 2);
@@ -315,47 +571,342 @@ impl GameUI {
         }
     }
 
+    fn confirm_risky_spell(&mut self, win: &mut Window, state: &mut ClientState, index: usize) -> Result<bool, ChaosError> {
+        let spell = &state.wizard.spells[index];
+        let ability = state.wizard.stats.spell_ability;
+        let percentage = match risky_percentage(spell, state.arena.alignment, ability, self.options.confirm_below_chance) {
+            Some(percentage) => percentage,
+            None => return Ok(true),
+        };
+        let text = format!("ONLY {}% CHANCE, CAST? (Y OR N)", percentage);
+        self.set_status(win, &text, BrightWhite);
+        loop {
+            win.update()?;
+            if let Some(key) = win.get_yes_or_no_or_cancel() {
+                self.clear_status(win);
+                match key {
+                    Key::Y => {
+                        return Ok(true);
+                    }
+                    _ => {
+                        self.set_status(win, "CHOOSE A SPELL", BrightYellow);
+                        return Ok(false);
+                    }
+                }
+            }
+            self.render(win, state)?;
+        }
+    }
+
+    pub fn suggested_spell(&self, state: &ClientState) -> Option<usize> {
+        if self.options.suggest_spell {
+            state.suggested_spell()
+        } else {
+            None
+        }
+    }
+
+    fn spell_shown(&self, spell: &Spell) -> bool {
+        match self.spell_filter {
+            Some(ref filter) => spell.matches(filter),
+            None => true,
+        }
+    }
+
+    fn set_spell_filter(&mut self, win: &mut Window, state: &mut ClientState, filter: Option<String>) {
+        match filter {
+            Some(ref filter) => {
+                let content = [("SEARCH:", BrightGreen), (filter, BrightWhite)];
+                self.multi_color_status(win, &content);
+            }
+            None => self.set_status(win, "CHOOSE A SPELL", BrightYellow),
+        }
+        self.spell_filter = filter;
+        self.update_spells(win, state);
+    }
+
+    fn search_spells(&mut self, win: &mut Window, state: &mut ClientState) -> bool {
+        let mut filter = match self.spell_filter.clone() {
+            Some(filter) => filter,
+            None => {
+                if win.key_pressed(Key::Slash) {
+                    self.set_spell_filter(win, state, Some(String::new()));
+                }
+                return false;
+            }
+        };
+        if win.escape_pressed() {
+            self.set_spell_filter(win, state, None);
+            return true;
+        }
+        let chars = win.typed_chars();
+        let erased = win.key_pressed(Key::Backspace) && filter.pop().is_some();
+        if !chars.is_empty() || erased {
+            filter.extend(chars.into_iter().take(16usize.saturating_sub(filter.len())));
+            self.set_spell_filter(win, state, Some(filter));
+        }
+        true
+    }
+
     pub fn choose_spell(&mut self, win: &mut Window, state: &mut ClientState) -> Result<Option<(u32, bool)>, ChaosError> {
+        let choice = self.choose_spell_from_filter(win, state);
+        if self.spell_filter.is_some() {
+            self.spell_filter = None;
+            self.update_spells(win, state);
+        }
+        choice
+    }
+
+    fn choose_spell_from_filter(&mut self, win: &mut Window, state: &mut ClientState) -> Result<Option<(u32, bool)>, ChaosError> {
         loop {
             win.update()?;
-            if win.escape_pressed() {
+            if !self.search_spells(win, state) && win.escape_pressed() {
                 return Ok(None);
             }
-            if win.mouse_clicked() {
-                if let MousePosition::Spell(index) = self.panel.pos {
-                    if let Some(spell) = state.wizard.spells.get(index) {
-                        if spell.is_creation() {
-                            if let Some(illusion) = self.ask_if_illusion(win, state)? {
-                                return Ok(Some((index as u32, illusion)));
-                            }
-                        } else {
-                            return Ok(Some((index as u32, false)));
+            self.scroll_spells(win, state);
+            let chosen = match self.panel.pos {
+                MousePosition::Spell(index) if win.mouse_clicked() => Some(index),
+                _ if self.spell_filter.is_none() && win.key_pressed(Key::Enter) => self.suggested_spell(state),
+                _ => None,
+            };
+            if let Some(index) = chosen {
+                if let Some(spell) = state.wizard.spells.get(index).filter(|spell| self.spell_shown(spell)) {
+                    let illusion = if spell.is_creation() {
+                        self.ask_if_illusion(win, state)?
+                    } else {
+                        Some(false)
+                    };
+                    if let Some(illusion) = illusion {
+                        if illusion || self.confirm_risky_spell(win, state, index)? {
+                            return Ok(Some((index as u32, illusion)));
                         }
                     }
                 }
             }
             self.render(win, state)?;
+            if !self.help && !self.net_stats {
+                self.render_spell_range(win, state);
+            }
+        }
+    }
+
+    fn scroll_spells(&mut self, win: &mut Window, state: &mut ClientState) {
+        let scroll = self.panel.spell_scroll;
+        if win.key_pressed(Key::PageDown) && (scroll + SPELL_ROWS) * self.panel.spell_layout.columns() < state.wizard.spells.len()
+        {
+            self.panel.spell_scroll += 1;
+        }
+        if win.key_pressed(Key::PageUp) && scroll > 0 {
+            self.panel.spell_scroll -= 1;
+        }
+        if self.panel.spell_scroll != scroll {
+            self.update_spells(win, state);
+        }
+    }
+
+    fn render_spell_range(&self, win: &mut Window, state: &ClientState) {
+        if let MousePosition::Spell(index) = self.panel.pos {
+            if let Some(spell) = state.wizard.spells.get(index).filter(|spell| self.spell_shown(spell)) {
+                if let Some((x, y)) = state.arena.maybe_find_wizard_pos(state.wizard.id) {
+                    for (x, y) in state.arena.tiles_in_spell_range(x, y, spell.range) {
+                        let x = 33 + (x * 2) as usize;
+                        let y = 1 + (y * 2) as usize;
+                        win.buf.draw_mouse_cursor(x, y, &MouseCursor::Corners, Blue);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn mark_affected(&mut self, state: &ClientState, id: u32, x: u8, y: u8) {
+        if id != state.wizard.id && !self.last_affected.contains(&(x, y)) {
+            self.last_affected.push((x, y));
         }
     }
 
+    pub fn clear_affected(&mut self) {
+        self.last_affected.clear();
+    }
+
     pub fn render(&mut self, win: &mut Window, state: &mut ClientState) -> Result<(), ChaosError> {
+        win.buf.border(32, 0, 32, 22, self.border_color, BrightBlack);
         win.buf.draw_buffer(&Buffer::from(&mut state.arena), 33, 1);
+        if self.options.owner_markers {
+            for (x, y, color) in state.arena.creation_owners() {
+                let x = 33 + (x * 2) as usize;
+                let y = 1 + (y * 2) as usize;
+                win.buf.draw_mouse_cursor(x, y, &MouseCursor::Marker, color.into());
+            }
+        }
+        for (x, y) in state.own_illusions() {
+            let x = 33 + (x * 2) as usize;
+            let y = 1 + (y * 2) as usize;
+            win.buf.draw_mouse_cursor(x, y, &MouseCursor::Illusion, BrightMagenta);
+        }
+        for (x, y) in &self.last_affected {
+            let x = 33 + (x * 2) as usize;
+            let y = 1 + (y * 2) as usize;
+            win.buf.draw_mouse_cursor(x, y, &MouseCursor::Corners, White);
+        }
+        if self.options.timers {
+            self.render_timers(win, state);
+        }
+        if state.spectating {
+            win.buf
+                .draw_text_with_bg("DEFEATED - TAB TO WATCH", 36, 0, BrightWhite, BrightBlack);
+        }
+        if self.out_of_sync {
+            win.buf.draw_text_with_bg("OUT OF SYNC WITH SERVER", 36, 21, BrightWhite, Red);
+        }
         self.panel.render(win, state)?;
+        if self.options.debug_line_of_sight {
+            self.render_line_of_sight(win, state);
+        }
+        if win.key_pressed(Key::F1) {
+            self.help = !self.help;
+        }
+        if win.key_pressed(Key::F2) {
+            self.net_stats = !self.net_stats;
+        }
+        if self.spell_filter.is_none() && win.key_pressed(Key::P) {
+            self.sender.send(Message::Pause(!self.paused))?;
+        }
+        if self.spell_filter.is_none() && win.key_pressed(Key::M) {
+            self.options.muted = !self.options.muted;
+            self.sound.set_volume(self.options.effective_volume());
+        }
+        if !win.is_focused() {
+            win.buf.fill_area(34, 8, 28, 6, Black);
+            win.buf.border(34, 8, 28, 6, BrightYellow, Black);
+            win.buf.draw_text("PAUSED (CLICK TO RESUME)", 36, 10, BrightYellow);
+        } else if self.help {
+            self.render_help(win);
+        } else if self.net_stats {
+            self.render_net_stats(win);
+        } else if self.paused {
+            win.buf.fill_area(40, 8, 16, 6, Black);
+            win.buf.border(40, 8, 16, 6, BrightYellow, Black);
+            win.buf.draw_text("PAUSED", 45, 10, BrightYellow);
+        }
         Ok(())
     }
 
+    fn render_timers(&self, win: &mut Window, state: &ClientState) {
+        win.buf.clear_area(TIMER_X, 0, 32 - TIMER_X, 2);
+        let (started, color) = match (state.turn_started, state.started) {
+            (Some(turn_started), _) => (turn_started, BrightCyan),
+            (None, Some(started)) => (started, White),
+            (None, None) => return,
+        };
+        let text = elapsed_text(started.elapsed().as_secs());
+        win.buf.draw_text(&text, 32 - text.len(), 0, color);
+    }
+
+    fn render_help(&self, win: &mut Window) {
+        let controls = [
+            ("CLICK", "SELECT"),
+            ("/", "SEARCH SPELLS"),
+            ("ESC", "CANCEL OR PASS"),
+            ("Y/N", "ANSWER QUESTIONS"),
+            ("UP/DOWN", "INFO PAGES"),
+            ("M", "MUTE SOUND"),
+            ("P", "PAUSE (HOST)"),
+            ("F2", "NETWORK STATS"),
+            ("F1", "CLOSE HELP"),
+        ];
+        win.buf.fill_area(34, 0, 28, 22, Black);
+        win.buf.border(34, 0, 28, 22, BrightCyan, Black);
+        win.buf.draw_text("CONTROLS", 44, 1, BrightYellow);
+        for (i, (key, action)) in controls.iter().enumerate() {
+            win.buf.draw_text(key, 36, 3 + i * 2, BrightWhite);
+            win.buf.draw_text(action, 44, 3 + i * 2, BrightCyan);
+        }
+    }
+
+    fn render_net_stats(&self, win: &mut Window) {
+        let mut stats = vec![("SENT", CLIENT_STATS.sent()), ("RECEIVED", CLIENT_STATS.received())];
+        // only the process running the server ever counts server traffic
+        let hosting = SERVER_STATS.sent().0 > 0;
+        if hosting {
+            stats.extend([("HOST SENT", SERVER_STATS.sent()), ("HOST RECV", SERVER_STATS.received())]);
+        }
+        let top = if hosting { 1 } else { 5 };
+        win.buf.fill_area(34, top, 28, stats.len() * 4 + 4, Black);
+        win.buf.border(34, top, 28, stats.len() * 4 + 4, BrightCyan, Black);
+        win.buf.draw_text("NETWORK STATS", 41, top + 1, BrightYellow);
+        for (i, (label, (messages, bytes))) in stats.into_iter().enumerate() {
+            let y = top + 4 + i * 4;
+            win.buf.draw_text(label, 36, y, BrightWhite);
+            win.buf.draw_text(&format!("{messages} MSGS"), 46, y, BrightCyan);
+            win.buf.draw_text(&format!("{bytes} BYTES"), 46, y + 2, BrightCyan);
+        }
+    }
+
+    fn render_line_of_sight(&mut self, win: &mut Window, state: &mut ClientState) {
+        if let MousePosition::Tile(x, y) = self.panel.pos {
+            if win.key_pressed(Key::L) {
+                self.line_of_sight_source = Some((x, y));
+            }
+            if let Some((sx, sy)) = self.line_of_sight_source {
+                if sx != x || sy != y {
+                    for (px, py, clear) in state.arena.line_of_sight_samples(sx, sy, x, y) {
+                        let color = if clear { BrightGreen } else { BrightRed };
+                        win.buf.draw_spell_cross(33 * 8 + px, 8 + py, color);
+                    }
+                }
+            }
+        }
+    }
+
     pub fn choose_tile(
         &mut self,
         win: &mut Window,
         state: &mut ClientState,
         tiles: Vec<(u8, u8)>,
         color: Color,
+    ) -> Result<Option<u8>, ChaosError> {
+        self.choose_tile_or_end(win, state, tiles, color, None)
+    }
+
+    pub fn choose_tile_or_end(
+        &mut self,
+        win: &mut Window,
+        state: &mut ClientState,
+        tiles: Vec<(u8, u8)>,
+        color: Color,
+        end_key: Option<Key>,
+    ) -> Result<Option<u8>, ChaosError> {
+        self.choose_tile_with_shortcut(win, state, tiles, color, end_key, None)
+    }
+
+    pub fn choose_tile_or_nearest(
+        &mut self,
+        win: &mut Window,
+        state: &mut ClientState,
+        tiles: Vec<(u8, u8)>,
+        color: Color,
+        nearest: Option<u8>,
+    ) -> Result<Option<u8>, ChaosError> {
+        self.choose_tile_with_shortcut(win, state, tiles, color, None, nearest)
+    }
+
+    fn choose_tile_with_shortcut(
+        &mut self,
+        win: &mut Window,
+        state: &mut ClientState,
+        tiles: Vec<(u8, u8)>,
+        color: Color,
+        end_key: Option<Key>,
+        nearest: Option<u8>,
     ) -> Result<Option<u8>, ChaosError> {
         loop {
             win.update()?;
-            if win.escape_pressed() {
+            if win.escape_pressed() || end_key.is_some_and(|key| win.key_pressed(key)) {
                 return Ok(None);
             }
+            if nearest.is_some() && win.key_pressed(Key::N) {
+                return Ok(nearest);
+            }
             if win.mouse_clicked() {
                 if let MousePosition::Tile(mouse_x, mouse_y) = self.panel.pos {
                     if let Some((index, _)) = tiles.iter().enumerate().find(|(_, (x, y))| mouse_x == *x && mouse_y == *y) {
@@ -364,15 +915,25 @@ impl GameUI {
                 }
             }
             self.render(win, state)?;
-            self.render_tiles(win, &tiles, color)?;
+            if !self.help && !self.net_stats {
+                self.render_tiles(win, &tiles, color)?;
+            }
+            self.cursor_tic = (self.cursor_tic + 1) % 16;
         }
     }
 
     pub fn render_tiles(&self, win: &mut Window, tiles: &[(u8, u8)], color: Color) -> Result<(), ChaosError> {
+        let (cursor, color) = if !self.options.high_visibility_cursor {
+            (MouseCursor::Box, color)
+        } else if self.cursor_tic < 8 {
+            (MouseCursor::BoldBox, color)
+        } else {
+            (MouseCursor::BoldBox, BrightWhite)
+        };
         for (x, y) in tiles {
             let x = 33 + (x * 2) as usize;
             let y = 1 + (y * 2) as usize;
-            win.buf.draw_mouse_cursor(x, y, &MouseCursor::Box, color);
+            win.buf.draw_mouse_cursor(x, y, &cursor, color);
         }
         Ok(())
     }
@@ -416,6 +977,30 @@ impl GameUI {
         Ok(())
     }
 
+    pub fn fizzle(&mut self, win: &mut Window, state: &mut ClientState, id: u32) -> Result<(), ChaosError> {
+        if self.targeted {
+            return Ok(());
+        }
+        let (x, y) = match state.arena.maybe_find_wizard_pos(id) {
+            Some(pos) => pos,
+            None => return Ok(()),
+        };
+        let cx = (33 + x as isize * 2) * 8 + 8;
+        let cy = (1 + y as isize * 2) * 8 + 8;
+        let sparks = [(0, -2), (2, -1), (2, 1), (0, 2), (-2, 1), (-2, -1)];
+        for frame in 1..=8 {
+            self.render(win, state)?;
+            let color = if frame < 5 { BrightWhite } else { Magenta };
+            for (dx, dy) in sparks {
+                let px = cx + dx * frame / 2;
+                let py = cy + dy * frame / 2;
+                win.buf.draw_spell_cross(px as usize, py as usize, color);
+            }
+            win.update()?;
+        }
+        Ok(())
+    }
+
     pub fn twirl(&mut self, win: &mut Window, state: &mut ClientState, x: u8, y: u8) -> Result<(), ChaosError> {
         for _ in 0..3 {
             for i in 0..4 {
@@ -466,6 +1051,7 @@ impl GameUI {
         dx: u8,
         dy: u8,
     ) -> Result<(), ChaosError> {
+        self.targeted = true;
         self.fireball(win, state, sx, sy, dx, dy)?;
         for buf in EXPLODING_CIRCLE_FX.iter() {
             self.fx(win, state, buf, dx, dy, 4)?;
@@ -529,13 +1115,13 @@ impl GameUI {
         }
     }
 
-    pub fn results(&mut self, win: &mut Window, players: Vec<Player>) -> Result<(), ChaosError> {
+    pub fn results(&mut self, win: &mut Window, players: Vec<Player>) -> Result<bool, ChaosError> {
         win.buf.clear();
-        if players.len() > 1 {
+        if contest_drawn(&players) {
             loop {
                 for color_index in 1..=7 {
                     let color = Color::try_from(color_index + 8).expect("invalid color");
-                    win.buf.screen_border("PRESS ANY KEY", color, Black);
+                    win.buf.screen_border("PLAY AGAIN? (Y OR N)", color, Black);
                     let title_color = Color::try_from((color_index + 1) % 7 + 9).expect("invalid color");
                     win.buf.center_text("THE CONTEST IS DRAWN BETWEEN", 2, title_color);
                     for (player_index, player) in players.iter().enumerate() {
@@ -546,8 +1132,10 @@ impl GameUI {
                     }
                     for _ in 0..8 {
                         win.update()?;
-                        if win.any_key_pressed() {
-                            return Ok(());
+                        match win.get_yes_or_no_or_cancel() {
+                            Some(Key::Y) => return Ok(true),
+                            Some(_) => return Ok(false),
+                            None => {}
                         }
                     }
                 }
@@ -556,7 +1144,7 @@ impl GameUI {
             loop {
                 for color_index in 1..=7 {
                     let color = Color::try_from(color_index + 8).expect("invalid color");
-                    win.buf.screen_border("PRESS ANY KEY", color, Black);
+                    win.buf.screen_border("PLAY AGAIN? (Y OR N)", color, Black);
                     let title_color = Color::try_from((color_index + 1) % 7 + 9).expect("invalid color");
                     win.buf.center_text("THE WINNER IS:", 4, title_color);
                     let lawful_border_color = Color::try_from((color_index + 2) % 7 + 9).expect("invalid color");
@@ -570,8 +1158,10 @@ impl GameUI {
                     win.buf.center_text(&player.name, 12, player_color);
                     for _ in 0..8 {
                         win.update()?;
-                        if win.any_key_pressed() {
-                            return Ok(());
+                        match win.get_yes_or_no_or_cancel() {
+                            Some(Key::Y) => return Ok(true),
+                            Some(_) => return Ok(false),
+                            None => {}
                         }
                     }
                 }
@@ -588,6 +1178,7 @@ impl GameUI {
         dx: u8,
         dy: u8,
     ) -> Result<(), ChaosError> {
+        self.targeted = true;
         let points = Arena::line_coords(sx, sy, dx, dy);
         for start in (0..points.len() + 30).step_by(4) {
             win.update()?;
@@ -669,6 +1260,7 @@ impl GameUI {
         dx: u8,
         dy: u8,
     ) -> Result<(), ChaosError> {
+        self.targeted = true;
         let points = Arena::line_coords(sx, sy, dx, dy);
         for start in (0..points.len() + 30).step_by(4) {
             win.update()?;
@@ -711,7 +1303,23 @@ impl GameUI {
         Ok(())
     }
 
+    fn death_flash(&mut self, win: &mut Window, state: &mut ClientState) -> Result<(), ChaosError> {
+        let width = state.arena.width as usize * 2;
+        let height = state.arena.height as usize * 2;
+        for flash in death_flash_frames(frame_timing(DEATH_FLASH_FRAMES)) {
+            self.render(win, state)?;
+            if flash {
+                win.buf.fill_area(33, 1, width, height, BrightWhite);
+            }
+            win.update()?;
+        }
+        Ok(())
+    }
+
     pub fn wizard_death(&mut self, win: &mut Window, state: &mut ClientState, id: u32) -> Result<(), ChaosError> {
+        if self.options.death_flash {
+            self.death_flash(win, state)?;
+        }
         let bytes = state.arena.find_wizard(id).current_bytes();
         let (x, y) = state.arena.find_wizard_pos(id);
         let x = x as isize * 2;
@@ -765,7 +1373,10 @@ impl GameUI {
         }
         let coords = state.arena.get_topmost_creations_and_corpses_coords(id);
         self.explosions(win, state, coords)?;
-        state.arena.kill_wizard_and_creations(id);
+        state.remove_wizard(id);
+        if id == state.wizard.id {
+            self.set_status(win, "YOU ARE DEFEATED, SPECTATING", BrightRed);
+        }
         Ok(())
     }
 
@@ -787,3 +1398,127 @@ impl GameUI {
         win.buf.clear_area(32, 22, 32, 2);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MAX_NAME_LEN;
+    use crate::data::spells::{create_spells, find_spells};
+    use rand::thread_rng;
+
+    fn player(id: isize) -> Player {
+        Player {
+            name: format!("WIZARD {id}"),
+            character: id.try_into().expect("character"),
+            color: id.try_into().expect("color"),
+        }
+    }
+
+    #[test]
+    fn only_a_single_survivor_wins() {
+        assert!(contest_drawn(&[]));
+        assert!(!contest_drawn(&[player(0)]));
+        assert!(contest_drawn(&[player(0), player(1)]));
+    }
+
+    #[test]
+    fn the_death_flash_always_ends_on_the_unflashed_board() {
+        for frames in 0..=u8::MAX {
+            let flashes = death_flash_frames(frames).collect::<Vec<_>>();
+            assert!(flashes.contains(&true), "{frames} frames");
+            assert_eq!(flashes.last(), Some(&false), "{frames} frames");
+            assert!(flashes.windows(2).all(|pair| !(pair[0] && pair[1])), "{frames} frames");
+        }
+    }
+
+    #[test]
+    fn alignment_text_always_fits_the_panel() {
+        for alignment in i8::MIN..=i8::MAX {
+            let text = alignment_text(alignment);
+            assert_eq!(text.is_none(), alignment == 0);
+            assert!(text.is_none_or(|text| text.len() <= 32), "alignment {alignment}");
+        }
+        assert_eq!(alignment_text(-4).as_deref(), Some("(CHAOS **)"));
+        assert_eq!(alignment_text(40).as_deref(), Some("(LAW ^X20)"));
+    }
+
+    #[test]
+    fn every_visible_spell_is_hit_at_both_ends_of_its_slot() {
+        let spells = create_spells(35, &[], &mut thread_rng());
+        for layout in [SpellLayout::Names(2), SpellLayout::Names(1), SpellLayout::Icons] {
+            let columns = layout.columns();
+            for show_percentages in [false, true] {
+                for scroll in 0..3 {
+                    let first = scroll * columns;
+                    for i in first..spells.len().min(first + SPELL_ROWS * columns) {
+                        let (x, y) = layout.slot_coords(i - first);
+                        let len = layout.slot_len(&spells[i], show_percentages);
+                        for x in [x, x + len] {
+                            let hit = preview_spell_coords(x, y, &spells, show_percentages, scroll, layout);
+                            assert_eq!(hit, Some(i), "spell {i} at {x},{y} with {columns} columns, scroll {scroll}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn only_spells_below_the_threshold_ask_for_confirmation() {
+        let mut bolt = find_spells(&["MAGIC BOLT".to_string()], &[]).remove(0);
+        bolt.chance = 1;
+        assert_eq!(risky_percentage(&bolt, 0, 0, 0), None);
+        assert_eq!(risky_percentage(&bolt, 0, 0, 30), Some(20));
+        assert_eq!(risky_percentage(&bolt, 0, 1, 30), None);
+        assert_eq!(risky_percentage(&bolt, 0, 8, 100), None);
+        bolt.chance = 9;
+        assert_eq!(risky_percentage(&bolt, 0, 0, 100), None);
+        let mut law = find_spells(&["LAW-1".to_string()], &[]).remove(0);
+        law.chance = 1;
+        assert_eq!(risky_percentage(&law, -8, 0, 30), Some(20));
+        assert_eq!(risky_percentage(&law, 8, 0, 30), None);
+    }
+
+    #[test]
+    fn timers_fit_beside_the_longest_spell_header() {
+        let header = format!("{}'S SPELLS", "W".repeat(MAX_NAME_LEN));
+        assert!(2 + header.len() <= TIMER_X);
+        assert_eq!(elapsed_text(0), "00:00");
+        assert_eq!(elapsed_text(3599), "59:59");
+        assert_eq!(elapsed_text(6000), "1:40:00");
+        assert_eq!(elapsed_text(u64::MAX), "9:59:59");
+        assert!(elapsed_text(u64::MAX).len() <= 32 - TIMER_X);
+    }
+
+    #[test]
+    fn single_column_rows_map_to_their_spell_across_the_full_width() {
+        let spells = create_spells(35, &[], &mut thread_rng());
+        let layout = SpellLayout::Names(1);
+        for scroll in [0, 2] {
+            for row in 0..SPELL_ROWS {
+                for y in [2 + row * 2, 3 + row * 2] {
+                    for x in [1, 16, 17, 30] {
+                        let hit = preview_spell_coords(x, y, &spells, true, scroll, layout);
+                        assert_eq!(hit, Some(scroll + row), "{x},{y} scroll {scroll}");
+                    }
+                    assert_eq!(preview_spell_coords(31, y, &spells, true, scroll, layout), None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn icons_are_hit_on_their_own_cell_and_not_the_gap() {
+        let spells = create_spells(35, &[], &mut thread_rng());
+        let layout = SpellLayout::Icons;
+        for slot in 0..30 {
+            let x = 1 + (slot % 10) * 3;
+            for y in [2 + (slot / 10) * 2, 3 + (slot / 10) * 2] {
+                assert_eq!(preview_spell_coords(x, y, &spells, false, 0, layout), Some(slot));
+                assert_eq!(preview_spell_coords(x + 1, y, &spells, false, 0, layout), Some(slot));
+                assert_eq!(preview_spell_coords(x + 2, y, &spells, false, 0, layout), None);
+            }
+        }
+        assert_eq!(preview_spell_coords(16, 8, &spells, false, 0, layout), None);
+    }
+}
@@ -1,6 +1,6 @@
 use super::game::game;
-use crate::config::Player;
-use crate::data::wizard::{LobbyWizard, LobbyWizards};
+use crate::config::{Options, Player};
+use crate::data::wizard::{position_table, LobbyWizard, LobbyWizards};
 use crate::error::ChaosError;
 use crate::gfx::color::Color::*;
 use crate::net::{ChaosClient, Message};
@@ -9,49 +9,134 @@ use crate::window::{Key, Window};
 fn lobby_list(win: &mut Window, wizards: impl Iterator<Item = LobbyWizard>) -> Result<(), ChaosError> {
     win.buf.clear_area(42, 4, 14, 16);
     for (i, wizard) in wizards.enumerate() {
-        let color = if wizard.ready { BrightYellow } else { Red };
+        let color = if !wizard.responsive {
+            White
+        } else if wizard.ready {
+            BrightYellow
+        } else {
+            Red
+        };
         win.buf.center_player(&wizard.player, 4 + 2 * i, color, wizard.ready);
     }
     Ok(())
 }
 
-pub async fn lobby(win: &mut Window, player: Player, client: &mut ChaosClient) -> Result<(), ChaosError> {
-    let mut wizards = LobbyWizards::new();
-    win.buf.clear();
-    win.buf.screen_border("ARE YOU READY? (Y OR N)", BrightRed, BrightYellow);
-    win.buf
-        .center_text("THE GAME WILL START WHEN ALL WIZARDS ARE READY", 2, BrightMagenta);
-    client.send(Message::Join(player.clone()))?;
-    loop {
-        win.update()?;
-        match win.get_yes_or_no_or_cancel() {
-            Some(Key::Y) => client.send(Message::Ready(true))?,
-            Some(Key::N) => client.send(Message::Ready(false))?,
-            Some(Key::Escape) => return Ok(()),
-            _ => {}
+fn banned_list(win: &mut Window, banned_spells: &[String]) {
+    win.buf.clear_area(2, 4, 24, 18);
+    if banned_spells.is_empty() {
+        return;
+    }
+    win.buf.draw_text("BANNED SPELLS", 2, 4, BrightMagenta);
+    for (i, name) in banned_spells.iter().take(7).enumerate() {
+        win.buf.draw_text(&name.to_uppercase(), 2, 6 + 2 * i, BrightWhite);
+    }
+    if banned_spells.len() > 7 {
+        let text = format!("+{} MORE", banned_spells.len() - 7);
+        win.buf.draw_text(&text, 2, 20, BrightWhite);
+    }
+}
+
+fn position_map(win: &mut Window, wizards: &LobbyWizards) -> Vec<(usize, usize, usize)> {
+    win.buf.clear_area(62, 4, 32, 16);
+    let table = match position_table(wizards.players.len()) {
+        Some(table) => table,
+        None => return Vec::new(),
+    };
+    win.buf.draw_text("START POSITIONS", 62, 4, BrightMagenta);
+    for y in 0..5 {
+        win.buf.draw_text(&".".repeat(15), 64, 6 + y * 2, Blue);
+    }
+    let mut items = Vec::new();
+    for (index, &(x, y)) in table.iter().enumerate() {
+        let (x, y) = (64 + x as usize, 6 + (y as usize / 2) * 2);
+        let color = match wizards.players.values().find(|wizard| wizard.position == Some(index)) {
+            Some(wizard) => wizard.player.color.into(),
+            None => BrightWhite,
+        };
+        win.buf.draw_text(&(index + 1).to_string(), x, y, color);
+        items.push((x, y, 1));
+    }
+    let text = format!("PRESS 1-{} TO CLAIM", table.len());
+    win.buf.draw_text(&text, 62, 17, BrightCyan);
+    items
+}
+
+const READY_TEXT: &str = "ARE YOU READY? (Y OR N)";
+
+pub async fn lobby(win: &mut Window, player: Player, client: &mut ChaosClient, options: &Options) -> Result<(), ChaosError> {
+    let mut auto_ready = options.auto_ready;
+    'lobby_loop: loop {
+        let mut wizards = LobbyWizards::new();
+        win.buf.clear();
+        win.buf.screen_border(READY_TEXT, BrightRed, BrightYellow);
+        let text_x = (win.buf.width / 8 - READY_TEXT.len()) / 2;
+        let text_y = win.buf.height / 8 - 2;
+        let answers = [(text_x + 16, text_y, 1), (text_x + 21, text_y, 1)];
+        win.buf
+            .center_text("THE GAME WILL START WHEN ALL WIZARDS ARE READY", 2, BrightMagenta);
+        let mut positions = Vec::new();
+        client.send(Message::Join(player.clone()))?;
+        if auto_ready {
+            client.send(Message::Ready(true))?;
+            win.buf.center_text("AUTO READY IS ON", 20, BrightGreen);
+            auto_ready = false;
         }
-        if let Some(msg) = client.recv()? {
-            match msg {
-                (id, Message::Join(player)) => {
-                    if wizards.join(id, player) {
-                        lobby_list(win, wizards.players())?;
+        loop {
+            win.update()?;
+            match win.get_yes_or_no_or_cancel() {
+                Some(Key::Y) => client.send(Message::Ready(true))?,
+                Some(Key::N) => client.send(Message::Ready(false))?,
+                Some(Key::Escape) => return Ok(()),
+                _ => match win.clicked_item(&answers) {
+                    Some(0) => client.send(Message::Ready(true))?,
+                    Some(_) => client.send(Message::Ready(false))?,
+                    None => {}
+                },
+            }
+            let claim = match win.digit_pressed(1..=positions.len() as isize) {
+                Some(digit) => Some(digit as usize - 1),
+                None => win.clicked_item(&positions),
+            };
+            if let Some(index) = claim {
+                client.send(Message::ClaimPosition(index))?;
+            }
+            if let Some(msg) = client.recv()? {
+                match msg {
+                    (id, Message::Join(player)) => {
+                        if wizards.join(id, player) {
+                            lobby_list(win, wizards.players())?;
+                            positions = position_map(win, &wizards);
+                        }
                     }
-                }
-                (id, Message::Leave(_)) => {
-                    if wizards.leave(id).is_some() {
-                        lobby_list(win, wizards.players())?;
+                    (id, Message::Leave(_)) => {
+                        if wizards.leave(id).is_some() {
+                            lobby_list(win, wizards.players())?;
+                            positions = position_map(win, &wizards);
+                        }
                     }
-                }
-                (id, Message::Ready(ready)) => {
-                    if wizards.ready(id, ready) {
+                    (id, Message::Ready(ready)) => {
+                        if wizards.ready(id, ready) {
+                            lobby_list(win, wizards.players())?;
+                        }
+                    }
+                    (id, Message::ClaimPosition(index)) if wizards.claim_position(id, index) => {
+                        positions = position_map(win, &wizards);
+                    }
+                    (id, Message::Responsive(responsive)) if wizards.responsive(id, responsive) => {
                         lobby_list(win, wizards.players())?;
                     }
+                    (_, Message::BannedSpells(banned_spells)) => {
+                        banned_list(win, &banned_spells);
+                    }
+                    (_, Message::Shutdown) => return Ok(()),
+                    (_, Message::Start(wizard)) => {
+                        if game(win, client, wizard, options)? {
+                            continue 'lobby_loop;
+                        }
+                        return Ok(());
+                    }
+                    _ => {}
                 }
-                (_, Message::Start(wizard)) => {
-                    game(win, client, wizard)?;
-                    return Ok(());
-                }
-                _ => {}
             }
         }
     }
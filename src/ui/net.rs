@@ -1,7 +1,8 @@
-use crate::config::NetAddress;
+use crate::config::{HostOptions, NetAddress};
 use crate::error::ChaosError;
 use crate::gfx::color::Color::*;
 use crate::window::Window;
+use minifb::Key;
 
 fn network(win: &mut Window, title: &str, address: NetAddress) -> Result<Option<NetAddress>, ChaosError> {
     win.buf.clear();
@@ -17,16 +18,154 @@ fn network(win: &mut Window, title: &str, address: NetAddress) -> Result<Option<
         Some(port) => port,
         None => return Ok(None),
     };
-    win.wait(900)?;
     Ok(Some(NetAddress { host, port }))
 }
 
-pub fn host_game(win: &mut Window, address: &Option<NetAddress>) -> Result<Option<NetAddress>, ChaosError> {
-    let addr = address.clone().unwrap_or_default();
-    network(win, "HOST GAME", addr)
+pub fn practice_game(win: &mut Window) -> Result<Option<usize>, ChaosError> {
+    win.buf.clear();
+    win.buf.screen_border("PRACTICE VS AI", BrightBlue, BrightCyan);
+    win.buf.draw_text("Computer Opponents (2-7)", 38, 6, BrightMagenta);
+    Ok(win.wait_for_number(2..=7)?.map(|bots| bots as usize))
 }
 
-pub fn join_game(win: &mut Window, address: &Option<NetAddress>) -> Result<Option<NetAddress>, ChaosError> {
+pub fn host_game(
+    win: &mut Window,
+    address: &Option<NetAddress>,
+    options: &mut HostOptions,
+) -> Result<Option<NetAddress>, ChaosError> {
     let addr = address.clone().unwrap_or_default();
-    network(win, "JOIN GAME", addr)
+    let addr = match network(win, "HOST GAME", addr)? {
+        Some(addr) => addr,
+        None => return Ok(None),
+    };
+    win.buf.draw_text("Starting Level (0-8)", 38, 16, BrightMagenta);
+    win.buf
+        .draw_text(&options.starting_level.min(8).to_string(), 38, 18, BrightCyan);
+    let starting_level = match win.wait_for_number(0..=8)? {
+        Some(level) => level as u8,
+        None => return Ok(None),
+    };
+    win.buf.clear_area(38, 18, 1, 2);
+    win.buf.draw_text(&starting_level.to_string(), 38, 18, BrightCyan);
+    win.buf.draw_text("All Interfaces (Y or N)", 64, 16, BrightMagenta);
+    let answer = if options.all_interfaces { "Y" } else { "N" };
+    win.buf.draw_text(answer, 64, 18, BrightCyan);
+    let all_interfaces = loop {
+        win.update()?;
+        match win.get_yes_or_no_or_cancel() {
+            Some(Key::Y) => break true,
+            Some(Key::N) => break false,
+            Some(_) => return Ok(None),
+            None => {}
+        }
+    };
+    win.buf.clear_area(64, 18, 1, 2);
+    if all_interfaces {
+        win.buf.draw_text("Y", 64, 18, BrightCyan);
+        let lan = match NetAddress::lan_ip() {
+            Some(ip) => format!(
+                "LAN {}",
+                NetAddress {
+                    host: ip.to_string(),
+                    port: addr.port
+                }
+            ),
+            None => "LAN address unknown".to_string(),
+        };
+        win.buf.draw_text(&lan, 64, 20, BrightWhite);
+        win.wait_for_any_key()?;
+    } else {
+        win.buf.draw_text("N", 64, 18, BrightCyan);
+        win.wait(900)?;
+    }
+    options.starting_level = starting_level;
+    options.all_interfaces = all_interfaces;
+    Ok(Some(addr))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum JoinScreen {
+    RecentServers,
+    Address,
+}
+
+impl JoinScreen {
+    fn first(recent_hosts: &[NetAddress]) -> Self {
+        if recent_hosts.is_empty() {
+            JoinScreen::Address
+        } else {
+            JoinScreen::RecentServers
+        }
+    }
+
+    fn back(self, recent_hosts: &[NetAddress]) -> Option<Self> {
+        match self {
+            JoinScreen::Address if !recent_hosts.is_empty() => Some(JoinScreen::RecentServers),
+            _ => None,
+        }
+    }
+}
+
+pub fn join_game(win: &mut Window, recent_hosts: &[NetAddress]) -> Result<Option<NetAddress>, ChaosError> {
+    let mut screen = JoinScreen::first(recent_hosts);
+    loop {
+        let chosen = match screen {
+            JoinScreen::RecentServers => {
+                win.buf.clear();
+                win.buf.screen_border("JOIN GAME", BrightBlue, BrightCyan);
+                win.buf.draw_text("RECENT SERVERS", 38, 4, BrightYellow);
+                let other_y = 8 + recent_hosts.len() * 2;
+                let mut items = vec![(38, other_y, 14)];
+                for (i, addr) in recent_hosts.iter().enumerate() {
+                    let text = format!("{}.{}", i + 1, addr);
+                    win.buf.draw_text(&text, 38, 6 + i * 2, BrightCyan);
+                    items.push((38, 6 + i * 2, text.len()));
+                }
+                win.buf.draw_text("0.OTHER SERVER", 38, other_y, BrightCyan);
+                match win.wait_for_menu(0..=recent_hosts.len() as isize, &items)? {
+                    Some(0) => {
+                        screen = JoinScreen::Address;
+                        continue;
+                    }
+                    Some(index) => return Ok(Some(recent_hosts[index as usize - 1].clone())),
+                    None => None,
+                }
+            }
+            JoinScreen::Address => {
+                let addr = recent_hosts.first().cloned().unwrap_or_default();
+                network(win, "JOIN GAME", addr)?
+            }
+        };
+        match chosen {
+            Some(addr) => {
+                win.wait(900)?;
+                return Ok(Some(addr));
+            }
+            None => match screen.back(recent_hosts) {
+                Some(previous) => screen = previous,
+                None => return Ok(None),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_steps_back_through_the_join_screens_to_the_menu() {
+        let recent = vec![NetAddress::default()];
+        let screen = JoinScreen::first(&recent);
+        assert_eq!(screen, JoinScreen::RecentServers);
+        assert_eq!(screen.back(&recent), None);
+        assert_eq!(JoinScreen::Address.back(&recent), Some(JoinScreen::RecentServers));
+    }
+
+    #[test]
+    fn without_recent_servers_escape_leaves_the_address_screen_for_the_menu() {
+        let screen = JoinScreen::first(&[]);
+        assert_eq!(screen, JoinScreen::Address);
+        assert_eq!(screen.back(&[]), None);
+    }
 }
@@ -0,0 +1,63 @@
+use crate::config::Options;
+use crate::error::ChaosError;
+use crate::gfx::color::Color::{self, *};
+use crate::window::Window;
+
+fn on_or_off(value: bool) -> (String, Color) {
+    if value {
+        ("ON".to_string(), BrightGreen)
+    } else {
+        ("OFF".to_string(), BrightRed)
+    }
+}
+
+pub fn options(win: &mut Window, options: &mut Options) -> Result<(), ChaosError> {
+    loop {
+        win.buf.clear();
+        win.buf
+            .screen_border("PRESS A NUMBER TO CHANGE, ESC TO RETURN", BrightRed, BrightYellow);
+        let entries = [
+            ("HIGH VISIBILITY CURSOR", on_or_off(options.high_visibility_cursor)),
+            ("VOLUME", (format!("{}%", options.volume.min(100)), BrightYellow)),
+            ("MUTE", on_or_off(options.muted)),
+            ("CREATION OWNER MARKERS", on_or_off(options.owner_markers)),
+            ("SPELL PERCENTAGES", on_or_off(options.spell_percentages)),
+            ("SPRITE FACING", on_or_off(options.sprite_facing)),
+            ("ALIGNMENT TREND", on_or_off(options.alignment_trend)),
+            ("ANIMATION SPEED", (format!("{}%", options.animation_speed), BrightYellow)),
+            ("CHANCE AS D10 ROLL", on_or_off(options.chance_thresholds)),
+        ];
+        let mut items = Vec::new();
+        for (i, (name, (value, color))) in entries.iter().enumerate() {
+            let y = 3 + i * 2;
+            win.buf.draw_text(&format!("{}.{}", i + 1, name), 24, y, BrightCyan);
+            win.buf.draw_text(value, 64, y, *color);
+            items.push((24, y, 64 - 24 + value.len()));
+        }
+        match win.wait_for_menu(1..=entries.len() as isize, &items)? {
+            Some(1) => options.high_visibility_cursor = !options.high_visibility_cursor,
+            Some(2) => {
+                options.volume = if options.volume >= 100 {
+                    0
+                } else {
+                    options.volume / 10 * 10 + 10
+                }
+            }
+            Some(3) => options.muted = !options.muted,
+            Some(4) => options.owner_markers = !options.owner_markers,
+            Some(5) => options.spell_percentages = !options.spell_percentages,
+            Some(6) => options.sprite_facing = !options.sprite_facing,
+            Some(7) => options.alignment_trend = !options.alignment_trend,
+            Some(8) => {
+                options.animation_speed = if options.animation_speed >= 200 {
+                    50
+                } else {
+                    options.animation_speed / 50 * 50 + 50
+                }
+            }
+            Some(9) => options.chance_thresholds = !options.chance_thresholds,
+            None => return Ok(()),
+            _ => unreachable!("Invalid option"),
+        }
+    }
+}
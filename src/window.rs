@@ -1,3 +1,4 @@
+use crate::config::NetAddress;
 use crate::error::ChaosError;
 use crate::gfx::buffer::Buffer;
 use crate::gfx::color::Color;
@@ -6,24 +7,157 @@ use minifb::{KeyRepeat, MouseButton, MouseMode, Scale, Window as MiniFBWindow, W
 use std::ops::RangeInclusive;
 use std::time::{Duration, Instant};
 
+static LETTERS: [Key; 26] = {
+    use Key::*;
+    [A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z]
+};
+
+static FUNCTION_KEYS: [Key; 12] = {
+    use Key::*;
+    [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12]
+};
+
+fn key_group(names: &str) -> Option<Vec<Key>> {
+    use Key::*;
+    let mut keys = Vec::new();
+    for name in names.split('|') {
+        let name = name.trim().to_uppercase();
+        match name.as_str() {
+            "ALT" => keys.extend([LeftAlt, RightAlt]),
+            "CTRL" => keys.extend([LeftCtrl, RightCtrl]),
+            "SHIFT" => keys.extend([LeftShift, RightShift]),
+            "SUPER" | "CMD" => keys.extend([LeftSuper, RightSuper]),
+            "ESC" | "ESCAPE" => keys.push(Escape),
+            _ => {
+                let key = match name.strip_prefix('F').and_then(|num| num.parse::<usize>().ok()) {
+                    Some(num @ 1..=12) => FUNCTION_KEYS[num - 1],
+                    _ if name.len() == 1 && name.as_bytes()[0].is_ascii_uppercase() => {
+                        LETTERS[(name.as_bytes()[0] - b'A') as usize]
+                    }
+                    _ => return None,
+                };
+                keys.push(key);
+            }
+        }
+    }
+    Some(keys)
+}
+
+fn quit_combo(names: &[String]) -> Option<Vec<Vec<Key>>> {
+    if names.is_empty() {
+        return None;
+    }
+    names.iter().map(|names| key_group(names)).collect()
+}
+
+fn combo_held(combo: &[Vec<Key>], is_down: impl Fn(Key) -> bool) -> bool {
+    combo.iter().all(|group| group.iter().any(|key| is_down(*key)))
+}
+
+fn item_at(items: &[(usize, usize, usize)], mouse_x: usize, mouse_y: usize) -> Option<usize> {
+    items
+        .iter()
+        .position(|&(x, y, width)| (y..y + 2).contains(&mouse_y) && (x..x + width).contains(&mouse_x))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn default_quit_combo() -> Vec<Vec<Key>> {
+    use Key::*;
+    vec![vec![LeftAlt, RightAlt], vec![F4]]
+}
+
+#[cfg(target_os = "macos")]
+fn default_quit_combo() -> Vec<Vec<Key>> {
+    use Key::*;
+    vec![vec![LeftSuper, RightSuper], vec![Q, W]]
+}
+
+#[cfg(not(target_os = "macos"))]
+fn is_active(win: &mut MiniFBWindow) -> bool {
+    win.is_active()
+}
+
+// minifb reports the inverse of the window's focus on macOS
+#[cfg(target_os = "macos")]
+fn is_active(win: &mut MiniFBWindow) -> bool {
+    !win.is_active()
+}
+
+struct Focus {
+    focused: bool,
+    seen: bool,
+    resume_click: bool,
+}
+
+impl Focus {
+    fn new() -> Self {
+        Self {
+            focused: true,
+            seen: false,
+            resume_click: false,
+        }
+    }
+
+    fn update(&mut self, active: bool, mouse_down: bool) {
+        self.seen |= active;
+        let focused = active || !self.seen;
+        if focused && !self.focused {
+            self.resume_click = true;
+        }
+        self.focused = focused;
+        if !mouse_down {
+            self.resume_click = false;
+        }
+    }
+
+    fn click(&self, mouse_down: bool) -> bool {
+        self.focused && !self.resume_click && mouse_down
+    }
+}
+
 pub struct Window {
     pub win: MiniFBWindow,
     pub buf: Buffer,
+    quit_combo: Vec<Vec<Key>>,
+    title: String,
+    mouse_down: bool,
+    mouse_pressed: bool,
+    focus: Focus,
 }
 
 impl Window {
     pub fn new() -> Result<Self, ChaosError> {
-        let name = env!("CARGO_PKG_DESCRIPTION");
+        let title = env!("CARGO_PKG_DESCRIPTION").to_string();
         let width = 768;
         let height = 192;
         let opts = WindowOptions {
             scale: Scale::X2,
             ..WindowOptions::default()
         };
-        let mut win = MiniFBWindow::new(name, width, height, opts)?;
+        let mut win = MiniFBWindow::new(&title, width, height, opts)?;
         win.limit_update_rate(Some(Duration::from_millis(1000 / 50)));
         let buf = Buffer::new(width / 8, height / 8);
-        Ok(Self { win, buf })
+        Ok(Self {
+            win,
+            buf,
+            quit_combo: default_quit_combo(),
+            title,
+            mouse_down: false,
+            mouse_pressed: false,
+            focus: Focus::new(),
+        })
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+        self.win.set_title(title);
+    }
+
+    pub fn set_title_context(&mut self, context: Option<&str>) {
+        match context {
+            Some(context) => self.win.set_title(&format!("{} - {}", self.title, context)),
+            None => self.win.set_title(&self.title),
+        }
     }
 
     pub fn update(&mut self) -> Result<(), ChaosError> {
@@ -31,6 +165,11 @@ impl Window {
             Err(ChaosError::Quit)
         } else {
             self.win.update_with_buffer(&self.buf.data, self.buf.width, self.buf.height)?;
+            let active = is_active(&mut self.win);
+            self.focus.update(active, self.win.get_mouse_down(MouseButton::Left));
+            let mouse_down = self.mouse_clicked();
+            self.mouse_pressed = mouse_down && !self.mouse_down;
+            self.mouse_down = mouse_down;
             Ok(())
         }
     }
@@ -50,40 +189,70 @@ impl Window {
         }
     }
 
+    pub fn is_focused(&self) -> bool {
+        self.focus.focused
+    }
+
+    fn keys_pressed(&self, repeat: KeyRepeat) -> Vec<Key> {
+        if self.focus.focused {
+            self.win.get_keys_pressed(repeat)
+        } else {
+            Vec::new()
+        }
+    }
+
     pub fn mouse_clicked(&self) -> bool {
-        self.win.get_mouse_down(MouseButton::Left)
+        self.focus.click(self.win.get_mouse_down(MouseButton::Left))
+    }
+
+    pub fn clicked_item(&self, items: &[(usize, usize, usize)]) -> Option<usize> {
+        if !self.mouse_pressed {
+            return None;
+        }
+        let (mouse_x, mouse_y) = self.mouse_coords()?;
+        item_at(items, mouse_x, mouse_y)
+    }
+
+    pub fn close(self) {
+        drop(self.win);
     }
 
     pub fn quit(&self) -> Result<(), ChaosError> {
         Err(ChaosError::Quit)
     }
 
-    #[cfg(not(target_os = "macos"))]
-    pub fn check_for_quit(&self) -> bool {
-        use Key::*;
-        self.win.is_key_down(LeftAlt) || self.win.is_key_down(RightAlt) && self.win.is_key_down(F4)
+    pub fn set_quit_combo(&mut self, names: &[String]) {
+        self.quit_combo = quit_combo(names).unwrap_or_else(default_quit_combo);
     }
 
-    #[cfg(target_os = "macos")]
     pub fn check_for_quit(&self) -> bool {
-        use Key::*;
-        (self.win.is_key_down(LeftSuper) || self.win.is_key_down(RightSuper))
-            && (self.win.is_key_down(Q) || self.win.is_key_down(W))
+        combo_held(&self.quit_combo, |key| self.win.is_key_down(key))
     }
 
     pub fn wait_for_any_key(&mut self) -> Result<(), ChaosError> {
         loop {
             self.update()?;
-            if !self.win.get_keys_pressed(KeyRepeat::No).is_empty() {
+            if !self.keys_pressed(KeyRepeat::No).is_empty() {
                 return Ok(());
             }
         }
     }
 
     pub fn wait_for_number(&mut self, range: RangeInclusive<isize>) -> Result<Option<isize>, ChaosError> {
+        self.wait_for_menu(range, &[])
+    }
+
+    pub fn wait_for_menu(
+        &mut self,
+        range: RangeInclusive<isize>,
+        items: &[(usize, usize, usize)],
+    ) -> Result<Option<isize>, ChaosError> {
         loop {
             self.update()?;
-            for key in self.win.get_keys_pressed(KeyRepeat::No) {
+            if let Some(index) = self.clicked_item(items) {
+                return Ok(Some(range.start() + index as isize));
+            }
+            for key in self.keys_pressed(KeyRepeat::No) {
                 use Key::*;
                 let digit = (key as isize) - (Key0 as isize);
                 if Escape == key {
@@ -107,7 +276,7 @@ impl Window {
         loop {
             self.update()?;
             self.buf.clear_area(x, y, max_len + 1, 2);
-            for key in self.win.get_keys_pressed(KeyRepeat::Yes) {
+            for key in self.keys_pressed(KeyRepeat::Yes) {
                 let discriminant = key as isize;
                 match discriminant {
                     0..=9 if name.len() < max_len => {
@@ -153,10 +322,13 @@ impl Window {
         max_len: usize,
         fg: Color,
     ) -> Result<Option<String>, ChaosError> {
+        let mut valid = true;
         loop {
             self.update()?;
             self.buf.clear_area(x, y, max_len + 1, 2);
-            for key in self.win.get_keys_pressed(KeyRepeat::Yes) {
+            for key in self.keys_pressed(KeyRepeat::Yes) {
+                valid = true;
+                let shift = self.win.is_key_down(Key::LeftShift) || self.win.is_key_down(Key::RightShift);
                 let discriminant = key as isize;
                 match discriminant {
                     0..=9 if host.len() < max_len => {
@@ -164,11 +336,7 @@ impl Window {
                         host.push(ch as char);
                     }
                     10..=35 if host.len() < max_len => {
-                        let ch = if self.win.is_key_down(Key::LeftShift) || self.win.is_key_down(Key::RightShift) {
-                            discriminant + 55
-                        } else {
-                            discriminant + 87
-                        } as u8;
+                        let ch = if shift { discriminant + 55 } else { discriminant + 87 } as u8;
                         host.push(ch as char);
                     }
                     _ => match key {
@@ -178,18 +346,28 @@ impl Window {
                         Key::Period if host.len() < max_len => {
                             host.push('.');
                         }
+                        Key::Minus if host.len() < max_len => {
+                            host.push('-');
+                        }
+                        Key::Semicolon if shift && host.len() < max_len => {
+                            host.push(':');
+                        }
                         Key::Backspace if !host.is_empty() => {
                             host.pop();
                         }
                         Key::Enter if !host.is_empty() => {
-                            self.buf.draw_text(&host, x, y, fg);
-                            return Ok(Some(host.clone()));
+                            if NetAddress::is_valid_host(&host) {
+                                self.buf.draw_text(&host, x, y, fg);
+                                return Ok(Some(host.clone()));
+                            }
+                            valid = false;
                         }
                         Key::Space if !host.is_empty() => host.push(' '),
                         _ => {}
                     },
                 }
             }
+            let fg = if valid { fg } else { Color::BrightRed };
             self.buf.draw_text(&host, x, y, fg);
             self.buf.draw_cursor(x + host.len(), y, fg);
         }
@@ -204,10 +382,12 @@ impl Window {
         fg: Color,
     ) -> Result<Option<usize>, ChaosError> {
         let mut string = port.to_string();
+        let mut valid = true;
         loop {
             self.update()?;
             self.buf.clear_area(x, y, max_len + 1, 2);
-            for key in self.win.get_keys_pressed(KeyRepeat::Yes) {
+            for key in self.keys_pressed(KeyRepeat::Yes) {
+                valid = true;
                 let discriminant = key as isize;
                 match discriminant {
                     0..=9 if string.len() < max_len => {
@@ -219,9 +399,11 @@ impl Window {
                             return Ok(None);
                         }
                         Key::Enter if !string.is_empty() => {
-                            self.buf.draw_text(&string, x, y, fg);
-                            let port = string.parse().expect("parsing port");
-                            return Ok(Some(port));
+                            if let Some(port) = NetAddress::parse_port(&string) {
+                                self.buf.draw_text(&string, x, y, fg);
+                                return Ok(Some(port));
+                            }
+                            valid = false;
                         }
                         Key::Backspace if !string.is_empty() => {
                             string.pop();
@@ -230,13 +412,14 @@ impl Window {
                     },
                 }
             }
+            let fg = if valid { fg } else { Color::BrightRed };
             self.buf.draw_text(&string, x, y, fg);
             self.buf.draw_cursor(x + string.len(), y, fg);
         }
     }
 
     pub fn get_yes_or_no_or_cancel(&mut self) -> Option<Key> {
-        for key in self.win.get_keys_pressed(KeyRepeat::No) {
+        for key in self.keys_pressed(KeyRepeat::No) {
             use Key::*;
             match key {
                 Y => return Some(Y),
@@ -248,19 +431,104 @@ impl Window {
         None
     }
 
+    pub fn digit_pressed(&mut self, range: RangeInclusive<isize>) -> Option<isize> {
+        self.keys_pressed(KeyRepeat::No)
+            .into_iter()
+            .map(|key| (key as isize) - (Key::Key0 as isize))
+            .find(|digit| range.contains(digit))
+    }
+
     pub fn escape_pressed(&mut self) -> bool {
-        self.win.is_key_pressed(Key::Escape, KeyRepeat::No)
+        self.focus.focused && self.win.is_key_pressed(Key::Escape, KeyRepeat::No)
+    }
+
+    pub fn key_pressed(&mut self, key: Key) -> bool {
+        self.focus.focused && self.win.is_key_pressed(key, KeyRepeat::No)
+    }
+
+    pub fn typed_chars(&mut self) -> Vec<char> {
+        self.keys_pressed(KeyRepeat::Yes)
+            .into_iter()
+            .filter_map(|key| match key as isize {
+                discriminant @ 10..=35 => Some((discriminant + 55) as u8 as char),
+                _ if key == Key::Space => Some(' '),
+                _ => None,
+            })
+            .collect()
     }
 
     pub fn is_down_pressed(&mut self) -> bool {
-        self.win.is_key_pressed(Key::Down, KeyRepeat::Yes)
+        self.focus.focused && self.win.is_key_pressed(Key::Down, KeyRepeat::Yes)
     }
 
     pub fn is_up_pressed(&mut self) -> bool {
-        self.win.is_key_pressed(Key::Up, KeyRepeat::Yes)
+        self.focus.focused && self.win.is_key_pressed(Key::Up, KeyRepeat::Yes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combo(names: &[&str]) -> Option<Vec<Vec<Key>>> {
+        quit_combo(&names.iter().map(|name| name.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn quit_combos_are_parsed_from_key_names() {
+        use Key::*;
+        assert_eq!(
+            combo(&["ctrl", "Shift", "q|F12"]),
+            Some(vec![vec![LeftCtrl, RightCtrl], vec![LeftShift, RightShift], vec![Q, F12]])
+        );
+        assert_eq!(combo(&["CMD", "esc"]), Some(vec![vec![LeftSuper, RightSuper], vec![Escape]]));
+        assert_eq!(combo(&[]), None);
+        assert_eq!(combo(&["ALT", "F13"]), None);
+        assert_eq!(combo(&["ALT", "QQ"]), None);
+    }
+
+    #[test]
+    fn a_combo_needs_one_key_from_every_group_held() {
+        use Key::*;
+        let combo = combo(&["ALT", "Q|W"]).unwrap();
+        let held = |keys: &'static [Key]| move |key: Key| keys.contains(&key);
+        assert!(combo_held(&combo, held(&[RightAlt, W])));
+        assert!(combo_held(&combo, held(&[LeftAlt, Q, X])));
+        assert!(!combo_held(&combo, held(&[LeftAlt])));
+        assert!(!combo_held(&combo, held(&[Q, W])));
+        assert!(!combo_held(&combo, held(&[LeftCtrl, Q])));
+    }
+
+    #[test]
+    fn items_are_hit_across_both_rows_of_their_text() {
+        let items = [(64, 6, 1), (71, 8, 1), (10, 20, 5)];
+        assert_eq!(item_at(&items, 64, 6), Some(0));
+        assert_eq!(item_at(&items, 64, 7), Some(0));
+        assert_eq!(item_at(&items, 71, 8), Some(1));
+        assert_eq!(item_at(&items, 71, 9), Some(1));
+        assert_eq!(item_at(&items, 71, 10), None);
+        assert_eq!(item_at(&items, 72, 8), None);
+        assert_eq!(item_at(&items, 14, 21), Some(2));
+        assert_eq!(item_at(&items, 15, 21), None);
+        assert_eq!(item_at(&items, 10, 19), None);
     }
 
-    pub fn any_key_pressed(&mut self) -> bool {
-        !self.win.get_keys_pressed(KeyRepeat::No).is_empty()
+    #[test]
+    fn clicks_are_ignored_while_unfocused_and_on_the_refocusing_click() {
+        let mut focus = Focus::new();
+        focus.update(false, true);
+        assert!(focus.click(true));
+        focus.update(true, false);
+        assert!(focus.click(true));
+        focus.update(false, true);
+        assert!(!focus.click(true));
+        focus.update(true, true);
+        assert!(!focus.click(true));
+        focus.update(true, true);
+        assert!(!focus.click(true));
+        focus.update(true, false);
+        assert!(!focus.click(false));
+        focus.update(true, true);
+        assert!(focus.click(true));
     }
 }